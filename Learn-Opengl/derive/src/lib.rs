@@ -0,0 +1,269 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, Data, DeriveInput, Error, Fields, FieldsNamed, LitInt, LitStr, Result,
+};
+
+// Implements `SetUniform` for a struct with named fields, setting every field as the member
+// of the GLSL struct with the same name, `name.field`. Fields can be anything that
+// implements `SetUniform`, so nested structs and arrays of them work too.
+//
+//     #[derive(Debug, SetUniform)]
+//     struct Light {
+//         position: Vector3<f32>,
+//         #[uniform(rename = "depthMap")]
+//         depth_map: i32,
+//         #[uniform(skip)]
+//         fbo: FrameBuffer,
+//     }
+#[proc_macro_derive(SetUniform, attributes(uniform))]
+pub fn derive_set_uniform(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    set_uniform(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+// what #[uniform(...)] says about a field
+#[derive(Default)]
+struct FieldOptions {
+    rename: Option<String>,
+    skip: bool,
+}
+
+fn field_options(field: &syn::Field) -> Result<FieldOptions> {
+    let mut options = FieldOptions::default();
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("uniform")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                options.skip = true;
+                Ok(())
+            } else if meta.path.is_ident("rename") {
+                options.rename = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                Err(meta.error("expected `skip` or `rename = \"...\"`"))
+            }
+        })?;
+    }
+    Ok(options)
+}
+
+fn named_fields<'a>(input: &'a DeriveInput, derive: &str) -> Result<&'a FieldsNamed> {
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new_spanned(
+            input,
+            format!("{derive} can only be derived for structs"),
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(Error::new_spanned(
+            &data.fields,
+            format!("{derive} can only be derived for structs with named fields"),
+        ));
+    };
+    Ok(fields)
+}
+
+fn set_uniform(input: &DeriveInput) -> Result<TokenStream2> {
+    let fields = named_fields(input, "SetUniform")?;
+
+    let mut members = vec![];
+    for field in fields.named.iter() {
+        let options = field_options(field)?;
+        if options.skip {
+            continue;
+        }
+        let ident = field.ident.as_ref().unwrap();
+        let member = options.rename.unwrap_or_else(|| ident.to_string());
+        members.push(quote! {
+            (format!("{}.{}", name, #member), &self.#ident)
+        });
+    }
+
+    let ty = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let set_uniform = quote!(::learn_opengl::gls::shader::set_uniform::SetUniform);
+    Ok(quote! {
+        impl #impl_generics #set_uniform for #ty #ty_generics #where_clause {
+            unsafe fn set_uniform(&self, _id: i32) {
+                panic!("Can't set a struct uniform directly");
+            }
+
+            fn name_data_list<'a>(&'a self, name: &str) -> Vec<(String, &'a dyn #set_uniform)> {
+                vec![#(#members),*]
+            }
+
+            fn has_next(&self) -> bool {
+                true
+            }
+        }
+    })
+}
+
+// Implements `Std140` for a struct with named fields, writing the fields in the order
+// they are declared, which has to be the order of the members of the GLSL block or struct.
+// Fields that aren't in the block, like samplers, are left out with `skip`.
+//
+//     #[derive(Std140)]
+//     struct Camera {
+//         projection: Matrix4<f32>,
+//         view: Matrix4<f32>,
+//         #[std140(skip)]
+//         fov: f32,
+//     }
+#[proc_macro_derive(Std140, attributes(std140))]
+pub fn derive_std140(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    std140(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn std140_skip(field: &syn::Field) -> Result<bool> {
+    let mut skip = false;
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("std140")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                skip = true;
+                Ok(())
+            } else {
+                Err(meta.error("expected `skip`"))
+            }
+        })?;
+    }
+    Ok(skip)
+}
+
+fn std140(input: &DeriveInput) -> Result<TokenStream2> {
+    let fields = named_fields(input, "Std140")?;
+
+    let mut writes = vec![];
+    for field in fields.named.iter() {
+        if std140_skip(field)? {
+            continue;
+        }
+        let ident = field.ident.as_ref().unwrap();
+        writes.push(quote!(w.write(&self.#ident);));
+    }
+
+    let ty = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let ubo = quote!(::learn_opengl::gls::buffers::ubo);
+    Ok(quote! {
+        impl #impl_generics #ubo::Std140 for #ty #ty_generics #where_clause {
+            // structs start and end on 16 bytes
+            const ALIGN: usize = 16;
+
+            fn write_std140(&self, w: &mut #ubo::Std140Writer) {
+                #(#writes)*
+                w.align(16);
+            }
+        }
+    })
+}
+
+// Implements `Vertex` for a struct, making every field an attribute. Locations follow the
+// order of the fields from 0 unless set with `location`, a matrix field taking one per
+// column, and each field's type gives the size and component type of its attribute, so a
+// field that can't be an attribute doesn't compile.
+//
+//     #[derive(Clone, Copy, Vertex)]
+//     struct ColoredVertex {
+//         position: [f32; 3],
+//         #[vertex(location = 2, normalized)]
+//         color: [u8; 4],
+//     }
+#[proc_macro_derive(Vertex, attributes(vertex))]
+pub fn derive_vertex(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    vertex(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+// what #[vertex(...)] says about a field
+#[derive(Default)]
+struct AttributeOptions {
+    location: Option<u32>,
+    normalized: bool,
+    skip: bool,
+}
+
+fn attribute_options(field: &syn::Field) -> Result<AttributeOptions> {
+    let mut options = AttributeOptions::default();
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("vertex")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                options.skip = true;
+                Ok(())
+            } else if meta.path.is_ident("normalized") {
+                options.normalized = true;
+                Ok(())
+            } else if meta.path.is_ident("location") {
+                options.location = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `location = N`, `normalized` or `skip`"))
+            }
+        })?;
+    }
+    Ok(options)
+}
+
+fn vertex(input: &DeriveInput) -> Result<TokenStream2> {
+    let fields = named_fields(input, "Vertex")?;
+
+    let vertex_attribute = quote!(::learn_opengl::gls::buffers::vertex::VertexAttribute);
+    let mut attributes = vec![];
+    // explicit locations, fields after a matrix overlapping it are caught by
+    // checked_attributes when the buffer is made
+    let mut locations = vec![];
+    for field in fields.named.iter() {
+        let options = attribute_options(field)?;
+        if options.skip {
+            continue;
+        }
+        let location = match options.location {
+            Some(location) if locations.contains(&location) => {
+                return Err(Error::new_spanned(
+                    field,
+                    format!("attribute location {location} is used twice"),
+                ));
+            }
+            Some(location) => {
+                locations.push(location);
+                quote!(#location)
+            }
+            None => quote!(location),
+        };
+
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let normalized = options.normalized;
+        attributes.push(quote! {
+            let attribute = #vertex_attribute::of::<#ty>(
+                #location,
+                ::std::mem::offset_of!(Self, #ident),
+                #normalized,
+            );
+            location = attribute.locations().end;
+            attributes.push(attribute);
+        });
+    }
+
+    let ty = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::learn_opengl::gls::buffers::vertex::Vertex for #ty #ty_generics #where_clause {
+            #[allow(unused_assignments, unused_mut)]
+            fn attributes() -> Vec<#vertex_attribute> {
+                let mut attributes = vec![];
+                let mut location = 0u32;
+                #(#attributes)*
+                attributes
+            }
+        }
+    })
+}
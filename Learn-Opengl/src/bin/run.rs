@@ -1,7 +1,7 @@
 use glfw::{Action, Context, Key};
 use learn_opengl::camera::{Camera, CameraDirection, CameraDirectionTrait};
 use learn_opengl::gls::buffers::texture::{Tex2DTrait, Texture2D, Textures};
-use learn_opengl::gls::buffers::{bindable::Bindable, Attribute, VOs};
+use learn_opengl::gls::buffers::{bindable::Bindable, vertex::Vertex, VOs};
 use learn_opengl::gls::shader::{Shader, ShaderProgram};
 use learn_opengl::window::Window;
 use std::path::Path;
@@ -48,6 +48,22 @@ void main()
 }
 "#;
 
+// aPos and aTexCoord in the vertex shader
+#[derive(Clone, Copy, Vertex)]
+struct TexturedVertex {
+    position: [f32; 3],
+    tex_coords: [f32; 2],
+}
+
+impl TexturedVertex {
+    fn new(position: [f32; 3], tex_coords: [f32; 2]) -> Self {
+        Self {
+            position,
+            tex_coords,
+        }
+    }
+}
+
 fn main() {
     let mut window = Window::new(SCR_WIDTH, SCR_HEIGHT, "Learn Opengl", false, false).unwrap();
     let v_shader =
@@ -69,38 +85,45 @@ fn main() {
         vec3(-1.3, 1.0, -1.5),
     ];
 
-    let verts: [f32; 180] = [
-        -0.5, -0.5, -0.5, 0.0, 0.0, 0.5, -0.5, -0.5, 1.0, 0.0, 0.5, 0.5, -0.5, 1.0, 1.0, 0.5, 0.5,
-        -0.5, 1.0, 1.0, -0.5, 0.5, -0.5, 0.0, 1.0, -0.5, -0.5, -0.5, 0.0, 0.0, -0.5, -0.5, 0.5,
-        0.0, 0.0, 0.5, -0.5, 0.5, 1.0, 0.0, 0.5, 0.5, 0.5, 1.0, 1.0, 0.5, 0.5, 0.5, 1.0, 1.0, -0.5,
-        0.5, 0.5, 0.0, 1.0, -0.5, -0.5, 0.5, 0.0, 0.0, -0.5, 0.5, 0.5, 1.0, 0.0, -0.5, 0.5, -0.5,
-        1.0, 1.0, -0.5, -0.5, -0.5, 0.0, 1.0, -0.5, -0.5, -0.5, 0.0, 1.0, -0.5, -0.5, 0.5, 0.0,
-        0.0, -0.5, 0.5, 0.5, 1.0, 0.0, 0.5, 0.5, 0.5, 1.0, 0.0, 0.5, 0.5, -0.5, 1.0, 1.0, 0.5,
-        -0.5, -0.5, 0.0, 1.0, 0.5, -0.5, -0.5, 0.0, 1.0, 0.5, -0.5, 0.5, 0.0, 0.0, 0.5, 0.5, 0.5,
-        1.0, 0.0, -0.5, -0.5, -0.5, 0.0, 1.0, 0.5, -0.5, -0.5, 1.0, 1.0, 0.5, -0.5, 0.5, 1.0, 0.0,
-        0.5, -0.5, 0.5, 1.0, 0.0, -0.5, -0.5, 0.5, 0.0, 0.0, -0.5, -0.5, -0.5, 0.0, 1.0, -0.5, 0.5,
-        -0.5, 0.0, 1.0, 0.5, 0.5, -0.5, 1.0, 1.0, 0.5, 0.5, 0.5, 1.0, 0.0, 0.5, 0.5, 0.5, 1.0, 0.0,
-        -0.5, 0.5, 0.5, 0.0, 0.0, -0.5, 0.5, -0.5, 0.0, 1.0,
+    let verts: [TexturedVertex; 36] = [
+        TexturedVertex::new([-0.5, -0.5, -0.5], [0.0, 0.0]),
+        TexturedVertex::new([0.5, -0.5, -0.5], [1.0, 0.0]),
+        TexturedVertex::new([0.5, 0.5, -0.5], [1.0, 1.0]),
+        TexturedVertex::new([0.5, 0.5, -0.5], [1.0, 1.0]),
+        TexturedVertex::new([-0.5, 0.5, -0.5], [0.0, 1.0]),
+        TexturedVertex::new([-0.5, -0.5, -0.5], [0.0, 0.0]),
+        TexturedVertex::new([-0.5, -0.5, 0.5], [0.0, 0.0]),
+        TexturedVertex::new([0.5, -0.5, 0.5], [1.0, 0.0]),
+        TexturedVertex::new([0.5, 0.5, 0.5], [1.0, 1.0]),
+        TexturedVertex::new([0.5, 0.5, 0.5], [1.0, 1.0]),
+        TexturedVertex::new([-0.5, 0.5, 0.5], [0.0, 1.0]),
+        TexturedVertex::new([-0.5, -0.5, 0.5], [0.0, 0.0]),
+        TexturedVertex::new([-0.5, 0.5, 0.5], [1.0, 0.0]),
+        TexturedVertex::new([-0.5, 0.5, -0.5], [1.0, 1.0]),
+        TexturedVertex::new([-0.5, -0.5, -0.5], [0.0, 1.0]),
+        TexturedVertex::new([-0.5, -0.5, -0.5], [0.0, 1.0]),
+        TexturedVertex::new([-0.5, -0.5, 0.5], [0.0, 0.0]),
+        TexturedVertex::new([-0.5, 0.5, 0.5], [1.0, 0.0]),
+        TexturedVertex::new([0.5, 0.5, 0.5], [1.0, 0.0]),
+        TexturedVertex::new([0.5, 0.5, -0.5], [1.0, 1.0]),
+        TexturedVertex::new([0.5, -0.5, -0.5], [0.0, 1.0]),
+        TexturedVertex::new([0.5, -0.5, -0.5], [0.0, 1.0]),
+        TexturedVertex::new([0.5, -0.5, 0.5], [0.0, 0.0]),
+        TexturedVertex::new([0.5, 0.5, 0.5], [1.0, 0.0]),
+        TexturedVertex::new([-0.5, -0.5, -0.5], [0.0, 1.0]),
+        TexturedVertex::new([0.5, -0.5, -0.5], [1.0, 1.0]),
+        TexturedVertex::new([0.5, -0.5, 0.5], [1.0, 0.0]),
+        TexturedVertex::new([0.5, -0.5, 0.5], [1.0, 0.0]),
+        TexturedVertex::new([-0.5, -0.5, 0.5], [0.0, 0.0]),
+        TexturedVertex::new([-0.5, -0.5, -0.5], [0.0, 1.0]),
+        TexturedVertex::new([-0.5, 0.5, -0.5], [0.0, 1.0]),
+        TexturedVertex::new([0.5, 0.5, -0.5], [1.0, 1.0]),
+        TexturedVertex::new([0.5, 0.5, 0.5], [1.0, 0.0]),
+        TexturedVertex::new([0.5, 0.5, 0.5], [1.0, 0.0]),
+        TexturedVertex::new([-0.5, 0.5, 0.5], [0.0, 0.0]),
+        TexturedVertex::new([-0.5, 0.5, -0.5], [0.0, 1.0]),
     ];
-    let attributes = [
-        Attribute {
-            // cords
-            location: 0,
-            size: 3,
-            normalized: false,
-            stride: 5,
-            offset: 0,
-        },
-        Attribute {
-            // texture
-            location: 1,
-            size: 2,
-            normalized: false,
-            stride: 5,
-            offset: 3,
-        },
-    ];
-    let vbo_vba = VOs::new(&verts, &attributes, gl::TRIANGLES).expect("vbo or vba failed to bind");
+    let vbo_vba = VOs::new(&verts, gl::TRIANGLES).expect("vbo or vba failed to bind");
 
     let container_texture = image::open(&Path::new("./Learn-Opengl/container.jpg")).unwrap();
     let texture1 = Texture2D::new(
@@ -159,6 +182,9 @@ fn main() {
             shader.set_uniform("model", model).unwrap();
             vbo_vba.draw_arrays(0, 36).unwrap();
         }
+        for warning in shader.take_warnings() {
+            eprintln!("warning: {warning}");
+        }
     });
 }
 
@@ -47,4 +47,26 @@ impl<T: GLSize> EBO<T> {
         }
         return Ok(());
     }
+
+    // Draws `count` indices from `offset` `instances` times in one call
+    pub fn draw_elements_instanced(
+        &self,
+        vo: &VOs,
+        count: u32,
+        offset: usize,
+        instances: u32,
+    ) -> Result<()> {
+        vo.bind()?;
+        self.bind()?;
+        unsafe {
+            gl::DrawElementsInstanced(
+                vo.shape,
+                count as i32,
+                T::gl_type(),
+                (offset * T::gl_size_of()) as *const c_void,
+                instances as i32,
+            )
+        }
+        Ok(())
+    }
 }
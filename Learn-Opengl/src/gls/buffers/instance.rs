@@ -0,0 +1,69 @@
+use super::{
+    mesh::GrowableBuffer,
+    vertex::{checked_attributes, Vertex, VertexAttribute},
+};
+use anyhow::{bail, Result};
+use gl::types::GLenum;
+use std::{marker::PhantomData, mem};
+
+// A buffer of per-instance data, like a model matrix for every copy of a mesh. Once attached
+// to a `VOs` or `Mesh` the attributes of T advance once per instance instead of once per
+// vertex, so one instanced draw call draws every instance.
+pub struct InstanceBuffer<T: Vertex> {
+    buffer: GrowableBuffer,
+    len: usize,
+    phantom: PhantomData<T>,
+}
+
+impl<T: Vertex> InstanceBuffer<T> {
+    // `usage` is gl::STATIC_DRAW, gl::DYNAMIC_DRAW or gl::STREAM_DRAW, as for a `Mesh`
+    pub fn new(instances: &[T], usage: GLenum) -> Result<Self> {
+        if !matches!(usage, gl::STATIC_DRAW | gl::DYNAMIC_DRAW | gl::STREAM_DRAW) {
+            bail!("usage must be STATIC_DRAW, DYNAMIC_DRAW or STREAM_DRAW");
+        }
+        let buffer = GrowableBuffer::new(instances, gl::ARRAY_BUFFER, usage)?;
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+        Ok(Self {
+            buffer,
+            len: instances.len(),
+            phantom: PhantomData,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Replaces the instances, the VAOs it is attached to read the new ones
+    pub fn set(&mut self, instances: &[T]) -> Result<()> {
+        self.buffer.upload(instances)?;
+        self.len = instances.len();
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+        Ok(())
+    }
+
+    // Sets up the attributes of T on the bound VAO, after checking they don't overlap the
+    // ones it already has, and adds them to `used`
+    pub(super) fn attach(&self, used: &mut Vec<VertexAttribute>) -> Result<()> {
+        let attributes = checked_attributes::<T>(used)?;
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.buffer.id);
+        }
+        for at in attributes.iter() {
+            at.enable(mem::size_of::<T>(), 1);
+        }
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+        used.extend(attributes);
+        Ok(())
+    }
+}
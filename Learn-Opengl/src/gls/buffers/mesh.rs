@@ -0,0 +1,310 @@
+use super::{
+    bindable::Bindable,
+    instance::InstanceBuffer,
+    vertex::{checked_attributes, Vertex, VertexAttribute},
+};
+use crate::gls::gl_size::GLSize;
+use anyhow::{bail, Result};
+use gl::types::*;
+use std::{marker::PhantomData, mem, os::raw::c_void, ptr};
+
+// A type the indices of a mesh can have
+pub trait Index: GLSize + Copy {}
+
+impl Index for u8 {}
+impl Index for u16 {}
+impl Index for u32 {}
+
+// A buffer that keeps its storage between updates and only reallocates when the data
+// outgrows it
+pub(super) struct GrowableBuffer {
+    pub(super) id: u32,
+    target: GLenum,
+    usage: GLenum,
+    // bytes allocated
+    capacity: usize,
+}
+
+impl Drop for GrowableBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.id);
+        }
+    }
+}
+
+impl GrowableBuffer {
+    // leaves the buffer bound to `target`
+    pub(super) fn new<T>(data: &[T], target: GLenum, usage: GLenum) -> Result<Self> {
+        let mut id = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut id);
+        }
+        if id == 0 {
+            bail!("Failed to make the buffer");
+        }
+        let mut buffer = Self {
+            id,
+            target,
+            usage,
+            capacity: 0,
+        };
+        buffer.upload(data)?;
+        Ok(buffer)
+    }
+
+    // Replaces the contents with `data`, in place when it fits, growing to the next power of
+    // two when it doesn't. Leaves the buffer bound to its target. Fails when the storage
+    // would be too large for GL to address, leaving the buffer as it was.
+    pub(super) fn upload<T>(&mut self, data: &[T]) -> Result<()> {
+        let size = mem::size_of_val(data);
+        let grow = size > self.capacity || self.capacity == 0;
+        let capacity = if grow {
+            size.checked_next_power_of_two()
+                .filter(|&capacity| GLsizeiptr::try_from(capacity).is_ok())
+        } else {
+            Some(self.capacity)
+        };
+        let Some(capacity) = capacity else {
+            bail!("{size} bytes don't fit in a buffer");
+        };
+        unsafe {
+            gl::BindBuffer(self.target, self.id);
+            if grow {
+                self.capacity = capacity;
+                gl::BufferData(
+                    self.target,
+                    self.capacity as GLsizeiptr,
+                    ptr::null(),
+                    self.usage,
+                );
+            } else if self.usage == gl::STREAM_DRAW {
+                // orphan the storage the last draws may still be reading, so the driver
+                // hands out new storage instead of waiting for them
+                gl::BufferData(
+                    self.target,
+                    self.capacity as GLsizeiptr,
+                    ptr::null(),
+                    self.usage,
+                );
+            }
+            if size > 0 {
+                gl::BufferSubData(
+                    self.target,
+                    0,
+                    size as GLsizeiptr,
+                    data.as_ptr() as *const c_void,
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+// A VAO with its vertex buffer and, for indexed meshes, its index buffer. Meshes made with
+// DYNAMIC_DRAW or STREAM_DRAW can be changed every frame with `set_vertices` and
+// `set_indices`, which reuse the buffers and only grow them when the new data doesn't fit.
+pub struct Mesh<V: Vertex, I: Index = u32> {
+    vao: u32,
+    vertices: GrowableBuffer,
+    indices: Option<GrowableBuffer>,
+    // of the vertices and of the instance buffers attached
+    attributes: Vec<VertexAttribute>,
+    vertex_count: usize,
+    index_count: usize,
+    shape: GLenum,
+    phantom: PhantomData<(V, I)>,
+}
+
+impl<V: Vertex, I: Index> Drop for Mesh<V, I> {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
+
+impl<V: Vertex, I: Index> Bindable for Mesh<V, I> {
+    fn bind(&self) -> Result<()> {
+        unsafe {
+            gl::BindVertexArray(self.vao);
+        }
+        Ok(())
+    }
+}
+
+impl<V: Vertex, I: Index> Mesh<V, I> {
+    // `usage` is gl::STATIC_DRAW for meshes that never change, gl::DYNAMIC_DRAW for ones
+    // that change now and then and gl::STREAM_DRAW for ones rebuilt every frame
+    pub fn new(
+        vertices: &[V],
+        indices: Option<&[I]>,
+        shape: GLenum,
+        usage: GLenum,
+    ) -> Result<Self> {
+        if !matches!(usage, gl::STATIC_DRAW | gl::DYNAMIC_DRAW | gl::STREAM_DRAW) {
+            bail!("usage must be STATIC_DRAW, DYNAMIC_DRAW or STREAM_DRAW");
+        }
+        let attributes = checked_attributes::<V>(&[])?;
+        let mut vao = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+        }
+        if vao == 0 {
+            bail!("Failed to make the VAO");
+        }
+        unsafe {
+            gl::BindVertexArray(vao);
+        }
+        let result = Self::with_vao(vao, attributes, vertices, indices, shape, usage);
+        unsafe {
+            gl::BindVertexArray(0);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+        if result.is_err() {
+            unsafe {
+                gl::DeleteVertexArrays(1, &vao);
+            }
+        }
+        result
+    }
+
+    // makes the buffers with `vao` bound
+    fn with_vao(
+        vao: u32,
+        attributes: Vec<VertexAttribute>,
+        vertices: &[V],
+        indices: Option<&[I]>,
+        shape: GLenum,
+        usage: GLenum,
+    ) -> Result<Self> {
+        let vertex_buffer = GrowableBuffer::new(vertices, gl::ARRAY_BUFFER, usage)?;
+        for at in attributes.iter() {
+            at.enable(mem::size_of::<V>(), 0);
+        }
+        let index_buffer = match indices {
+            Some(indices) => Some(GrowableBuffer::new(
+                indices,
+                gl::ELEMENT_ARRAY_BUFFER,
+                usage,
+            )?),
+            None => None,
+        };
+        Ok(Self {
+            vao,
+            vertices: vertex_buffer,
+            indices: index_buffer,
+            vertex_count: vertices.len(),
+            index_count: indices.map_or(0, |i| i.len()),
+            attributes,
+            shape,
+            phantom: PhantomData,
+        })
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertex_count
+    }
+
+    pub fn index_count(&self) -> usize {
+        self.index_count
+    }
+
+    pub fn is_indexed(&self) -> bool {
+        self.indices.is_some()
+    }
+
+    pub fn set_shape(&mut self, shape: GLenum) {
+        self.shape = shape;
+    }
+
+    // Replaces the vertices, failing when there are too many to fit in a buffer
+    pub fn set_vertices(&mut self, vertices: &[V]) -> Result<()> {
+        self.vertices.upload(vertices)?;
+        self.vertex_count = vertices.len();
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+        Ok(())
+    }
+
+    // Replaces the indices, making the mesh indexed if it wasn't
+    pub fn set_indices(&mut self, indices: &[I]) -> Result<()> {
+        // the index buffer binding belongs to the VAO, so it has to be bound first
+        self.bind()?;
+        match self.indices.as_mut() {
+            Some(buffer) => buffer.upload(indices)?,
+            None => {
+                self.indices = Some(GrowableBuffer::new(
+                    indices,
+                    gl::ELEMENT_ARRAY_BUFFER,
+                    self.vertices.usage,
+                )?)
+            }
+        }
+        self.index_count = indices.len();
+        unsafe {
+            gl::BindVertexArray(0);
+        }
+        Ok(())
+    }
+
+    // Reads the attributes of T from `instances` once per instance, see `draw_instanced`
+    pub fn attach_instances<T: Vertex>(&mut self, instances: &InstanceBuffer<T>) -> Result<()> {
+        self.bind()?;
+        let result = instances.attach(&mut self.attributes);
+        unsafe {
+            gl::BindVertexArray(0);
+        }
+        result
+    }
+
+    // Draws the whole mesh, with glDrawElements if it has indices and glDrawArrays if not
+    pub fn draw(&self) -> Result<()> {
+        self.bind()?;
+        unsafe {
+            if self.indices.is_some() {
+                if self.index_count > 0 {
+                    gl::DrawElements(
+                        self.shape,
+                        self.index_count as GLsizei,
+                        I::gl_type(),
+                        ptr::null(),
+                    );
+                }
+            } else if self.vertex_count > 0 {
+                gl::DrawArrays(self.shape, 0, self.vertex_count as GLsizei);
+            }
+        }
+        Ok(())
+    }
+
+    // Draws the whole mesh `instances` times in one call
+    pub fn draw_instanced(&self, instances: usize) -> Result<()> {
+        self.bind()?;
+        if instances == 0 {
+            return Ok(());
+        }
+        unsafe {
+            if self.indices.is_some() {
+                if self.index_count > 0 {
+                    gl::DrawElementsInstanced(
+                        self.shape,
+                        self.index_count as GLsizei,
+                        I::gl_type(),
+                        ptr::null(),
+                        instances as GLsizei,
+                    );
+                }
+            } else if self.vertex_count > 0 {
+                gl::DrawArraysInstanced(
+                    self.shape,
+                    0,
+                    self.vertex_count as GLsizei,
+                    instances as GLsizei,
+                );
+            }
+        }
+        Ok(())
+    }
+}
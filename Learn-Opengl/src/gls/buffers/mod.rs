@@ -1,15 +1,21 @@
 pub mod bindable;
 pub mod ebo;
 pub mod framebuffer;
+pub mod instance;
+pub mod mesh;
 pub mod texture;
+pub mod ubo;
+pub mod vertex;
 
 use super::gl_size::GLSize;
 use anyhow::{bail, Result};
 use bindable::Bindable;
 use gl::types::*;
-use std::os::raw::c_void;
+use instance::InstanceBuffer;
+use std::{mem, os::raw::c_void};
+use vertex::{Vertex, VertexAttribute};
 
-unsafe fn make_buffer<T: GLSize>(data: &[T], buffer_type: GLenum, usage: GLenum) -> Result<u32> {
+unsafe fn make_buffer<T>(data: &[T], buffer_type: GLenum, usage: GLenum) -> Result<u32> {
     let mut buffer = 0;
 
     gl::GenBuffers(1, &mut buffer);
@@ -19,8 +25,8 @@ unsafe fn make_buffer<T: GLSize>(data: &[T], buffer_type: GLenum, usage: GLenum)
     gl::BindBuffer(buffer_type, buffer);
     gl::BufferData(
         buffer_type,
-        (data.len() * T::gl_size_of()) as GLsizeiptr,
-        &data[0] as *const T as *const c_void,
+        mem::size_of_val(data) as GLsizeiptr,
+        data.as_ptr() as *const c_void,
         usage,
     );
     return Ok(buffer);
@@ -30,14 +36,8 @@ pub struct VOs {
     vbo: u32,
     vao: u32,
     shape: GLenum,
-}
-
-pub struct Attribute {
-    pub location: u32,
-    pub size: i32,
-    pub normalized: bool,
-    pub stride: usize,
-    pub offset: usize,
+    // of the vertices and of the instance buffers attached
+    attributes: Vec<VertexAttribute>,
 }
 
 impl Drop for VOs {
@@ -50,54 +50,51 @@ impl Drop for VOs {
 }
 
 impl VOs {
-    pub fn new<T: GLSize>(verts: &[T], attributes: &[Attribute], shape: GLenum) -> Result<Self> {
+    // Puts `verts` in a new VBO, with the VAO set up from the attributes of V
+    pub fn new<V: Vertex>(verts: &[V], shape: GLenum) -> Result<Self> {
         let vbo;
         let mut vao = 0;
-        if verts.len() <= 0 {
+        if verts.is_empty() {
             bail!("input verts was empty");
         }
-        if attributes.len() >= gl::MAX_VERTEX_ATTRIBS as usize {
-            bail!("Too many attributes")
-        }
+        let attributes = vertex::checked_attributes::<V>(&[])?;
         unsafe {
             gl::GenVertexArrays(1, &mut vao);
-            if vao <= 0 {
+            if vao == 0 {
                 bail!("Failed to make the VBA");
             }
             gl::BindVertexArray(vao);
             vbo = make_buffer(verts, gl::ARRAY_BUFFER, gl::STATIC_DRAW)?;
-            for (i, at) in attributes.iter().enumerate() {
-                if at.size < 1 || at.size > 4 {
-                    let _ = Self { vbo, vao, shape }; // this drops the current VBO and VAO
-                                                      // deleting them from GPU memory
-                    bail!("size is not between 1-4 or GLBGRA")
-                }
-
-                if at.offset >= verts.len() || at.stride >= verts.len() {
-                    let _ = Self { vbo, vao, shape }; // this drops the current VBO and VAO
-                                                      // deleting them from GPU memory
-                    bail!("Properties do not make sense")
-                }
-                gl::VertexAttribPointer(
-                    at.location,
-                    at.size,
-                    T::gl_type(),
-                    if at.normalized { gl::TRUE } else { gl::FALSE },
-                    (at.stride * T::gl_size_of()) as GLsizei,
-                    (at.offset * T::gl_size_of()) as *const c_void,
-                );
-                gl::EnableVertexAttribArray(i as GLuint);
+            for at in attributes.iter() {
+                at.enable(mem::size_of::<V>(), 0);
             }
             gl::BindBuffer(gl::ARRAY_BUFFER, 0);
             gl::BindVertexArray(0);
         };
-        Ok(Self { vbo, vao, shape })
+        Ok(Self {
+            vbo,
+            vao,
+            shape,
+            attributes,
+        })
     }
 
     pub fn set_shape(&mut self, shape: GLenum) {
         self.shape = shape;
     }
 
+    // Reads the attributes of T from `instances` once per instance, for the instanced draws
+    pub fn attach_instances<T: Vertex>(&mut self, instances: &InstanceBuffer<T>) -> Result<()> {
+        unsafe {
+            gl::BindVertexArray(self.vao);
+        }
+        let result = instances.attach(&mut self.attributes);
+        unsafe {
+            gl::BindVertexArray(0);
+        }
+        result
+    }
+
     pub fn draw_arrays(&self, start: i32, len: u32) -> Result<()> {
         let len = len as i32;
         self.bind()?;
@@ -106,6 +103,15 @@ impl VOs {
         }
         Ok(())
     }
+
+    // Draws the vertices from `start` to `start + len` `instances` times in one call
+    pub fn draw_arrays_instanced(&self, start: i32, len: u32, instances: u32) -> Result<()> {
+        self.bind()?;
+        unsafe {
+            gl::DrawArraysInstanced(self.shape, start, len as i32, instances as i32);
+        }
+        Ok(())
+    }
 }
 
 impl Bindable for VOs {
@@ -0,0 +1,442 @@
+use super::{bindable::Bindable, make_buffer};
+use crate::gls::shader::ShaderProgram;
+use anyhow::{bail, Result};
+use cgmath::{Matrix3, Matrix4, Vector2, Vector3, Vector4};
+use gl::types::*;
+use std::{ffi::CString, marker::PhantomData, os::raw::c_void, sync::Mutex};
+
+// Bytes laid out by the std140 rules of uniform blocks
+#[derive(Debug, Default)]
+pub struct Std140Writer {
+    bytes: Vec<u8>,
+}
+
+impl Std140Writer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // pads with zeros up to the next multiple of `align`
+    pub fn align(&mut self, align: usize) {
+        let len = self.bytes.len().next_multiple_of(align);
+        self.bytes.resize(len, 0);
+    }
+
+    pub fn write<T: Std140 + ?Sized>(&mut self, value: &T) {
+        self.align(T::ALIGN);
+        value.write_std140(self);
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+// A value that can be stored in a std140 uniform block. Vectors of 3 take the space of 4,
+// and arrays and structs start and end on 16 bytes. `#[derive(Std140)]` implements it for
+// structs, field by field.
+pub trait Std140 {
+    // base alignment in bytes
+    const ALIGN: usize;
+    // writes the value at the writer's offset, which is already aligned to ALIGN
+    fn write_std140(&self, w: &mut Std140Writer);
+}
+
+// `#[derive(Std140)]` for structs, see learn-opengl-derive
+pub use learn_opengl_derive::Std140;
+
+pub fn std140_bytes<T: Std140 + ?Sized>(value: &T) -> Vec<u8> {
+    let mut w = Std140Writer::new();
+    w.write(value);
+    w.into_bytes()
+}
+
+impl Std140 for f32 {
+    const ALIGN: usize = 4;
+    fn write_std140(&self, w: &mut Std140Writer) {
+        w.push(&self.to_ne_bytes());
+    }
+}
+
+impl Std140 for i32 {
+    const ALIGN: usize = 4;
+    fn write_std140(&self, w: &mut Std140Writer) {
+        w.push(&self.to_ne_bytes());
+    }
+}
+
+impl Std140 for u32 {
+    const ALIGN: usize = 4;
+    fn write_std140(&self, w: &mut Std140Writer) {
+        w.push(&self.to_ne_bytes());
+    }
+}
+
+impl Std140 for bool {
+    const ALIGN: usize = 4;
+    fn write_std140(&self, w: &mut Std140Writer) {
+        w.push(&(*self as u32).to_ne_bytes());
+    }
+}
+
+impl Std140 for Vector2<f32> {
+    const ALIGN: usize = 8;
+    fn write_std140(&self, w: &mut Std140Writer) {
+        w.push(&self.x.to_ne_bytes());
+        w.push(&self.y.to_ne_bytes());
+    }
+}
+
+impl Std140 for Vector3<f32> {
+    const ALIGN: usize = 16;
+    fn write_std140(&self, w: &mut Std140Writer) {
+        w.push(&self.x.to_ne_bytes());
+        w.push(&self.y.to_ne_bytes());
+        w.push(&self.z.to_ne_bytes());
+    }
+}
+
+impl Std140 for Vector4<f32> {
+    const ALIGN: usize = 16;
+    fn write_std140(&self, w: &mut Std140Writer) {
+        w.push(&self.x.to_ne_bytes());
+        w.push(&self.y.to_ne_bytes());
+        w.push(&self.z.to_ne_bytes());
+        w.push(&self.w.to_ne_bytes());
+    }
+}
+
+// matrices are stored like arrays of their columns
+impl Std140 for Matrix3<f32> {
+    const ALIGN: usize = 16;
+    fn write_std140(&self, w: &mut Std140Writer) {
+        for column in [self.x, self.y, self.z] {
+            w.write(&column);
+        }
+        w.align(16);
+    }
+}
+
+impl Std140 for Matrix4<f32> {
+    const ALIGN: usize = 16;
+    fn write_std140(&self, w: &mut Std140Writer) {
+        for column in [self.x, self.y, self.z, self.w] {
+            w.write(&column);
+        }
+    }
+}
+
+// every element of an array starts on 16 bytes, even scalars
+impl<T: Std140> Std140 for [T] {
+    const ALIGN: usize = 16;
+    fn write_std140(&self, w: &mut Std140Writer) {
+        for element in self.iter() {
+            w.write(element);
+            w.align(16);
+        }
+    }
+}
+
+impl<T: Std140, const N: usize> Std140 for [T; N] {
+    const ALIGN: usize = 16;
+    fn write_std140(&self, w: &mut Std140Writer) {
+        self.as_slice().write_std140(w);
+    }
+}
+
+impl<T: Std140> Std140 for Vec<T> {
+    const ALIGN: usize = 16;
+    fn write_std140(&self, w: &mut Std140Writer) {
+        self.as_slice().write_std140(w);
+    }
+}
+
+// binding points handed out so far, and the ones given back by dropped buffers
+static BINDINGS: Mutex<(u32, Vec<u32>)> = Mutex::new((0, Vec::new()));
+
+fn take_binding() -> Result<u32> {
+    let mut bindings = BINDINGS.lock().unwrap();
+    if let Some(binding) = bindings.1.pop() {
+        return Ok(binding);
+    }
+    let mut max = 0;
+    unsafe {
+        gl::GetIntegerv(gl::MAX_UNIFORM_BUFFER_BINDINGS, &mut max);
+    }
+    if bindings.0 >= max as u32 {
+        bail!("Ran out of uniform buffer binding points, there are {max}");
+    }
+    bindings.0 += 1;
+    Ok(bindings.0 - 1)
+}
+
+// A uniform buffer holding a T in std140 layout, bound to a binding point of its own for as
+// long as it lives. Programs read it through a uniform block bound with `bind_block`, so
+// one upload is seen by every program using the block.
+pub struct UniformBuffer<T: Std140 + ?Sized> {
+    ubo: u32,
+    binding: u32,
+    // what the buffer holds, so updates only upload the bytes that changed
+    bytes: Vec<u8>,
+    phantom: PhantomData<T>,
+}
+
+impl<T: Std140 + ?Sized> Drop for UniformBuffer<T> {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.ubo);
+        }
+        BINDINGS.lock().unwrap().1.push(self.binding);
+    }
+}
+
+impl<T: Std140 + ?Sized> Bindable for UniformBuffer<T> {
+    fn bind(&self) -> Result<()> {
+        unsafe {
+            gl::BindBuffer(gl::UNIFORM_BUFFER, self.ubo);
+        }
+        Ok(())
+    }
+}
+
+impl<T: Std140 + ?Sized> UniformBuffer<T> {
+    pub fn new(value: &T) -> Result<Self> {
+        let bytes = std140_bytes(value);
+        if bytes.is_empty() {
+            bail!("uniform buffer would be empty");
+        }
+        let binding = take_binding()?;
+        let ubo = unsafe { make_buffer(&bytes, gl::UNIFORM_BUFFER, gl::DYNAMIC_DRAW) };
+        let ubo = match ubo {
+            Ok(ubo) => ubo,
+            Err(e) => {
+                BINDINGS.lock().unwrap().1.push(binding);
+                return Err(e);
+            }
+        };
+        unsafe {
+            gl::BindBufferBase(gl::UNIFORM_BUFFER, binding, ubo);
+            gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+        }
+        Ok(Self {
+            ubo,
+            binding,
+            bytes,
+            phantom: PhantomData,
+        })
+    }
+
+    pub fn binding(&self) -> u32 {
+        self.binding
+    }
+
+    // Uploads `value`, only the range of bytes that differ from what the buffer holds. A
+    // value of a different size, like a longer slice, replaces the whole buffer.
+    pub fn set(&mut self, value: &T) -> Result<()> {
+        let bytes = std140_bytes(value);
+        if bytes.is_empty() {
+            bail!("uniform buffer would be empty");
+        }
+        self.bind()?;
+        unsafe {
+            if bytes.len() != self.bytes.len() {
+                gl::BufferData(
+                    gl::UNIFORM_BUFFER,
+                    bytes.len() as GLsizeiptr,
+                    bytes.as_ptr() as *const c_void,
+                    gl::DYNAMIC_DRAW,
+                );
+                gl::BindBufferBase(gl::UNIFORM_BUFFER, self.binding, self.ubo);
+            } else {
+                let first = bytes
+                    .iter()
+                    .zip(self.bytes.iter())
+                    .position(|(a, b)| a != b);
+                let last = bytes
+                    .iter()
+                    .zip(self.bytes.iter())
+                    .rposition(|(a, b)| a != b);
+                if let (Some(first), Some(last)) = (first, last) {
+                    gl::BufferSubData(
+                        gl::UNIFORM_BUFFER,
+                        first as GLintptr,
+                        (last + 1 - first) as GLsizeiptr,
+                        bytes[first..].as_ptr() as *const c_void,
+                    );
+                }
+            }
+            gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+        }
+        self.bytes = bytes;
+        Ok(())
+    }
+
+    // Points the uniform block `block` of `program` at this buffer
+    pub fn bind_block(&self, program: &ShaderProgram, block: &str) -> Result<()> {
+        let name = CString::new(block)?;
+        unsafe {
+            let index = gl::GetUniformBlockIndex(program.id, name.as_ptr());
+            if index == gl::INVALID_INDEX {
+                bail!(
+                    "uniform block {block} is not used by shader program {}",
+                    program.id
+                );
+            }
+            let mut size = 0;
+            gl::GetActiveUniformBlockiv(program.id, index, gl::UNIFORM_BLOCK_DATA_SIZE, &mut size);
+            if size as usize > self.bytes.len() {
+                bail!(
+                    "uniform block {block} is {size} bytes but the buffer only holds {}",
+                    self.bytes.len()
+                );
+            }
+            gl::UniformBlockBinding(program.id, index, self.binding);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cgmath::{vec2, vec3, vec4, SquareMatrix};
+
+    // the fields `PointLight` puts in its block, it can't be made without a GL context
+    #[derive(Std140)]
+    struct Light {
+        position: Vector3<f32>,
+        color: Vector3<f32>,
+        constant: f32,
+        linear: f32,
+        quadratic: f32,
+        ambient: Vector3<f32>,
+        diffuse: Vector3<f32>,
+        specular: Vector3<f32>,
+    }
+
+    fn light(i: f32) -> Light {
+        Light {
+            position: vec3(1., 2., 3.) * i,
+            color: vec3(4., 5., 6.) * i,
+            constant: 7. * i,
+            linear: 8. * i,
+            quadratic: 9. * i,
+            ambient: vec3(10., 11., 12.) * i,
+            diffuse: vec3(13., 14., 15.) * i,
+            specular: vec3(16., 17., 18.) * i,
+        }
+    }
+
+    #[derive(Std140)]
+    struct Inner {
+        v: Vector3<f32>,
+        s: f32,
+    }
+
+    #[derive(Std140)]
+    struct Outer {
+        a: f32,
+        inner: Inner,
+        b: Vector2<f32>,
+        flags: [bool; 2],
+        m: Matrix3<f32>,
+        c: f32,
+        #[std140(skip)]
+        #[allow(dead_code)]
+        skipped: f32,
+    }
+
+    // the bytes as floats, padding reads as 0
+    fn floats(bytes: &[u8]) -> Vec<f32> {
+        bytes
+            .chunks(4)
+            .map(|b| f32::from_ne_bytes(b.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_point_light() {
+        let bytes = std140_bytes(&light(1.));
+        assert_eq!(bytes.len(), 96);
+        #[rustfmt::skip]
+        assert_eq!(
+            floats(&bytes),
+            [
+                1., 2., 3., 0.,     // position at 0
+                4., 5., 6., 7.,     // color at 16, constant packed after it at 28
+                8., 9., 0., 0.,     // linear at 32, quadratic at 36
+                10., 11., 12., 0.,  // ambient at 48
+                13., 14., 15., 0.,  // diffuse at 64
+                16., 17., 18., 0.,  // specular at 80, padded to 96
+            ]
+        );
+    }
+
+    #[test]
+    fn test_arrays() {
+        let lights = vec![light(1.), light(2.)];
+        let bytes = std140_bytes(&lights);
+        assert_eq!(bytes.len(), 192);
+        assert_eq!(floats(&bytes[96..]), floats(&std140_bytes(&light(2.))));
+        assert_eq!(std140_bytes(lights.as_slice()), bytes);
+        // every element starts on 16 bytes
+        assert_eq!(
+            floats(&std140_bytes(&[1f32, 2., 3.])),
+            [1., 0., 0., 0., 2., 0., 0., 0., 3., 0., 0., 0.]
+        );
+        assert_eq!(
+            floats(&std140_bytes(&[vec2(1f32, 2.), vec2(3., 4.)])),
+            [1., 2., 0., 0., 3., 4., 0., 0.]
+        );
+        let empty: Vec<f32> = vec![];
+        assert!(std140_bytes(&empty).is_empty());
+    }
+
+    #[test]
+    fn test_nested() {
+        let outer = Outer {
+            a: 1.,
+            inner: Inner {
+                v: vec3(2., 3., 4.),
+                s: 5.,
+            },
+            b: vec2(6., 7.),
+            flags: [true, false],
+            m: Matrix3::from_diagonal(vec3(8., 9., 10.)),
+            c: 11.,
+            skipped: 12.,
+        };
+        let bytes = std140_bytes(&outer);
+        assert_eq!(bytes.len(), 144);
+        let f = floats(&bytes);
+        // a at 0, the struct starts on 16 with s packed after v
+        assert_eq!(f[..8], [1., 0., 0., 0., 2., 3., 4., 5.]);
+        // b at 32, the bool array elements at 48 and 64 as 32 bit integers
+        assert_eq!(f[8..10], [6., 7.]);
+        assert_eq!(bytes[48..52], 1u32.to_ne_bytes());
+        assert_eq!(bytes[64..68], 0u32.to_ne_bytes());
+        // the matrix columns at 80, 96 and 112, c right after them at 128
+        assert_eq!(f[20..32], [8., 0., 0., 0., 0., 9., 0., 0., 0., 0., 10., 0.]);
+        assert_eq!(f[32..], [11., 0., 0., 0.]);
+    }
+
+    #[test]
+    fn test_matrix4() {
+        let m = Matrix4::from_cols(
+            vec4(1., 2., 3., 4.),
+            vec4(5., 6., 7., 8.),
+            vec4(9., 10., 11., 12.),
+            vec4(13., 14., 15., 16.),
+        );
+        let bytes = std140_bytes(&[m, Matrix4::identity()]);
+        assert_eq!(bytes.len(), 128);
+        assert_eq!(
+            floats(&bytes[..64]),
+            (1..=16).map(|i| i as f32).collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use anyhow::{bail, Result};
+use cgmath::{Matrix2, Matrix3, Matrix4, Vector2, Vector3, Vector4};
+use gl::types::*;
+use std::os::raw::c_void;
+
+// `#[derive(Vertex)]` for structs, see learn-opengl-derive
+pub use learn_opengl_derive::Vertex;
+
+// A type the components of an attribute can have
+pub trait Component: Copy {
+    const GL_TYPE: GLenum;
+    // whether the shader reads it as an int, not a float
+    const INTEGER: bool;
+}
+
+macro_rules! component {
+    ($ty:ty, $gl_type:expr, $integer:expr) => {
+        impl Component for $ty {
+            const GL_TYPE: GLenum = $gl_type;
+            const INTEGER: bool = $integer;
+        }
+    };
+}
+
+component!(f32, gl::FLOAT, false);
+component!(i8, gl::BYTE, true);
+component!(u8, gl::UNSIGNED_BYTE, true);
+component!(i16, gl::SHORT, true);
+component!(u16, gl::UNSIGNED_SHORT, true);
+component!(i32, gl::INT, true);
+component!(u32, gl::UNSIGNED_INT, true);
+
+// A type a vertex attribute can have, a scalar, 2 to 4 components or a matrix. Matrices take
+// one location per column.
+pub trait AttributeType: Copy {
+    type Component: Component;
+    const SIZE: i32;
+    const COLUMNS: u32 = 1;
+}
+
+impl<T: Component> AttributeType for T {
+    type Component = T;
+    const SIZE: i32 = 1;
+}
+
+macro_rules! attribute_type {
+    ($ty:ident<T>, $size:expr) => {
+        impl<T: Component> AttributeType for $ty<T> {
+            type Component = T;
+            const SIZE: i32 = $size;
+        }
+    };
+    ([T; $size:expr]) => {
+        impl<T: Component> AttributeType for [T; $size] {
+            type Component = T;
+            const SIZE: i32 = $size;
+        }
+    };
+}
+
+attribute_type!([T; 1]);
+attribute_type!([T; 2]);
+attribute_type!([T; 3]);
+attribute_type!([T; 4]);
+attribute_type!(Vector2<T>, 2);
+attribute_type!(Vector3<T>, 3);
+attribute_type!(Vector4<T>, 4);
+
+macro_rules! matrix_attribute_type {
+    ($ty:ident<T>, $size:expr) => {
+        impl<T: Component> AttributeType for $ty<T> {
+            type Component = T;
+            const SIZE: i32 = $size;
+            const COLUMNS: u32 = $size;
+        }
+    };
+}
+
+matrix_attribute_type!(Matrix2<T>, 2);
+matrix_attribute_type!(Matrix3<T>, 3);
+matrix_attribute_type!(Matrix4<T>, 4);
+
+// Where an attribute is in a vertex and how the shader reads it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VertexAttribute {
+    pub location: u32,
+    pub size: i32,
+    pub gl_type: GLenum,
+    // integers are read as floats from 0 to 1 (or -1 to 1 when signed)
+    pub normalized: bool,
+    // integers that aren't normalized are read as ints
+    pub integer: bool,
+    // in bytes from the start of the vertex
+    pub offset: usize,
+    // locations taken from `location` on, one per column for matrices
+    pub columns: u32,
+}
+
+impl VertexAttribute {
+    pub fn of<A: AttributeType>(location: u32, offset: usize, normalized: bool) -> Self {
+        Self {
+            location,
+            size: A::SIZE,
+            gl_type: <A::Component as Component>::GL_TYPE,
+            normalized,
+            integer: <A::Component as Component>::INTEGER && !normalized,
+            offset,
+            columns: A::COLUMNS,
+        }
+    }
+
+    pub fn locations(&self) -> std::ops::Range<u32> {
+        self.location..self.location + self.columns
+    }
+
+    // Sets up the attribute on the bound VAO, reading from the bound GL_ARRAY_BUFFER. With
+    // a divisor of 0 it advances every vertex, with 1 every instance.
+    pub(super) fn enable(&self, stride: usize, divisor: u32) {
+        let component_size = match self.gl_type {
+            gl::BYTE | gl::UNSIGNED_BYTE => 1,
+            gl::SHORT | gl::UNSIGNED_SHORT => 2,
+            _ => 4,
+        };
+        let column_size = self.size as usize * component_size;
+        for (column, location) in self.locations().enumerate() {
+            let offset = (self.offset + column * column_size) as *const c_void;
+            unsafe {
+                if self.integer {
+                    gl::VertexAttribIPointer(
+                        location,
+                        self.size,
+                        self.gl_type,
+                        stride as GLsizei,
+                        offset,
+                    );
+                } else {
+                    gl::VertexAttribPointer(
+                        location,
+                        self.size,
+                        self.gl_type,
+                        if self.normalized { gl::TRUE } else { gl::FALSE },
+                        stride as GLsizei,
+                        offset,
+                    );
+                }
+                gl::EnableVertexAttribArray(location);
+                gl::VertexAttribDivisor(location, divisor);
+            }
+        }
+    }
+}
+
+// A vertex that can be put in a vertex buffer, one attribute per field. Implement it with
+// `#[derive(Vertex)]`.
+pub trait Vertex: Copy {
+    fn attributes() -> Vec<VertexAttribute>;
+}
+
+// The attributes of V, checked against the number of locations the driver has and against
+// the locations already taken by `used`
+pub(super) fn checked_attributes<V: Vertex>(
+    used: &[VertexAttribute],
+) -> Result<Vec<VertexAttribute>> {
+    let attributes = V::attributes();
+    let mut max_attributes = 0;
+    unsafe {
+        gl::GetIntegerv(gl::MAX_VERTEX_ATTRIBS, &mut max_attributes);
+    }
+    check_locations(&attributes, used, max_attributes as u32)?;
+    Ok(attributes)
+}
+
+fn check_locations(
+    attributes: &[VertexAttribute],
+    used: &[VertexAttribute],
+    max_attributes: u32,
+) -> Result<()> {
+    for (i, at) in attributes.iter().enumerate() {
+        if at.locations().end > max_attributes {
+            bail!(
+                "attribute location {} is past the last one, {}",
+                at.locations().end - 1,
+                max_attributes as i64 - 1
+            )
+        }
+        let overlapping = used
+            .iter()
+            .chain(attributes[..i].iter())
+            .find(|other| at.locations().any(|l| other.locations().contains(&l)));
+        if let Some(other) = overlapping {
+            bail!(
+                "attributes at locations {:?} and {:?} overlap",
+                other.locations(),
+                at.locations()
+            )
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::mem::offset_of;
+
+    use super::*;
+
+    #[derive(Clone, Copy, Vertex)]
+    struct TestVertex {
+        position: [f32; 3],
+        model: Matrix4<f32>,
+        uv: Vector2<f32>,
+        #[vertex(skip)]
+        id: u32,
+        #[vertex(location = 8, normalized)]
+        color: [u8; 4],
+        count: u16,
+    }
+
+    #[derive(Clone, Copy, Vertex)]
+    struct Overlapping {
+        model: Matrix4<f32>,
+        #[vertex(location = 3)]
+        uv: Vector2<f32>,
+    }
+
+    #[test]
+    fn test_attributes() {
+        let attributes = TestVertex::attributes();
+        let expected = [
+            VertexAttribute::of::<[f32; 3]>(0, offset_of!(TestVertex, position), false),
+            VertexAttribute::of::<Matrix4<f32>>(1, offset_of!(TestVertex, model), false),
+            VertexAttribute::of::<Vector2<f32>>(5, offset_of!(TestVertex, uv), false),
+            VertexAttribute::of::<[u8; 4]>(8, offset_of!(TestVertex, color), true),
+            VertexAttribute::of::<u16>(9, offset_of!(TestVertex, count), false),
+        ];
+        assert_eq!(attributes, expected);
+        assert!(check_locations(&attributes, &[], 16).is_ok());
+    }
+
+    #[test]
+    fn test_of() {
+        let position = VertexAttribute::of::<[f32; 3]>(0, 0, false);
+        assert_eq!((position.size, position.gl_type), (3, gl::FLOAT));
+        assert!(!position.integer && !position.normalized);
+        assert_eq!(position.locations(), 0..1);
+
+        // a matrix takes a location per column
+        let model = VertexAttribute::of::<Matrix4<f32>>(1, 12, false);
+        assert_eq!((model.size, model.columns), (4, 4));
+        assert_eq!(model.locations(), 1..5);
+
+        // normalized integers are read as floats
+        let color = VertexAttribute::of::<[u8; 4]>(8, 0, true);
+        assert_eq!((color.size, color.gl_type), (4, gl::UNSIGNED_BYTE));
+        assert!(color.normalized && !color.integer);
+        let count = VertexAttribute::of::<u16>(9, 0, false);
+        assert_eq!((count.size, count.gl_type), (1, gl::UNSIGNED_SHORT));
+        assert!(count.integer);
+    }
+
+    #[test]
+    fn test_check_locations() {
+        // the uv at location 3 is inside the matrix's 0..4
+        let overlapping = Overlapping::attributes();
+        assert_eq!(overlapping[1].location, 3);
+        assert!(check_locations(&overlapping, &[], 16).is_err());
+
+        // against locations used by another buffer
+        let attributes = TestVertex::attributes();
+        let used = [VertexAttribute::of::<Vector4<f32>>(9, 0, false)];
+        assert!(check_locations(&attributes, &used, 16).is_err());
+        let used = [VertexAttribute::of::<Vector4<f32>>(10, 0, false)];
+        assert!(check_locations(&attributes, &used, 16).is_ok());
+
+        // past the driver's last location
+        assert!(check_locations(&attributes, &[], 10).is_ok());
+        assert!(check_locations(&attributes, &[], 9).is_err());
+    }
+}
@@ -15,6 +15,16 @@ impl GLSize for u8 {
     }
 }
 
+impl GLSize for u16 {
+    fn gl_size_of() -> usize {
+        mem::size_of::<GLushort>()
+    }
+
+    fn gl_type() -> GLenum {
+        gl::UNSIGNED_SHORT
+    }
+}
+
 impl GLSize for f32 {
     fn gl_size_of() -> usize {
         mem::size_of::<GLfloat>()
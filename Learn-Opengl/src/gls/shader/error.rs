@@ -0,0 +1,198 @@
+use gl::types::*;
+use std::{fmt, path::PathBuf};
+
+// One message from a driver's info log that points at a line of the source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    // index of the source string the line is in, 0 for a single source
+    pub file: u32,
+    pub line: u32,
+    // the file and line before preprocessing, when the source was preprocessed
+    pub origin: Option<(PathBuf, u32)>,
+    pub message: String,
+}
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Compile {
+        stage: GLenum,
+        log: String,
+        diagnostics: Vec<Diagnostic>,
+        source: String,
+    },
+    Link {
+        log: String,
+    },
+}
+
+pub fn stage_name(stage: GLenum) -> &'static str {
+    match stage {
+        gl::VERTEX_SHADER => "VERTEX",
+        gl::FRAGMENT_SHADER => "FRAGMENT",
+        gl::GEOMETRY_SHADER => "GEOMETRY",
+        _ => "UNKNOWN",
+    }
+}
+
+// reads a number from the front of `s`, returning it and the rest
+fn number(s: &str) -> Option<(u32, &str)> {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    Some((s[..end].parse().ok()?, &s[end..]))
+}
+
+// Parses the location drivers put in front of a message:
+//   NVIDIA      0(12) : error C0000: ...
+//   Mesa        0:12(5): error: ...
+//   AMD, Apple  ERROR: 0:12: ...
+fn parse_line(line: &str) -> Option<Diagnostic> {
+    let trimmed = line.trim();
+    let (severity, rest) = match ["ERROR:", "WARNING:"]
+        .iter()
+        .find_map(|prefix| Some((prefix, trimmed.strip_prefix(prefix)?)))
+    {
+        Some((prefix, rest)) => (Some(prefix.trim_end_matches(':').to_lowercase()), rest),
+        None => (None, trimmed),
+    };
+    let (file, rest) = number(rest.trim_start())?;
+    let (line_number, rest) = if let Some(rest) = rest.strip_prefix('(') {
+        let (n, rest) = number(rest)?;
+        (n, rest.strip_prefix(')')?)
+    } else {
+        number(rest.strip_prefix(':')?)?
+    };
+    // Mesa adds the column
+    let rest = match rest.strip_prefix('(').and_then(number) {
+        Some((_, after)) => after.strip_prefix(')').unwrap_or(after),
+        None => rest,
+    };
+    let rest = rest.trim_start_matches([' ', ':']);
+    Some(Diagnostic {
+        file,
+        line: line_number,
+        origin: None,
+        message: match severity {
+            Some(severity) => format!("{severity}: {rest}"),
+            None => rest.to_owned(),
+        },
+    })
+}
+
+pub fn parse_log(log: &str) -> Vec<Diagnostic> {
+    log.lines().filter_map(parse_line).collect()
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::Compile {
+                stage,
+                log,
+                diagnostics,
+                source,
+            } => {
+                writeln!(
+                    f,
+                    "ERROR::SHADER::{}::COMPILATION_FAILED",
+                    stage_name(*stage)
+                )?;
+                if diagnostics.is_empty() {
+                    return write!(f, "{}", log.trim_end());
+                }
+                let lines: Vec<&str> = source.lines().collect();
+                for d in diagnostics.iter() {
+                    match &d.origin {
+                        Some((path, line)) => {
+                            writeln!(f, "{}:{}: {}", path.display(), line, d.message)?
+                        }
+                        None => writeln!(f, "{}:{}: {}", d.file, d.line, d.message)?,
+                    }
+                    if d.file != 0 {
+                        continue;
+                    }
+                    if let Some(src) = (d.line as usize).checked_sub(1).and_then(|i| lines.get(i)) {
+                        let line = d.origin.as_ref().map_or(d.line, |(_, line)| *line);
+                        writeln!(f, "{:>5} | {}", line, src)?;
+                    }
+                }
+                Ok(())
+            }
+            ShaderError::Link { log } => {
+                write!(
+                    f,
+                    "ERROR::SHADER::PROGRAM::LINKING_FAILED\n{}",
+                    log.trim_end()
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn diagnostic(file: u32, line: u32, message: &str) -> Diagnostic {
+        Diagnostic {
+            file,
+            line,
+            origin: None,
+            message: message.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_nvidia() {
+        let log = "0(12) : error C0000: syntax error, unexpected '}' at token \"}\"\n\
+                   1(3) : warning C7022: unrecognized profile specifier \"foo\"\n";
+        assert_eq!(
+            parse_log(log),
+            [
+                diagnostic(
+                    0,
+                    12,
+                    "error C0000: syntax error, unexpected '}' at token \"}\""
+                ),
+                diagnostic(
+                    1,
+                    3,
+                    "warning C7022: unrecognized profile specifier \"foo\""
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mesa() {
+        let log = "0:12(5): error: `foo' undeclared\n\
+                   0:30(18): warning: `bar' used uninitialized\n";
+        assert_eq!(
+            parse_log(log),
+            [
+                diagnostic(0, 12, "error: `foo' undeclared"),
+                diagnostic(0, 30, "warning: `bar' used uninitialized"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_amd() {
+        let log = "ERROR: 0:12: 'foo' : undeclared identifier \n\
+                   WARNING: 2:7: 'bar' : unused variable\n\
+                   ERROR: 1 compilation errors.  No code generated.\n\n";
+        assert_eq!(
+            parse_log(log),
+            [
+                diagnostic(0, 12, "error: 'foo' : undeclared identifier"),
+                diagnostic(2, 7, "warning: 'bar' : unused variable"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_lines() {
+        assert_eq!(parse_log("Vertex info\n-----------\nlink failed"), []);
+        assert_eq!(parse_log(""), []);
+    }
+}
@@ -1,8 +1,49 @@
+pub mod error;
+pub mod preprocess;
+pub mod reload;
 pub mod set_uniform;
-use anyhow::{bail, Context, Result};
+pub mod uniforms;
+use anyhow::{bail, Result};
+use error::{parse_log, ShaderError};
 use gl::types::*;
+use preprocess::Source;
 use set_uniform::SetUniform;
-use std::{ffi::CString, ptr};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    ffi::CString,
+    ptr,
+};
+use uniforms::{active_uniforms, glsl_type_name, ActiveUniform};
+
+thread_local! {
+    // The program last made current by a `ShaderProgram` in the context current on this
+    // thread, so switching to the program already in use is skipped. None when unknown.
+    // Programs have to be bound through `ShaderProgram::use_program` for it to stay right,
+    // debug builds check that nothing else changed it when a bind is skipped.
+    static CURRENT_PROGRAM: Cell<Option<u32>> = const { Cell::new(None) };
+}
+
+// Forgets which program is current, for after another context is made current on this
+// thread or a program was bound with `gl::UseProgram` directly
+pub fn forget_current_program() {
+    CURRENT_PROGRAM.set(None);
+}
+
+fn use_program_id(id: u32) {
+    let current = CURRENT_PROGRAM.replace(Some(id));
+    if current != Some(id) {
+        unsafe { gl::UseProgram(id) }
+    } else if cfg!(debug_assertions) {
+        // only a skipped bind can be wrong, so only those are checked
+        let mut bound = 0;
+        unsafe { gl::GetIntegerv(gl::CURRENT_PROGRAM, &mut bound) };
+        assert_eq!(
+            bound as u32, id,
+            "the current program was changed outside of ShaderProgram, call forget_current_program after gl::UseProgram or switching contexts"
+        );
+    }
+}
 
 pub struct Shader {
     shader: u32,
@@ -10,10 +51,22 @@ pub struct Shader {
 
 pub struct ShaderProgram {
     pub id: u32,
+    uniforms: Vec<ActiveUniform>,
+    // location and type of every uniform element by name, arrays by their base name too,
+    // with the number of elements from there to the end of the array
+    locations: HashMap<String, (i32, GLenum, usize)>,
+    // names already warned about, so a bad name in the render loop is reported once
+    warned: RefCell<HashSet<String>>,
+    // warnings the caller hasn't taken yet
+    warnings: RefCell<Vec<String>>,
 }
 
 impl Drop for ShaderProgram {
     fn drop(&mut self) {
+        // a new program may get the same id
+        if CURRENT_PROGRAM.get() == Some(self.id) {
+            forget_current_program();
+        }
         unsafe {
             gl::DeleteProgram(self.id);
         }
@@ -22,54 +75,89 @@ impl Drop for ShaderProgram {
 
 impl ShaderProgram {
     pub fn use_program(&self) {
-        unsafe { gl::UseProgram(self.id) }
+        use_program_id(self.id);
     }
 
-    unsafe fn get_uniform(&self, name: &str) -> Result<i32> {
-        Ok(gl::GetUniformLocation(
-            self.id,
-            CString::new(name.as_bytes())?.as_ptr(),
-        ))
+    // the uniforms the linker kept
+    pub fn active_uniforms(&self) -> &[ActiveUniform] {
+        &self.uniforms
     }
 
-    fn set_uniform_dyn(&self, name: &str, data: &dyn SetUniform) -> Result<()> {
-        self.use_program();
-        if !data.has_next() {
-            unsafe {
-                let uid = self.get_uniform(name)?;
-                // if uid < 0 {
-                //     println!("Invalid UID {} for name {}", uid, name);
-                // }
-                data.set_uniform(uid);
-            }
-            return Ok(());
+    // Warnings about uniforms set by names the program doesn't have, each name once, for the
+    // caller to report
+    pub fn take_warnings(&self) -> Vec<String> {
+        self.warnings.take()
+    }
+
+    // queues a warning the first time it is given for `name`
+    fn warn_once(&self, name: &str, warning: impl FnOnce() -> String) {
+        if self.warned.borrow_mut().insert(name.to_owned()) {
+            self.warnings.borrow_mut().push(warning());
         }
-        for (name, d) in data.name_data_list(name) {
-            self.set_uniform_dyn(&name, d)?;
+    }
+
+    fn uniform_location(&self, name: &str, data: &dyn SetUniform) -> Result<Option<i32>> {
+        let Some(&(location, gl_type, _)) = self.locations.get(name) else {
+            self.warn_once(name, || {
+                format!(
+                    "program {}: uniform {name} is not used by the program or is in a uniform block",
+                    self.id
+                )
+            });
+            return Ok(None);
+        };
+        if !data.accepts(gl_type) {
+            bail!(
+                "uniform {name} is a {} in the shader and can't be set from {:?}",
+                glsl_type_name(gl_type),
+                data
+            );
         }
-        return Ok(());
+        Ok(Some(location))
     }
 
-    pub fn set_uniform<T: SetUniform>(&self, name: &str, data: T) -> Result<()> {
+    fn set_uniform_dyn(&self, name: &str, data: &dyn SetUniform) -> Result<()> {
         self.use_program();
         if !data.has_next() {
-            unsafe {
-                let uid = self.get_uniform(name)?;
-                // if uid < 0 {
-                //     println!("Invalid UID {} for name {}", uid, name);
-                // }
-                // println!("setting name {name}");
-                data.set_uniform(uid);
+            if let Some(location) = self.uniform_location(name, data)? {
+                unsafe {
+                    data.set_uniform(location);
+                }
             }
             return Ok(());
         }
+        // arrays of values GL reads straight from memory go up in one call
+        if let Some(&(location, gl_type, len)) = self.locations.get(name) {
+            if data.accepts(gl_type) {
+                if let Some(given) = unsafe { data.set_uniform_contiguous(location, len) } {
+                    if given > len {
+                        self.warn_once(name, || {
+                            format!(
+                                "program {}: uniform {name} has {len} elements, the {} after them were not set",
+                                self.id,
+                                given - len
+                            )
+                        });
+                    }
+                    return Ok(());
+                }
+            }
+        }
         for (name, d) in data.name_data_list(name) {
             self.set_uniform_dyn(&name, d)?;
         }
         return Ok(());
     }
 
+    pub fn set_uniform<T: SetUniform>(&self, name: &str, data: T) -> Result<()> {
+        self.set_uniform_dyn(name, &data)
+    }
+
     pub fn new<const N: usize>(shaders: [Shader; N]) -> Result<Self> {
+        Self::new_shallow(&shaders)
+    }
+
+    pub fn new_shallow(shaders: &[Shader]) -> Result<Self> {
         let id;
         unsafe {
             id = gl::CreateProgram();
@@ -80,26 +168,68 @@ impl ShaderProgram {
                 gl::AttachShader(id, shader.shader);
             }
             gl::LinkProgram(id);
+
+            let mut success = gl::FALSE as GLint;
+            gl::GetProgramiv(id, gl::LINK_STATUS, &mut success);
+            if success != gl::TRUE as GLint {
+                let log = info_log(id, gl::GetProgramiv, gl::GetProgramInfoLog);
+                gl::DeleteProgram(id);
+                return Err(ShaderError::Link { log }.into());
+            }
         }
-        Ok(Self { id })
-    }
 
-    pub fn new_shallow(shaders: &[Shader]) -> Result<Self> {
-        let id;
-        unsafe {
-            id = gl::CreateProgram();
-            if id == 0 {
-                bail!("Couldn't Create Shader Program")
+        let uniforms = active_uniforms(id);
+        let mut locations = HashMap::new();
+        for uniform in uniforms.iter() {
+            let names = uniform.element_names();
+            let len = names.len();
+            for (i, name) in names.into_iter().enumerate() {
+                let location =
+                    unsafe { gl::GetUniformLocation(id, CString::new(name.as_bytes())?.as_ptr()) };
+                // members of uniform blocks have no location, they are set through a buffer
+                if location >= 0 {
+                    locations.insert(name, (location, uniform.gl_type, len - i));
+                }
             }
-            for shader in shaders.iter() {
-                gl::AttachShader(id, shader.shader);
+            // an array can also be set by its name without an index, starting at element 0
+            if let Some(base) = uniform.name.strip_suffix("[0]") {
+                if let Some(&first) = locations.get(&uniform.name) {
+                    locations.insert(base.to_owned(), first);
+                }
             }
-            gl::LinkProgram(id);
         }
-        Ok(Self { id })
+        Ok(Self {
+            id,
+            uniforms,
+            locations,
+            warned: RefCell::new(HashSet::new()),
+            warnings: RefCell::new(vec![]),
+        })
     }
 }
 
+// The info log of a shader or a program, read with the getters for that kind of object
+unsafe fn info_log(
+    object: u32,
+    get_iv: unsafe fn(GLuint, GLenum, *mut GLint),
+    get_info_log: unsafe fn(GLuint, GLsizei, *mut GLsizei, *mut GLchar),
+) -> String {
+    let mut len = 0;
+    get_iv(object, gl::INFO_LOG_LENGTH, &mut len);
+    let mut info_log = vec![0u8; len.max(1) as usize];
+    get_info_log(
+        object,
+        info_log.len() as GLsizei,
+        ptr::null_mut(),
+        info_log.as_mut_ptr() as *mut GLchar,
+    );
+    let end = info_log
+        .iter()
+        .position(|&c| c == 0)
+        .unwrap_or(info_log.len());
+    String::from_utf8_lossy(&info_log[..end]).into_owned()
+}
+
 impl Drop for Shader {
     fn drop(&mut self) {
         unsafe {
@@ -121,26 +251,46 @@ impl Shader {
             gl::CompileShader(shader);
 
             let mut success = gl::FALSE as GLint;
-            let mut info_log: Vec<u8> = Vec::with_capacity(1024);
-            info_log.set_len(1024 - 1); // subtract 1 to skip the trailing null character
             gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
             if success != gl::TRUE as GLint {
-                gl::GetShaderInfoLog(
-                    shader,
-                    1024,
-                    ptr::null_mut(),
-                    info_log.as_mut_ptr() as *mut GLchar,
-                );
-                let info_log = info_log
-                    .split(|&c| c == 0)
-                    .next()
-                    .context("Malformed Error message")?;
-                bail!(
-                    "ERROR::SHADER::VERTEX::COMPILATION_FAILED\n{}",
-                    std::str::from_utf8(info_log)?
-                );
+                let log = info_log(shader, gl::GetShaderiv, gl::GetShaderInfoLog);
+                gl::DeleteShader(shader);
+                return Err(ShaderError::Compile {
+                    stage: shader_type,
+                    diagnostics: parse_log(&log),
+                    log,
+                    source: shader_str.to_owned(),
+                }
+                .into());
             }
         }
         Ok(Self { shader })
     }
+
+    // Compiles preprocessed source, pointing compile errors back at the files they came from
+    pub fn from_source(source: &Source, shader_type: GLenum) -> Result<Self> {
+        Self::new(&source.text, shader_type).map_err(|e| match e.downcast::<ShaderError>() {
+            Ok(ShaderError::Compile {
+                stage,
+                log,
+                mut diagnostics,
+                source: text,
+            }) => {
+                for d in diagnostics.iter_mut() {
+                    d.origin = source
+                        .origin(d.line)
+                        .map(|(path, line)| (path.to_path_buf(), line));
+                }
+                ShaderError::Compile {
+                    stage,
+                    log,
+                    diagnostics,
+                    source: text,
+                }
+                .into()
+            }
+            Ok(e) => e.into(),
+            Err(e) => e,
+        })
+    }
 }
@@ -0,0 +1,314 @@
+use anyhow::{bail, Context, Result};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+// Shader source after preprocessing, remembering which file and line every line came from
+#[derive(Debug, Clone, Default)]
+pub struct Source {
+    pub text: String,
+    // the files read, the file given first then its includes
+    pub files: Vec<PathBuf>,
+    // file index and line of each line of `text`, None for injected defines
+    lines: Vec<Option<(usize, u32)>>,
+}
+
+impl Source {
+    // the file and line a line of the preprocessed text came from, lines counted from 1
+    pub fn origin(&self, line: u32) -> Option<(&Path, u32)> {
+        let (file, line) = (*self.lines.get((line as usize).checked_sub(1)?)?)?;
+        Some((&self.files[file], line))
+    }
+
+    fn push_line(&mut self, line: &str, origin: Option<(usize, u32)>) {
+        self.text.push_str(line);
+        self.text.push('\n');
+        self.lines.push(origin);
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+// The code of `line` outside of comments. `in_comment` says whether the line starts inside a
+// /* comment, and is left saying whether the next one does.
+fn strip_comments(line: &str, in_comment: &mut bool) -> String {
+    let mut code = String::new();
+    let mut rest = line;
+    loop {
+        if *in_comment {
+            let Some(end) = rest.find("*/") else {
+                return code;
+            };
+            rest = &rest[end + 2..];
+            *in_comment = false;
+        }
+        match (rest.find("//"), rest.find("/*")) {
+            (Some(line_comment), block) if block.is_none_or(|b| line_comment < b) => {
+                code.push_str(&rest[..line_comment]);
+                return code;
+            }
+            (_, Some(block)) => {
+                code.push_str(&rest[..block]);
+                code.push(' ');
+                rest = &rest[block + 2..];
+                *in_comment = true;
+            }
+            _ => {
+                code.push_str(rest);
+                return code;
+            }
+        }
+    }
+}
+
+// Runs before compiling, resolving `#include "file"` relative to the including file and
+// adding `#define`s from the host after the `#version` line, or at the top without one.
+// Every file is included once however many times it is asked for, so shared files need no
+// include guards of their own, and a file can't be included twice on purpose. Includes in
+// comments are skipped, but conditionals aren't evaluated: an include inside `#if 0` is
+// still read.
+#[derive(Debug, Clone, Default)]
+pub struct Preprocessor {
+    defines: Vec<(String, String)>,
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn define(mut self, name: &str, value: impl ToString) -> Self {
+        self.defines.push((name.to_owned(), value.to_string()));
+        self
+    }
+
+    pub fn process_file(&self, path: impl AsRef<Path>) -> Result<Source> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("reading shader {}", path.display()))?;
+        self.process_str(path, &text)
+    }
+
+    // Preprocesses `text` as if it were read from `path`, for sources embedded with
+    // `include_str!`. Includes are still read from disk relative to `path`.
+    pub fn process_str(&self, path: impl AsRef<Path>, text: &str) -> Result<Source> {
+        let mut source = Source {
+            files: vec![path.as_ref().to_path_buf()],
+            ..Source::default()
+        };
+        let lines: Vec<&str> = text.lines().collect();
+        // only comments and blank lines may come before #version
+        let mut in_comment = false;
+        let header = lines
+            .iter()
+            .position(|line| !strip_comments(line, &mut in_comment).trim().is_empty())
+            .filter(|&i| lines[i].trim_start().starts_with("#version"))
+            .map_or(0, |version| version + 1);
+        if header == 0 {
+            in_comment = false;
+        }
+        for (i, line) in lines[..header].iter().enumerate() {
+            source.push_line(line, Some((0, i as u32 + 1)));
+        }
+        for (name, value) in self.defines.iter() {
+            source.push_line(&format!("#define {name} {value}"), None);
+        }
+        let rest = lines.into_iter().enumerate().skip(header);
+        self.include(&mut source, 0, rest, in_comment)?;
+        Ok(source)
+    }
+
+    fn include<'a>(
+        &self,
+        source: &mut Source,
+        file: usize,
+        lines: impl Iterator<Item = (usize, &'a str)>,
+        mut in_comment: bool,
+    ) -> Result<()> {
+        for (i, line) in lines {
+            let line_number = i as u32 + 1;
+            let code = strip_comments(line, &mut in_comment);
+            let Some(rest) = code.trim_start().strip_prefix("#include") else {
+                source.push_line(line, Some((file, line_number)));
+                continue;
+            };
+            let here = format!("{}:{}", source.files[file].display(), line_number);
+            let rest = rest.trim();
+            let Some(name) = rest.strip_prefix('"').and_then(|r| r.strip_suffix('"')) else {
+                bail!("{here}: expected #include \"file\", found {rest}");
+            };
+            let path = source.files[file]
+                .parent()
+                .unwrap_or(Path::new(""))
+                .join(name);
+            if source.files.iter().any(|f| same_file(f, &path)) {
+                continue;
+            }
+            let text = fs::read_to_string(&path)
+                .with_context(|| format!("{here}: including {}", path.display()))?;
+            source.files.push(path);
+            let included = source.files.len() - 1;
+            self.include(source, included, text.lines().enumerate(), false)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // a fresh directory holding `files`
+    fn dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "learn-opengl-preprocess-{name}-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        for (file, text) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        dir
+    }
+
+    fn lines(source: &Source) -> Vec<&str> {
+        source.text.lines().collect()
+    }
+
+    #[test]
+    fn test_defines_after_version() {
+        let text = "// header\n\n/* a\n   block */\n#version 330 core\nvoid main() {}\n";
+        let source = Preprocessor::new()
+            .define("LIGHT_MAX", 4)
+            .define("CHECK_LIGHTS", "CHECK(0)")
+            .process_str("frag.glsl", text)
+            .unwrap();
+        assert_eq!(
+            lines(&source),
+            [
+                "// header",
+                "",
+                "/* a",
+                "   block */",
+                "#version 330 core",
+                "#define LIGHT_MAX 4",
+                "#define CHECK_LIGHTS CHECK(0)",
+                "void main() {}",
+            ]
+        );
+        let path = Path::new("frag.glsl");
+        assert_eq!(source.origin(5), Some((path, 5)));
+        assert_eq!(source.origin(6), None);
+        assert_eq!(source.origin(7), None);
+        assert_eq!(source.origin(8), Some((path, 6)));
+        assert_eq!(source.origin(0), None);
+        assert_eq!(source.origin(9), None);
+    }
+
+    #[test]
+    fn test_defines_without_version() {
+        let source = Preprocessor::new()
+            .define("A", 1)
+            .process_str("frag.glsl", "float a = A;\n#version 330 core\n")
+            .unwrap();
+        assert_eq!(
+            lines(&source),
+            ["#define A 1", "float a = A;", "#version 330 core"]
+        );
+        assert_eq!(source.origin(2), Some((Path::new("frag.glsl"), 1)));
+    }
+
+    #[test]
+    fn test_includes() {
+        let dir = dir(
+            "includes",
+            &[
+                ("light.glsl", "struct Light { vec3 p; };\n"),
+                (
+                    "lib/shading.glsl",
+                    "#include \"../light.glsl\"\nvec3 shade(Light l);\n",
+                ),
+            ],
+        );
+        let text = "#version 330 core\n\
+                    #include \"light.glsl\"\n  \
+                    #include \"lib/shading.glsl\" // shade()\n\
+                    void main() {}\n";
+        let source = Preprocessor::new()
+            .process_str(dir.join("frag.glsl"), text)
+            .unwrap();
+        // light.glsl is only read the first time
+        assert_eq!(
+            lines(&source),
+            [
+                "#version 330 core",
+                "struct Light { vec3 p; };",
+                "vec3 shade(Light l);",
+                "void main() {}",
+            ]
+        );
+        assert_eq!(
+            source.files,
+            [
+                dir.join("frag.glsl"),
+                dir.join("light.glsl"),
+                dir.join("lib/shading.glsl"),
+            ]
+        );
+        assert_eq!(
+            source.origin(2),
+            Some((dir.join("light.glsl").as_path(), 1))
+        );
+        assert_eq!(
+            source.origin(3),
+            Some((dir.join("lib/shading.glsl").as_path(), 2))
+        );
+        assert_eq!(source.origin(4), Some((dir.join("frag.glsl").as_path(), 4)));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_includes_in_comments() {
+        let text = "// #include \"missing.glsl\"\n\
+                    /*\n\
+                    #include \"missing.glsl\"\n\
+                    */ float a;\n";
+        let source = Preprocessor::new().process_str("frag.glsl", text).unwrap();
+        assert_eq!(source.text, text);
+        assert_eq!(source.files.len(), 1);
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = Preprocessor::new()
+            .process_str("frag.glsl", "#version 330 core\n#include <light.glsl>\n")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "frag.glsl:2: expected #include \"file\", found <light.glsl>"
+        );
+        let dir = dir("errors", &[]);
+        let err = Preprocessor::new()
+            .process_str(dir.join("frag.glsl"), "\n#include \"missing.glsl\"\n")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "{}:2: including {}",
+                dir.join("frag.glsl").display(),
+                dir.join("missing.glsl").display()
+            )
+        );
+        assert!(Preprocessor::new()
+            .process_file(dir.join("missing.glsl"))
+            .is_err());
+    }
+}
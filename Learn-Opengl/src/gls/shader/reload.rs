@@ -0,0 +1,114 @@
+use super::{preprocess::Preprocessor, uniforms::copy_uniforms, Shader, ShaderProgram};
+use anyhow::{Context, Result};
+use gl::types::*;
+use std::{
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+// files with when they were last changed
+type Watched = Vec<(PathBuf, Option<SystemTime>)>;
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// A ShaderProgram built from files on disk that rebuilds itself when they change. Derefs to
+// the program it is currently using, so uniforms are set on it like on any other program.
+pub struct ReloadingProgram {
+    program: ShaderProgram,
+    stages: Vec<(PathBuf, GLenum)>,
+    preprocessor: Preprocessor,
+    // every file the program was built from, includes too, with when it was last changed
+    watched: Watched,
+}
+
+impl ReloadingProgram {
+    pub fn new<P: AsRef<Path>>(stages: &[(P, GLenum)]) -> Result<Self> {
+        Self::with_preprocessor(stages, Preprocessor::new())
+    }
+
+    pub fn with_preprocessor<P: AsRef<Path>>(
+        stages: &[(P, GLenum)],
+        preprocessor: Preprocessor,
+    ) -> Result<Self> {
+        let stages: Vec<(PathBuf, GLenum)> = stages
+            .iter()
+            .map(|(path, shader_type)| (path.as_ref().to_path_buf(), *shader_type))
+            .collect();
+        let (program, watched) = Self::build(&stages, &preprocessor)?;
+        Ok(Self {
+            program,
+            stages,
+            preprocessor,
+            watched,
+        })
+    }
+
+    fn build(
+        stages: &[(PathBuf, GLenum)],
+        preprocessor: &Preprocessor,
+    ) -> Result<(ShaderProgram, Watched)> {
+        let mut watched = vec![];
+        let mut shaders = vec![];
+        for (path, shader_type) in stages.iter() {
+            let source = preprocessor.process_file(path)?;
+            for file in source.files.iter() {
+                watched.push((file.clone(), modified(file)));
+            }
+            shaders.push(
+                Shader::from_source(&source, *shader_type)
+                    .with_context(|| format!("compiling shader {}", path.display()))?,
+            );
+        }
+        Ok((ShaderProgram::new_shallow(&shaders)?, watched))
+    }
+
+    // Rebuilds the program if one of its files changed since the last call, cheap enough to
+    // call every frame. The uniforms set on the old program are copied to the new one.
+    // Returns whether the program was replaced, if the new sources fail to build the error
+    // is returned once and the last good program stays in use.
+    pub fn reload(&mut self) -> Result<bool> {
+        let mut changed = false;
+        for (path, last_modified) in self.watched.iter_mut() {
+            let modified = modified(path);
+            if modified != *last_modified {
+                *last_modified = modified;
+                changed = true;
+            }
+        }
+        if !changed {
+            return Ok(false);
+        }
+        let (program, watched) = Self::build(&self.stages, &self.preprocessor)?;
+        copy_uniforms(self.program.id, program.id)?;
+        self.program = program;
+        self.watched = watched;
+        Ok(true)
+    }
+
+    // reload for the render loop, printing build errors instead of returning them, and the
+    // program's warnings about unknown uniforms
+    pub fn update(&mut self) -> bool {
+        for warning in self.program.take_warnings() {
+            eprintln!("warning: {warning}");
+        }
+        match self.reload() {
+            Ok(reloaded) => reloaded,
+            Err(e) => {
+                eprintln!("{e:?}");
+                false
+            }
+        }
+    }
+}
+
+impl Deref for ReloadingProgram {
+    type Target = ShaderProgram;
+
+    fn deref(&self) -> &ShaderProgram {
+        &self.program
+    }
+}
@@ -1,9 +1,15 @@
 use std::fmt::Debug;
 
+use super::uniforms::is_sampler;
+
 use cgmath::prelude::*;
 use cgmath::Matrix4;
 use cgmath::Vector3;
 use cgmath::Vector4;
+use gl::types::GLsizei;
+
+// `#[derive(SetUniform)]` for structs, see learn-opengl-derive
+pub use learn_opengl_derive::SetUniform;
 
 pub trait SetUniform: Debug {
     unsafe fn set_uniform(&self, id: i32);
@@ -11,62 +17,181 @@ pub trait SetUniform: Debug {
     fn has_next(&self) -> bool {
         false
     }
+    // whether the value can be set on a uniform of the GL type `gl_type`
+    fn accepts(&self, _gl_type: u32) -> bool {
+        true
+    }
+    /// Sets `values` as the elements of an array uniform from `id` on with one glUniform*v
+    /// call, for types GL can read straight from the slice. False for types that have to be
+    /// set element by element.
+    ///
+    /// # Safety
+    ///
+    /// A GL context has to be current with the program owning the uniform in use, `id` has
+    /// to be a valid location in it and the array from `id` on must have room for
+    /// `values.len()` elements.
+    unsafe fn set_uniform_array(_values: &[Self], _id: i32) -> bool
+    where
+        Self: Sized,
+    {
+        false
+    }
+    /// For arrays, sets at most `max_len` elements from `id` on in one call with
+    /// `set_uniform_array`, returning how many elements the array has. None when it has to
+    /// be set element by element.
+    ///
+    /// # Safety
+    ///
+    /// A GL context has to be current with the program owning the uniform in use, `id` has
+    /// to be a valid location in it and `max_len` must not be more than the elements of the
+    /// uniform array from `id` on.
+    unsafe fn set_uniform_contiguous(&self, _id: i32, _max_len: usize) -> Option<usize> {
+        None
+    }
 }
 
-impl SetUniform for bool {
+impl<T: SetUniform + ?Sized> SetUniform for &T {
     unsafe fn set_uniform(&self, id: i32) {
-        gl::Uniform1i(id, *self as i32);
+        (**self).set_uniform(id);
     }
     fn name_data_list<'a>(&'a self, name: &str) -> Vec<(String, &'a dyn SetUniform)> {
-        vec![(name.to_owned(), self)]
+        (**self).name_data_list(name)
+    }
+    fn has_next(&self) -> bool {
+        (**self).has_next()
+    }
+    fn accepts(&self, gl_type: u32) -> bool {
+        (**self).accepts(gl_type)
+    }
+    unsafe fn set_uniform_contiguous(&self, id: i32, max_len: usize) -> Option<usize> {
+        (**self).set_uniform_contiguous(id, max_len)
     }
 }
 
-impl SetUniform for i32 {
-    unsafe fn set_uniform(&self, id: i32) {
-        gl::Uniform1i(id, *self);
+// Arrays of matrices, vectors and scalars go up in one call, arrays of anything else are
+// set element by element, as "name[0]", "name[1]", ...
+impl<T: SetUniform> SetUniform for [T] {
+    unsafe fn set_uniform(&self, _id: i32) {
+        panic!("Can't set an array uniform directly");
     }
     fn name_data_list<'a>(&'a self, name: &str) -> Vec<(String, &'a dyn SetUniform)> {
-        vec![(name.to_owned(), self)]
+        self.iter()
+            .enumerate()
+            .map(|(i, element)| (format!("{name}[{i}]"), element as &dyn SetUniform))
+            .collect()
+    }
+    fn has_next(&self) -> bool {
+        true
+    }
+    fn accepts(&self, gl_type: u32) -> bool {
+        self.first().is_none_or(|element| element.accepts(gl_type))
+    }
+    unsafe fn set_uniform_contiguous(&self, id: i32, max_len: usize) -> Option<usize> {
+        T::set_uniform_array(&self[..self.len().min(max_len)], id).then_some(self.len())
     }
 }
 
-impl SetUniform for f32 {
+impl<T: SetUniform, const N: usize> SetUniform for [T; N] {
+    unsafe fn set_uniform(&self, _id: i32) {
+        panic!("Can't set an array uniform directly");
+    }
+    fn name_data_list<'a>(&'a self, name: &str) -> Vec<(String, &'a dyn SetUniform)> {
+        self.as_slice().name_data_list(name)
+    }
+    fn has_next(&self) -> bool {
+        true
+    }
+    fn accepts(&self, gl_type: u32) -> bool {
+        self.as_slice().accepts(gl_type)
+    }
+    unsafe fn set_uniform_contiguous(&self, id: i32, max_len: usize) -> Option<usize> {
+        self.as_slice().set_uniform_contiguous(id, max_len)
+    }
+}
+
+impl<T: SetUniform> SetUniform for Vec<T> {
+    unsafe fn set_uniform(&self, _id: i32) {
+        panic!("Can't set an array uniform directly");
+    }
+    fn name_data_list<'a>(&'a self, name: &str) -> Vec<(String, &'a dyn SetUniform)> {
+        self.as_slice().name_data_list(name)
+    }
+    fn has_next(&self) -> bool {
+        true
+    }
+    fn accepts(&self, gl_type: u32) -> bool {
+        self.as_slice().accepts(gl_type)
+    }
+    unsafe fn set_uniform_contiguous(&self, id: i32, max_len: usize) -> Option<usize> {
+        self.as_slice().set_uniform_contiguous(id, max_len)
+    }
+}
+
+impl SetUniform for bool {
     unsafe fn set_uniform(&self, id: i32) {
-        gl::Uniform1f(id, *self);
+        gl::Uniform1i(id, *self as i32);
     }
     fn name_data_list<'a>(&'a self, name: &str) -> Vec<(String, &'a dyn SetUniform)> {
         vec![(name.to_owned(), self)]
     }
+    fn accepts(&self, gl_type: u32) -> bool {
+        matches!(gl_type, gl::BOOL | gl::INT)
+    }
 }
 
-impl SetUniform for Matrix4<f32> {
+impl SetUniform for i32 {
     unsafe fn set_uniform(&self, id: i32) {
-        gl::UniformMatrix4fv(id, 1, gl::FALSE, self.as_ptr());
+        gl::Uniform1i(id, *self);
     }
     fn name_data_list<'a>(&'a self, name: &str) -> Vec<(String, &'a dyn SetUniform)> {
         vec![(name.to_owned(), self)]
     }
+    fn accepts(&self, gl_type: u32) -> bool {
+        matches!(gl_type, gl::INT | gl::BOOL) || is_sampler(gl_type)
+    }
+    unsafe fn set_uniform_array(values: &[Self], id: i32) -> bool {
+        gl::Uniform1iv(id, values.len() as GLsizei, values.as_ptr());
+        true
+    }
 }
 
-impl SetUniform for Vec<Matrix4<f32>> {
+impl SetUniform for f32 {
     unsafe fn set_uniform(&self, id: i32) {
-        gl::UniformMatrix4fv(id, self.len() as i32, gl::FALSE, self[0].as_ptr());
+        gl::Uniform1f(id, *self);
     }
-
     fn name_data_list<'a>(&'a self, name: &str) -> Vec<(String, &'a dyn SetUniform)> {
         vec![(name.to_owned(), self)]
     }
+    fn accepts(&self, gl_type: u32) -> bool {
+        gl_type == gl::FLOAT
+    }
+    unsafe fn set_uniform_array(values: &[Self], id: i32) -> bool {
+        gl::Uniform1fv(id, values.len() as GLsizei, values.as_ptr());
+        true
+    }
 }
 
-impl SetUniform for Vec<Vector4<f32>> {
+impl SetUniform for Matrix4<f32> {
     unsafe fn set_uniform(&self, id: i32) {
-        gl::Uniform4fv(id, self.len() as i32, self[0].as_ptr());
+        gl::UniformMatrix4fv(id, 1, gl::FALSE, self.as_ptr());
     }
     fn name_data_list<'a>(&'a self, name: &str) -> Vec<(String, &'a dyn SetUniform)> {
         vec![(name.to_owned(), self)]
     }
+    fn accepts(&self, gl_type: u32) -> bool {
+        gl_type == gl::FLOAT_MAT4
+    }
+    unsafe fn set_uniform_array(values: &[Self], id: i32) -> bool {
+        gl::UniformMatrix4fv(
+            id,
+            values.len() as GLsizei,
+            gl::FALSE,
+            values.as_ptr() as *const f32,
+        );
+        true
+    }
 }
+
 impl SetUniform for Vector4<f32> {
     unsafe fn set_uniform(&self, id: i32) {
         gl::Uniform4fv(id, 1, self.as_ptr());
@@ -74,6 +199,13 @@ impl SetUniform for Vector4<f32> {
     fn name_data_list<'a>(&'a self, name: &str) -> Vec<(String, &'a dyn SetUniform)> {
         vec![(name.to_owned(), self)]
     }
+    fn accepts(&self, gl_type: u32) -> bool {
+        gl_type == gl::FLOAT_VEC4
+    }
+    unsafe fn set_uniform_array(values: &[Self], id: i32) -> bool {
+        gl::Uniform4fv(id, values.len() as GLsizei, values.as_ptr() as *const f32);
+        true
+    }
 }
 
 impl SetUniform for Vector3<f32> {
@@ -83,6 +215,13 @@ impl SetUniform for Vector3<f32> {
     fn name_data_list<'a>(&'a self, name: &str) -> Vec<(String, &'a dyn SetUniform)> {
         vec![(name.to_owned(), self)]
     }
+    fn accepts(&self, gl_type: u32) -> bool {
+        gl_type == gl::FLOAT_VEC3
+    }
+    unsafe fn set_uniform_array(values: &[Self], id: i32) -> bool {
+        gl::Uniform3fv(id, values.len() as GLsizei, values.as_ptr() as *const f32);
+        true
+    }
 }
 
 impl SetUniform for (f32, f32, f32) {
@@ -92,4 +231,7 @@ impl SetUniform for (f32, f32, f32) {
     fn name_data_list<'a>(&'a self, name: &str) -> Vec<(String, &'a dyn SetUniform)> {
         vec![(name.to_owned(), self)]
     }
+    fn accepts(&self, gl_type: u32) -> bool {
+        gl_type == gl::FLOAT_VEC3
+    }
 }
@@ -0,0 +1,215 @@
+use anyhow::Result;
+use gl::types::*;
+use std::ffi::CString;
+
+// A uniform the linker kept, as reported by glGetActiveUniform. Arrays are reported once,
+// by the name of their first element with `size` elements.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveUniform {
+    pub name: String,
+    pub size: i32,
+    pub gl_type: GLenum,
+}
+
+impl ActiveUniform {
+    // names of every element of the uniform, "lights[0]", "lights[1]", ... for arrays
+    pub fn element_names(&self) -> Vec<String> {
+        match self.name.strip_suffix("[0]") {
+            Some(base) if self.size > 1 => (0..self.size).map(|i| format!("{base}[{i}]")).collect(),
+            _ => vec![self.name.clone()],
+        }
+    }
+}
+
+pub fn active_uniforms(program: u32) -> Vec<ActiveUniform> {
+    unsafe {
+        let mut count = 0;
+        gl::GetProgramiv(program, gl::ACTIVE_UNIFORMS, &mut count);
+        let mut max_len = 0;
+        gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_len);
+        let mut name = vec![0u8; max_len.max(1) as usize];
+        (0..count.max(0) as u32)
+            .map(|i| {
+                let (mut len, mut size, mut gl_type) = (0, 0, 0);
+                gl::GetActiveUniform(
+                    program,
+                    i,
+                    name.len() as GLsizei,
+                    &mut len,
+                    &mut size,
+                    &mut gl_type,
+                    name.as_mut_ptr() as *mut GLchar,
+                );
+                ActiveUniform {
+                    name: String::from_utf8_lossy(&name[..len as usize]).into_owned(),
+                    size,
+                    gl_type,
+                }
+            })
+            .collect()
+    }
+}
+
+pub fn is_sampler(gl_type: GLenum) -> bool {
+    matches!(
+        gl_type,
+        gl::SAMPLER_1D
+            | gl::SAMPLER_2D
+            | gl::SAMPLER_3D
+            | gl::SAMPLER_CUBE
+            | gl::SAMPLER_2D_SHADOW
+            | gl::SAMPLER_CUBE_SHADOW
+            | gl::SAMPLER_2D_ARRAY
+    )
+}
+
+// the GLSL name of a uniform type, for error messages
+pub fn glsl_type_name(gl_type: GLenum) -> &'static str {
+    match gl_type {
+        gl::FLOAT => "float",
+        gl::FLOAT_VEC2 => "vec2",
+        gl::FLOAT_VEC3 => "vec3",
+        gl::FLOAT_VEC4 => "vec4",
+        gl::FLOAT_MAT2 => "mat2",
+        gl::FLOAT_MAT3 => "mat3",
+        gl::FLOAT_MAT4 => "mat4",
+        gl::INT => "int",
+        gl::INT_VEC2 => "ivec2",
+        gl::INT_VEC3 => "ivec3",
+        gl::INT_VEC4 => "ivec4",
+        gl::UNSIGNED_INT => "uint",
+        gl::UNSIGNED_INT_VEC2 => "uvec2",
+        gl::UNSIGNED_INT_VEC3 => "uvec3",
+        gl::UNSIGNED_INT_VEC4 => "uvec4",
+        gl::BOOL => "bool",
+        gl::BOOL_VEC2 => "bvec2",
+        gl::BOOL_VEC3 => "bvec3",
+        gl::BOOL_VEC4 => "bvec4",
+        gl::SAMPLER_1D => "sampler1D",
+        gl::SAMPLER_2D => "sampler2D",
+        gl::SAMPLER_3D => "sampler3D",
+        gl::SAMPLER_CUBE => "samplerCube",
+        gl::SAMPLER_2D_SHADOW => "sampler2DShadow",
+        gl::SAMPLER_CUBE_SHADOW => "samplerCubeShadow",
+        gl::SAMPLER_2D_ARRAY => "sampler2DArray",
+        _ => "uniform of an unsupported type",
+    }
+}
+
+enum Component {
+    Float,
+    Int,
+    Uint,
+}
+
+// how a uniform type is read and written: component type, components and matrix columns
+fn layout(gl_type: GLenum) -> Option<(Component, i32, i32)> {
+    let layout = match gl_type {
+        gl::FLOAT => (Component::Float, 1, 1),
+        gl::FLOAT_VEC2 => (Component::Float, 2, 1),
+        gl::FLOAT_VEC3 => (Component::Float, 3, 1),
+        gl::FLOAT_VEC4 => (Component::Float, 4, 1),
+        gl::FLOAT_MAT2 => (Component::Float, 2, 2),
+        gl::FLOAT_MAT3 => (Component::Float, 3, 3),
+        gl::FLOAT_MAT4 => (Component::Float, 4, 4),
+        gl::INT | gl::BOOL => (Component::Int, 1, 1),
+        gl::INT_VEC2 | gl::BOOL_VEC2 => (Component::Int, 2, 1),
+        gl::INT_VEC3 | gl::BOOL_VEC3 => (Component::Int, 3, 1),
+        gl::INT_VEC4 | gl::BOOL_VEC4 => (Component::Int, 4, 1),
+        gl::UNSIGNED_INT => (Component::Uint, 1, 1),
+        gl::UNSIGNED_INT_VEC2 => (Component::Uint, 2, 1),
+        gl::UNSIGNED_INT_VEC3 => (Component::Uint, 3, 1),
+        gl::UNSIGNED_INT_VEC4 => (Component::Uint, 4, 1),
+        t if is_sampler(t) => (Component::Int, 1, 1),
+        _ => return None,
+    };
+    Some(layout)
+}
+
+// Copies the value of every uniform `to` shares with `from`, and the binding points of the
+// uniform blocks they share, leaving `to` in use. Uniforms whose type changed between the
+// two programs are left at their defaults.
+pub fn copy_uniforms(from: u32, to: u32) -> Result<()> {
+    unsafe {
+        let to_types: Vec<ActiveUniform> = active_uniforms(to);
+        super::use_program_id(to);
+        for uniform in active_uniforms(from) {
+            let Some((component, n, columns)) = layout(uniform.gl_type) else {
+                continue;
+            };
+            let same_type = to_types
+                .iter()
+                .any(|u| u.name == uniform.name && u.gl_type == uniform.gl_type);
+            if !same_type {
+                continue;
+            }
+            for name in uniform.element_names() {
+                let name = CString::new(name)?;
+                let src = gl::GetUniformLocation(from, name.as_ptr());
+                let dst = gl::GetUniformLocation(to, name.as_ptr());
+                if src < 0 || dst < 0 {
+                    continue;
+                }
+                match component {
+                    Component::Float => {
+                        let mut v = [0f32; 16];
+                        gl::GetUniformfv(from, src, v.as_mut_ptr());
+                        match (n, columns) {
+                            (1, _) => gl::Uniform1fv(dst, 1, v.as_ptr()),
+                            (2, 1) => gl::Uniform2fv(dst, 1, v.as_ptr()),
+                            (3, 1) => gl::Uniform3fv(dst, 1, v.as_ptr()),
+                            (4, 1) => gl::Uniform4fv(dst, 1, v.as_ptr()),
+                            (2, _) => gl::UniformMatrix2fv(dst, 1, gl::FALSE, v.as_ptr()),
+                            (3, _) => gl::UniformMatrix3fv(dst, 1, gl::FALSE, v.as_ptr()),
+                            _ => gl::UniformMatrix4fv(dst, 1, gl::FALSE, v.as_ptr()),
+                        }
+                    }
+                    Component::Int => {
+                        let mut v = [0i32; 4];
+                        gl::GetUniformiv(from, src, v.as_mut_ptr());
+                        match n {
+                            1 => gl::Uniform1iv(dst, 1, v.as_ptr()),
+                            2 => gl::Uniform2iv(dst, 1, v.as_ptr()),
+                            3 => gl::Uniform3iv(dst, 1, v.as_ptr()),
+                            _ => gl::Uniform4iv(dst, 1, v.as_ptr()),
+                        }
+                    }
+                    Component::Uint => {
+                        let mut v = [0u32; 4];
+                        gl::GetUniformuiv(from, src, v.as_mut_ptr());
+                        match n {
+                            1 => gl::Uniform1uiv(dst, 1, v.as_ptr()),
+                            2 => gl::Uniform2uiv(dst, 1, v.as_ptr()),
+                            3 => gl::Uniform3uiv(dst, 1, v.as_ptr()),
+                            _ => gl::Uniform4uiv(dst, 1, v.as_ptr()),
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut blocks = 0;
+        gl::GetProgramiv(from, gl::ACTIVE_UNIFORM_BLOCKS, &mut blocks);
+        let mut max_len = 0;
+        gl::GetProgramiv(from, gl::ACTIVE_UNIFORM_BLOCK_MAX_NAME_LENGTH, &mut max_len);
+        let mut name = vec![0u8; max_len.max(1) as usize];
+        for i in 0..blocks.max(0) as u32 {
+            let mut len = 0;
+            gl::GetActiveUniformBlockName(
+                from,
+                i,
+                name.len() as GLsizei,
+                &mut len,
+                name.as_mut_ptr() as *mut GLchar,
+            );
+            let mut binding = 0;
+            gl::GetActiveUniformBlockiv(from, i, gl::UNIFORM_BLOCK_BINDING, &mut binding);
+            let block = CString::new(&name[..len as usize])?;
+            let index = gl::GetUniformBlockIndex(to, block.as_ptr());
+            if index != gl::INVALID_INDEX {
+                gl::UniformBlockBinding(to, index, binding as u32);
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,8 @@
+// lets code generated by learn-opengl-derive name this crate from inside it too
+extern crate self as learn_opengl;
+
 pub mod camera;
 pub mod gls;
 pub mod lights;
+pub mod model;
 pub mod window;
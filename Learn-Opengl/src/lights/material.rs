@@ -1,6 +1,6 @@
 use crate::gls::shader::set_uniform::SetUniform;
 
-#[derive(Debug)]
+#[derive(Debug, SetUniform)]
 pub struct Material {
     diffuse: i32,
     specular: i32,
@@ -16,21 +16,3 @@ impl Material {
         }
     }
 }
-
-impl SetUniform for Material {
-    unsafe fn set_uniform(&self, id: i32) {
-        panic!("can't set nested types");
-    }
-
-    fn name_data_list<'a>(&'a self, name: &str) -> Vec<(String, &'a dyn SetUniform)> {
-        vec![
-            (format!("{}.diffuse", name), &self.diffuse),
-            (format!("{}.specular", name), &self.specular),
-            (format!("{}.shininess", name), &self.shininess),
-        ]
-    }
-
-    fn has_next(&self) -> bool {
-        true
-    }
-}
@@ -2,11 +2,13 @@ use anyhow::Result;
 use cgmath::{perspective, vec3, Deg, EuclideanSpace, Matrix4, Point3, Vector3};
 
 use crate::gls::{
-    buffers::{framebuffer::FrameBuffer, texture::CubeMap},
+    buffers::{framebuffer::FrameBuffer, texture::CubeMap, ubo::Std140},
     shader::set_uniform::SetUniform,
 };
 
-#[derive(Debug)]
+// in a uniform block the shadow map is left out, it is a sampler so it has to be set on its
+// own
+#[derive(Debug, SetUniform, Std140)]
 pub struct PointLight {
     position: Vector3<f32>,
 
@@ -20,9 +22,15 @@ pub struct PointLight {
     diffuse: Vector3<f32>,
     specular: Vector3<f32>,
 
+    #[uniform(rename = "depthMap")]
+    #[std140(skip)]
     depth_map: i32,
 
+    #[uniform(skip)]
+    #[std140(skip)]
     fbo: FrameBuffer,
+    #[uniform(skip)]
+    #[std140(skip)]
     cube_map: CubeMap,
 }
 
@@ -65,6 +73,10 @@ impl PointLight {
     pub fn get_cube_map(&self) -> &CubeMap {
         &self.cube_map
     }
+    pub fn get_depth_map(&self) -> i32 {
+        self.depth_map
+    }
+
     pub fn get_pos(&self) -> Vector3<f32> {
         self.position.clone()
     }
@@ -118,70 +130,6 @@ impl PointLight {
         ];
     }
 }
-impl SetUniform for &Vec<PointLight> {
-    unsafe fn set_uniform(&self, _id: i32) {
-        panic!("Can't set a struct uniform directly");
-    }
-
-    fn name_data_list<'a>(&'a self, name: &str) -> Vec<(String, &'a dyn SetUniform)> {
-        (0..10)
-            .flat_map(|i| {
-                let name = format!("{}[{}]", name, i);
-                self[i.min(self.len() - 1)].name_data_list(name.as_str())
-            })
-            .collect::<Vec<(String, &dyn SetUniform)>>()
-    }
-
-    fn has_next(&self) -> bool {
-        true
-    }
-}
-
-impl SetUniform for PointLight {
-    unsafe fn set_uniform(&self, _id: i32) {
-        panic!("Can't set a struct uniform directly");
-    }
-    fn has_next(&self) -> bool {
-        true
-    }
-
-    fn name_data_list<'a>(&'a self, name: &str) -> Vec<(String, &'a dyn SetUniform)> {
-        vec![
-            (format!("{name}.position"), &self.position),
-            (format!("{name}.color"), &self.color),
-            (format!("{name}.constant"), &self.constant),
-            (format!("{name}.linear"), &self.linear),
-            (format!("{name}.quadratic"), &self.quadratic),
-            (format!("{name}.ambient"), &self.ambient),
-            (format!("{name}.diffuse"), &self.diffuse),
-            (format!("{name}.specular"), &self.specular),
-            (format!("{name}.depthMap"), &self.depth_map),
-        ]
-    }
-}
-
-impl SetUniform for &PointLight {
-    unsafe fn set_uniform(&self, _id: i32) {
-        panic!("Can't set a struct uniform directly");
-    }
-    fn has_next(&self) -> bool {
-        true
-    }
-
-    fn name_data_list<'a>(&'a self, name: &str) -> Vec<(String, &'a dyn SetUniform)> {
-        vec![
-            (format!("{name}.position"), &self.position),
-            (format!("{name}.color"), &self.color),
-            (format!("{name}.constant"), &self.constant),
-            (format!("{name}.linear"), &self.linear),
-            (format!("{name}.quadratic"), &self.quadratic),
-            (format!("{name}.ambient"), &self.ambient),
-            (format!("{name}.diffuse"), &self.diffuse),
-            (format!("{name}.specular"), &self.specular),
-            (format!("{name}.depthMap"), &self.depth_map),
-        ]
-    }
-}
 
 #[derive(Default, Clone)]
 pub struct PointLightBuilder {
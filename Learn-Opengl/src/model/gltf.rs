@@ -0,0 +1,386 @@
+use super::{
+    color_texture, compute_normals, index_out_of_range, rgba_texture, MeshData, Model,
+    ModelMaterial, ModelVertex,
+};
+use crate::gls::buffers::texture::Texture2D;
+use ::gltf::{buffer, image::Source, mesh::Mode, texture::WrappingMode, Gltf, Node, Primitive};
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use cgmath::{InnerSpace, Matrix, Matrix3, Matrix4, SquareMatrix, Transform, Vector3};
+use gl::types::GLenum;
+use std::{collections::HashMap, fs, path::Path, rc::Rc};
+
+// Loads the meshes of the default scene of a .gltf or .glb file, one mesh per primitive,
+// with node transforms baked into the vertices. Metallic-roughness materials are turned
+// into diffuse and specular textures: the base color is the diffuse, and smoother surfaces
+// get a brighter and sharper highlight.
+pub(super) fn load(path: &Path) -> Result<Model> {
+    let gltf = Gltf::open(path).with_context(|| format!("reading glTF {}", path.display()))?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    let buffers = gltf
+        .buffers()
+        .map(|b| load_buffer(&b, base, gltf.blob.as_deref()))
+        .collect::<Result<Vec<_>>>()
+        .with_context(|| format!("loading buffers of {}", path.display()))?;
+    let mut loader = Loader {
+        base,
+        buffers,
+        textures: HashMap::new(),
+        materials: HashMap::new(),
+        model: Model::default(),
+    };
+    let scene = gltf
+        .document
+        .default_scene()
+        .or_else(|| gltf.document.scenes().next())
+        .ok_or_else(|| anyhow!("{} contains no scene", path.display()))?;
+    for node in scene.nodes() {
+        loader.node(&node, Matrix4::identity())?;
+    }
+    Ok(loader.model)
+}
+
+fn decode_data_uri(uri: &str) -> Option<Result<Vec<u8>>> {
+    let (_, data) = uri.strip_prefix("data:")?.split_once(";base64,")?;
+    Some(STANDARD.decode(data).context("decoding data uri"))
+}
+
+// relative uris may escape characters like spaces as %20
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// the bytes of a data uri or of a file relative to the glTF file
+fn read_uri(uri: &str, base: &Path) -> Result<Vec<u8>> {
+    match decode_data_uri(uri) {
+        Some(data) => data,
+        None => {
+            let path = base.join(percent_decode(uri));
+            fs::read(&path).with_context(|| format!("reading {}", path.display()))
+        }
+    }
+}
+
+fn load_buffer(buffer: &buffer::Buffer, base: &Path, blob: Option<&[u8]>) -> Result<Vec<u8>> {
+    let data = match buffer.source() {
+        buffer::Source::Bin => blob
+            .ok_or_else(|| anyhow!("buffer {} refers to a missing binary chunk", buffer.index()))?
+            .to_vec(),
+        buffer::Source::Uri(uri) => read_uri(uri, base)?,
+    };
+    if data.len() < buffer.length() {
+        bail!(
+            "buffer {} has {} bytes, expected {}",
+            buffer.index(),
+            data.len(),
+            buffer.length()
+        );
+    }
+    Ok(data)
+}
+
+fn wrap(mode: WrappingMode) -> GLenum {
+    match mode {
+        WrappingMode::ClampToEdge => gl::CLAMP_TO_EDGE,
+        WrappingMode::MirroredRepeat => gl::MIRRORED_REPEAT,
+        WrappingMode::Repeat => gl::REPEAT,
+    }
+}
+
+fn describe(kind: &str, index: Option<usize>, name: Option<&str>) -> String {
+    match (name, index) {
+        (Some(name), _) => format!("{kind} {name:?}"),
+        (None, Some(index)) => format!("{kind} {index}"),
+        (None, None) => format!("default {kind}"),
+    }
+}
+
+// The vertices of a triangle primitive moved into place by `world`, and its indices. Errors
+// are about primitives that can't be loaded, which are skipped.
+fn read_primitive(
+    primitive: &Primitive,
+    buffers: &[Vec<u8>],
+    world: &Matrix4<f32>,
+    normal_matrix: &Matrix3<f32>,
+) -> Result<(Vec<ModelVertex>, Vec<u32>)> {
+    if primitive.mode() != Mode::Triangles {
+        bail!(
+            "{:?} primitives are not supported, only triangles",
+            primitive.mode()
+        );
+    }
+    let reader = primitive.reader(|b| buffers.get(b.index()).map(|data| data.as_slice()));
+    let Some(positions) = reader.read_positions() else {
+        bail!("primitive {} has no positions", primitive.index());
+    };
+    let mut vertices: Vec<ModelVertex> = positions
+        .map(|p| ModelVertex {
+            position: world.transform_point(p.into()).into(),
+            ..ModelVertex::default()
+        })
+        .collect();
+    let indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..vertices.len() as u32).collect(),
+    };
+    if let Some(index) = index_out_of_range(&indices, vertices.len()) {
+        bail!(
+            "primitive {} has index {index} but only {} vertices",
+            primitive.index(),
+            vertices.len()
+        );
+    }
+    match reader.read_normals() {
+        Some(normals) => {
+            for (vertex, n) in vertices.iter_mut().zip(normals) {
+                vertex.normal = (normal_matrix * Vector3::from(n)).normalize().into();
+            }
+        }
+        None => compute_normals(&mut vertices, &indices),
+    }
+    if let Some(uvs) = reader.read_tex_coords(0) {
+        // glTF puts v = 0 at the top of the image, which is the first row uploaded so they
+        // are used as they are
+        for (vertex, uv) in vertices.iter_mut().zip(uvs.into_f32()) {
+            vertex.tex_coords = uv;
+        }
+    }
+    Ok((vertices, indices))
+}
+
+struct Loader<'a> {
+    base: &'a Path,
+    buffers: Vec<Vec<u8>>,
+    // keyed by texture index and the color factor multiplied into it
+    textures: HashMap<(usize, [u32; 4]), Option<Rc<Texture2D>>>,
+    // index in the model's materials by glTF material index
+    materials: HashMap<Option<usize>, usize>,
+    model: Model,
+}
+
+impl<'a> Loader<'a> {
+    fn node(&mut self, node: &Node, parent: Matrix4<f32>) -> Result<()> {
+        let world = parent * Matrix4::from(node.transform().matrix());
+        if let Some(mesh) = node.mesh() {
+            self.mesh(&mesh, &world)?;
+        }
+        for child in node.children() {
+            self.node(&child, world)?;
+        }
+        Ok(())
+    }
+
+    fn mesh(&mut self, mesh: &::gltf::Mesh, world: &Matrix4<f32>) -> Result<()> {
+        let name = describe("mesh", Some(mesh.index()), mesh.name());
+        // normals go through the inverse transpose so scaling doesn't bend them
+        let normal_matrix = world
+            .invert()
+            .map(|inverse| {
+                let m = inverse.transpose();
+                Matrix3::from_cols(m.x.truncate(), m.y.truncate(), m.z.truncate())
+            })
+            .unwrap_or_else(Matrix3::identity);
+        for primitive in mesh.primitives() {
+            let (vertices, indices) =
+                match read_primitive(&primitive, &self.buffers, world, &normal_matrix) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        self.model.warn(format!("{name}: {e}"));
+                        continue;
+                    }
+                };
+            let material = self.material(&primitive.material())?;
+            self.model.upload(MeshData {
+                name: format!("{}.{}", mesh.name().unwrap_or("mesh"), primitive.index()),
+                vertices,
+                indices,
+                material: Some(material),
+            })?;
+        }
+        Ok(())
+    }
+
+    fn material(&mut self, material: &::gltf::Material) -> Result<usize> {
+        if let Some(&cached) = self.materials.get(&material.index()) {
+            return Ok(cached);
+        }
+        let name = describe("material", material.index(), material.name());
+        let pbr = material.pbr_metallic_roughness();
+        let factor = pbr.base_color_factor();
+        let diffuse = match pbr.base_color_texture() {
+            Some(info) if info.tex_coord() == 0 => self.texture(&name, &info.texture(), factor),
+            Some(info) => {
+                self.model.warn(format!(
+                    "{name}: texture {} uses TEXCOORD_{}, only TEXCOORD_0 is supported",
+                    info.texture().index(),
+                    info.tex_coord()
+                ));
+                None
+            }
+            None => None,
+        };
+        let diffuse = match diffuse {
+            Some(texture) => texture,
+            None => Rc::new(color_texture([factor[0], factor[1], factor[2]])?),
+        };
+        // the usual conversion from roughness to a Blinn-Phong exponent
+        let alpha = pbr.roughness_factor().powi(2).max(0.01);
+        let shininess = (2. / alpha.powi(2) - 2.).clamp(1., 256.);
+        let highlight = 1. - pbr.roughness_factor();
+        self.model.materials.push(ModelMaterial {
+            name: material.name().unwrap_or(&name).to_owned(),
+            diffuse,
+            specular: Rc::new(color_texture([highlight; 3])?),
+            shininess,
+        });
+        let index = self.model.materials.len() - 1;
+        self.materials.insert(material.index(), index);
+        Ok(index)
+    }
+
+    fn texture(
+        &mut self,
+        material: &str,
+        texture: &::gltf::Texture,
+        factor: [f32; 4],
+    ) -> Option<Rc<Texture2D>> {
+        let key = (texture.index(), factor.map(f32::to_bits));
+        if let Some(cached) = self.textures.get(&key) {
+            return cached.clone();
+        }
+        let loaded = match self.image(texture, factor) {
+            Ok(texture) => Some(Rc::new(texture)),
+            Err(e) => {
+                self.model.warn(format!(
+                    "{material}: texture {} skipped: {e:#}",
+                    texture.index()
+                ));
+                None
+            }
+        };
+        self.textures.insert(key, loaded.clone());
+        loaded
+    }
+
+    fn image(&self, texture: &::gltf::Texture, factor: [f32; 4]) -> Result<Texture2D> {
+        let bytes = match texture.source().source() {
+            Source::View { view, .. } => {
+                let buffer = &self.buffers[view.buffer().index()];
+                buffer
+                    .get(view.offset()..view.offset() + view.length())
+                    .ok_or_else(|| anyhow!("buffer view {} is out of bounds", view.index()))?
+                    .to_vec()
+            }
+            Source::Uri { uri, .. } => read_uri(uri, self.base)?,
+        };
+        let mut img = image::load_from_memory(&bytes)?.to_rgba8();
+        if factor != [1.; 4] {
+            for pixel in img.pixels_mut() {
+                for (c, f) in pixel.0.iter_mut().zip(factor) {
+                    *c = (*c as f32 * f).round() as u8;
+                }
+            }
+        }
+        let sampler = texture.sampler();
+        rgba_texture(img, [wrap(sampler.wrap_s()), wrap(sampler.wrap_t())])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("my%20model.bin"), "my model.bin");
+        assert_eq!(percent_decode("a%2Fb%2fc"), "a/b/c");
+        // anything that isn't an escape is kept
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("50%zz"), "50%zz");
+        assert_eq!(percent_decode("plain.bin"), "plain.bin");
+    }
+
+    #[test]
+    fn test_decode_data_uri() {
+        let data = decode_data_uri("data:application/octet-stream;base64,AAEC/w==");
+        assert_eq!(data.unwrap().unwrap(), [0, 1, 2, 255]);
+        assert!(
+            decode_data_uri("data:application/octet-stream;base64,!!").is_some_and(|d| d.is_err())
+        );
+        assert!(decode_data_uri("model.bin").is_none());
+        assert!(decode_data_uri("data:text/plain,hello").is_none());
+    }
+
+    // a triangle with the indices 0, 1 and `last`
+    fn triangle(last: u16) -> (Gltf, Vec<Vec<u8>>) {
+        let mut data: Vec<u8> = [0f32, 0., 0., 1., 0., 0., 0., 1., 0.]
+            .iter()
+            .flat_map(|f| f.to_le_bytes())
+            .collect();
+        data.extend([0u16, 1, last, 0].iter().flat_map(|i| i.to_le_bytes()));
+        let json = format!(
+            r#"{{
+                "asset": {{"version": "2.0"}},
+                "buffers": [{{"byteLength": 44, "uri": "data:application/octet-stream;base64,{}"}}],
+                "bufferViews": [
+                    {{"buffer": 0, "byteOffset": 0, "byteLength": 36}},
+                    {{"buffer": 0, "byteOffset": 36, "byteLength": 6}}
+                ],
+                "accessors": [
+                    {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                      "min": [0, 0, 0], "max": [1, 1, 0]}},
+                    {{"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}}
+                ],
+                "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0}}, "indices": 1}}]}}]
+            }}"#,
+            STANDARD.encode(&data)
+        );
+        let gltf = Gltf::from_slice(json.as_bytes()).unwrap();
+        let buffers = gltf
+            .buffers()
+            .map(|b| load_buffer(&b, Path::new("."), None))
+            .collect::<Result<_>>()
+            .unwrap();
+        (gltf, buffers)
+    }
+
+    #[test]
+    fn test_read_primitive() {
+        let world = Matrix4::from_translation(Vector3::new(0., 0., 2.));
+        let (gltf, buffers) = triangle(2);
+        let primitive = gltf.meshes().next().unwrap().primitives().next().unwrap();
+        let (vertices, indices) =
+            read_primitive(&primitive, &buffers, &world, &Matrix3::identity()).unwrap();
+        assert_eq!(indices, [0, 1, 2]);
+        assert_eq!(vertices[1].position, [1., 0., 2.]);
+        // without normals in the file they are computed
+        assert!(vertices.iter().all(|v| v.normal == [0., 0., 1.]));
+
+        let (gltf, buffers) = triangle(3);
+        let primitive = gltf.meshes().next().unwrap().primitives().next().unwrap();
+        let error = read_primitive(&primitive, &buffers, &world, &Matrix3::identity());
+        assert_eq!(
+            error.unwrap_err().to_string(),
+            "primitive 0 has index 3 but only 3 vertices"
+        );
+    }
+}
@@ -0,0 +1,256 @@
+mod gltf;
+mod obj;
+
+use crate::{
+    gls::{
+        buffers::{bindable::Bindable, mesh::Mesh, texture::Texture2D, vertex::Vertex},
+        shader::ShaderProgram,
+    },
+    lights::material::Material,
+};
+use anyhow::{bail, Context, Result};
+use cgmath::{InnerSpace, Vector3};
+use gl::types::GLenum;
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::{path::Path, rc::Rc};
+
+// A vertex of a loaded model, at the locations the maze's vert.glsl reads: position 0,
+// normal 1 and texture coordinates 2
+#[derive(Debug, Clone, Copy, Default, Vertex)]
+pub struct ModelVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub tex_coords: [f32; 2],
+}
+
+// What a part of a model is drawn with. Colors without a texture are turned into 1x1
+// textures, so every material samples its textures the same way.
+pub struct ModelMaterial {
+    pub name: String,
+    pub diffuse: Rc<Texture2D>,
+    pub specular: Rc<Texture2D>,
+    pub shininess: f32,
+}
+
+impl ModelMaterial {
+    // the material for meshes that don't name one
+    fn default() -> Result<Self> {
+        Ok(Self {
+            name: "default".to_owned(),
+            diffuse: Rc::new(color_texture([0.8, 0.8, 0.8])?),
+            specular: Rc::new(color_texture([0.5, 0.5, 0.5])?),
+            shininess: 32.,
+        })
+    }
+}
+
+// One mesh of a model and the index of the material it is drawn with
+pub struct SubMesh {
+    pub name: String,
+    pub mesh: Mesh<ModelVertex>,
+    pub material: usize,
+}
+
+// A mesh as read from a file, before it is uploaded
+struct MeshData {
+    name: String,
+    vertices: Vec<ModelVertex>,
+    indices: Vec<u32>,
+    // index in the model's materials, None for the default material
+    material: Option<usize>,
+}
+
+// Meshes loaded from an OBJ or glTF file with their materials
+#[derive(Default)]
+pub struct Model {
+    pub meshes: Vec<SubMesh>,
+    pub materials: Vec<ModelMaterial>,
+    // what couldn't be loaded, the rest of the model is loaded anyway
+    pub warnings: Vec<String>,
+    default_material: Option<usize>,
+}
+
+impl Model {
+    // Loads an .obj (with the .mtl files it names) or a .gltf/.glb file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        match extension.as_deref() {
+            Some("obj") => obj::load(path),
+            Some("gltf") | Some("glb") => gltf::load(path),
+            _ => bail!("{} is not an .obj, .gltf or .glb file", path.display()),
+        }
+    }
+
+    // Draws every mesh with its material. The diffuse and specular textures are bound to
+    // texture units 0 and 1 and the `material` uniform (a `Material`) is pointed at them.
+    pub fn draw(&self, shader: &ShaderProgram, material: &str) -> Result<()> {
+        for sub_mesh in self.meshes.iter() {
+            let m = &self.materials[sub_mesh.material];
+            unsafe {
+                gl::ActiveTexture(gl::TEXTURE0);
+            }
+            m.diffuse.bind()?;
+            unsafe {
+                gl::ActiveTexture(gl::TEXTURE1);
+            }
+            m.specular.bind()?;
+            shader.set_uniform(material, Material::new(0, 1, m.shininess))?;
+            sub_mesh.mesh.draw()?;
+        }
+        Ok(())
+    }
+
+    fn warn(&mut self, warning: String) {
+        if !self.warnings.contains(&warning) {
+            self.warnings.push(warning);
+        }
+    }
+
+    fn upload(&mut self, mesh: MeshData) -> Result<()> {
+        let material = match mesh.material {
+            Some(material) => material,
+            None => self.default_material()?,
+        };
+        let gl_mesh = Mesh::new(
+            &mesh.vertices,
+            Some(&mesh.indices),
+            gl::TRIANGLES,
+            gl::STATIC_DRAW,
+        )
+        .with_context(|| format!("uploading {}", mesh.name))?;
+        self.meshes.push(SubMesh {
+            name: mesh.name,
+            mesh: gl_mesh,
+            material,
+        });
+        Ok(())
+    }
+
+    // the index of the default material, added the first time it is needed
+    fn default_material(&mut self) -> Result<usize> {
+        if let Some(i) = self.default_material {
+            return Ok(i);
+        }
+        self.materials.push(ModelMaterial::default()?);
+        self.default_material = Some(self.materials.len() - 1);
+        Ok(self.materials.len() - 1)
+    }
+}
+
+fn rgba_texture(img: RgbaImage, wrap: [GLenum; 2]) -> Result<Texture2D> {
+    Texture2D::new(
+        DynamicImage::ImageRgba8(img),
+        wrap,
+        [gl::LINEAR_MIPMAP_LINEAR, gl::LINEAR],
+        gl::RGBA,
+        None,
+    )
+}
+
+fn color_texture([r, g, b]: [f32; 3]) -> Result<Texture2D> {
+    let pixel = [r, g, b, 1.].map(|c| (c.clamp(0., 1.) * 255.).round() as u8);
+    rgba_texture(
+        RgbaImage::from_pixel(1, 1, Rgba(pixel)),
+        [gl::REPEAT, gl::REPEAT],
+    )
+}
+
+// the first of `indices` that doesn't point at one of `vertex_count` vertices
+fn index_out_of_range(indices: &[u32], vertex_count: usize) -> Option<u32> {
+    indices
+        .iter()
+        .copied()
+        .find(|&i| i as usize >= vertex_count)
+}
+
+// normals for meshes that come without them, each vertex gets the average of the faces
+// around it weighted by their area. The indices have to be in range.
+fn compute_normals(vertices: &mut [ModelVertex], indices: &[u32]) {
+    let mut normals = vec![Vector3::new(0f32, 0., 0.); vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| Vector3::from(vertices[triangle[i] as usize].position));
+        let normal = (b - a).cross(c - a);
+        for &i in triangle {
+            normals[i as usize] += normal;
+        }
+    }
+    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+        if normal.magnitude2() > 0. {
+            vertex.normal = normal.normalize().into();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn vertex(position: [f32; 3]) -> ModelVertex {
+        ModelVertex {
+            position,
+            ..ModelVertex::default()
+        }
+    }
+
+    #[test]
+    fn test_index_out_of_range() {
+        assert_eq!(index_out_of_range(&[0, 1, 2], 3), None);
+        assert_eq!(index_out_of_range(&[0, 3, 2], 3), Some(3));
+        assert_eq!(index_out_of_range(&[0, 1, 2, 4, 5], 4), Some(4));
+        assert_eq!(index_out_of_range(&[0], 0), Some(0));
+        assert_eq!(index_out_of_range(&[], 0), None);
+    }
+
+    #[test]
+    fn test_flat_normals() {
+        // a quad facing +z and a vertex no triangle uses
+        let mut vertices = [
+            [0., 0., 0.],
+            [1., 0., 0.],
+            [1., 1., 0.],
+            [0., 1., 0.],
+            [5., 5., 5.],
+        ]
+        .map(vertex);
+        compute_normals(&mut vertices, &[0, 1, 2, 0, 2, 3]);
+        for v in &vertices[..4] {
+            assert_eq!(v.normal, [0., 0., 1.]);
+        }
+        assert_eq!(vertices[4].normal, [0., 0., 0.]);
+    }
+
+    #[test]
+    fn test_shared_normals() {
+        // a unit cube with its 8 corners shared by the faces around them
+        let mut vertices: Vec<ModelVertex> = (0..8)
+            .map(|i| vertex([i & 1, (i >> 1) & 1, (i >> 2) & 1].map(|c| c as f32)))
+            .collect();
+        let faces = [
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+        ];
+        let indices: Vec<u32> = faces
+            .iter()
+            .flat_map(|[a, b, c, d]| [a, b, c, a, c, d])
+            .copied()
+            .collect();
+        compute_normals(&mut vertices, &indices);
+        for v in &vertices {
+            let normal = Vector3::from(v.normal);
+            assert!((normal.magnitude() - 1.).abs() < 1e-6);
+            // every corner points away from the middle, along all three of its faces
+            for axis in 0..3 {
+                let outwards = v.position[axis] - 0.5;
+                assert!(normal[axis] * outwards.signum() > 0.3, "{v:?}");
+            }
+        }
+    }
+}
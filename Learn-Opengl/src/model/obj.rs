@@ -0,0 +1,206 @@
+use super::{
+    color_texture, compute_normals, index_out_of_range, rgba_texture, MeshData, Model,
+    ModelMaterial, ModelVertex,
+};
+use crate::gls::buffers::texture::Texture2D;
+use anyhow::{Context, Result};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+// Loads every object and group of an OBJ file as its own mesh, with the materials of the
+// MTL files it names. Faces are triangulated and vertices deduplicated into an index buffer.
+pub(super) fn load(path: &Path) -> Result<Model> {
+    let (objects, materials) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)
+        .with_context(|| format!("reading OBJ {}", path.display()))?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    let mut model = Model::default();
+    let materials = materials.unwrap_or_else(|e| {
+        model.warn(format!("materials of {} not loaded: {e}", path.display()));
+        vec![]
+    });
+    let mut textures = HashMap::new();
+    for material in materials.iter() {
+        let loaded = load_material(&mut model, &mut textures, base, material)?;
+        model.materials.push(loaded);
+    }
+
+    let (meshes, warnings) = read_meshes(objects, materials.len());
+    for warning in warnings {
+        model.warn(warning);
+    }
+    for mesh in meshes {
+        model
+            .upload(mesh)
+            .with_context(|| format!("loading {}", path.display()))?;
+    }
+    Ok(model)
+}
+
+// The vertices and indices of the objects tobj read, with the warnings about objects that
+// were skipped or got the default material
+fn read_meshes(objects: Vec<tobj::Model>, material_count: usize) -> (Vec<MeshData>, Vec<String>) {
+    let mut meshes = vec![];
+    let mut warnings = vec![];
+    for object in objects {
+        let mesh = object.mesh;
+        if mesh.positions.is_empty() {
+            continue;
+        }
+        let mut vertices: Vec<ModelVertex> = (0..mesh.positions.len() / 3)
+            .map(|i| ModelVertex {
+                position: [0, 1, 2].map(|c| mesh.positions[3 * i + c]),
+                normal: match mesh.normals.get(3 * i..3 * i + 3) {
+                    Some(n) => [n[0], n[1], n[2]],
+                    None => [0., 0., 0.],
+                },
+                tex_coords: match mesh.texcoords.get(2 * i..2 * i + 2) {
+                    Some(t) => [t[0], t[1]],
+                    None => [0., 0.],
+                },
+            })
+            .collect();
+        if let Some(index) = index_out_of_range(&mesh.indices, vertices.len()) {
+            warnings.push(format!(
+                "{}: index {index} but only {} vertices, not loaded",
+                object.name,
+                vertices.len()
+            ));
+            continue;
+        }
+        if mesh.normals.is_empty() {
+            compute_normals(&mut vertices, &mesh.indices);
+        }
+        let material = match mesh.material_id {
+            Some(id) if id < material_count => Some(id),
+            Some(id) => {
+                warnings.push(format!(
+                    "{}: material {id} out of range, there are {material_count}, using the default material",
+                    object.name,
+                ));
+                None
+            }
+            None => None,
+        };
+        meshes.push(MeshData {
+            name: object.name,
+            vertices,
+            indices: mesh.indices,
+            material,
+        });
+    }
+    (meshes, warnings)
+}
+
+fn load_texture(path: &Path) -> Result<Texture2D> {
+    // OBJ texture coordinates start at the bottom of the image like OpenGL's, images are
+    // stored from the top
+    let img = image::open(path)?.flipv().to_rgba8();
+    rgba_texture(img, [gl::REPEAT, gl::REPEAT])
+}
+
+fn load_material(
+    model: &mut Model,
+    textures: &mut HashMap<PathBuf, Option<Rc<Texture2D>>>,
+    base: &Path,
+    material: &tobj::Material,
+) -> Result<ModelMaterial> {
+    let mut map = |file: &Option<String>| -> Option<Rc<Texture2D>> {
+        let path = base.join(file.as_ref()?);
+        textures
+            .entry(path.clone())
+            .or_insert_with(|| match load_texture(&path) {
+                Ok(texture) => Some(Rc::new(texture)),
+                Err(e) => {
+                    model.warn(format!(
+                        "material {}: texture {} skipped: {e:#}",
+                        material.name,
+                        path.display()
+                    ));
+                    None
+                }
+            })
+            .clone()
+    };
+    let diffuse = map(&material.diffuse_texture);
+    let specular = map(&material.specular_texture);
+    Ok(ModelMaterial {
+        name: material.name.clone(),
+        diffuse: match diffuse {
+            Some(texture) => texture,
+            None => Rc::new(color_texture(material.diffuse.unwrap_or([0.8, 0.8, 0.8]))?),
+        },
+        specular: match specular {
+            Some(texture) => texture,
+            None => Rc::new(color_texture(material.specular.unwrap_or([0.5, 0.5, 0.5]))?),
+        },
+        shininess: material.shininess.unwrap_or(32.).max(1.),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const OBJ: &str = "mtllib test.mtl
+o triangle
+v 0 0 1
+v 1 0 1
+v 0 1 1
+vn 0 0 -1
+f 1//1 2//1 3//1
+o quad
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+usemtl gold
+f 4 5 6 7
+";
+
+    fn objects() -> Vec<tobj::Model> {
+        // a material library that maps gold to a material it doesn't have
+        let (objects, _) = tobj::load_obj_buf(&mut OBJ.as_bytes(), &tobj::GPU_LOAD_OPTIONS, |_| {
+            Ok((vec![], [("gold".to_owned(), 0)].into_iter().collect()))
+        })
+        .unwrap();
+        objects
+    }
+
+    #[test]
+    fn test_read_meshes() {
+        let (meshes, warnings) = read_meshes(objects(), 0);
+        assert_eq!(
+            warnings,
+            ["quad: material 0 out of range, there are 0, using the default material"]
+        );
+        assert_eq!(meshes.len(), 2);
+        let (triangle, quad) = (&meshes[0], &meshes[1]);
+        assert_eq!((quad.name.as_str(), quad.material), ("quad", None));
+        assert_eq!(quad.vertices.len(), 4);
+        assert_eq!(quad.indices.len(), 6);
+        // normals are computed for the quad, the triangle has its own
+        assert!(quad.vertices.iter().all(|v| v.normal == [0., 0., 1.]));
+        assert!(triangle.vertices.iter().all(|v| v.normal == [0., 0., -1.]));
+
+        let (meshes, warnings) = read_meshes(objects(), 1);
+        assert!(warnings.is_empty());
+        assert_eq!(meshes[1].material, Some(0));
+    }
+
+    #[test]
+    fn test_index_out_of_range() {
+        // tobj rejects faces past the vertices it read, so the indices are broken by hand
+        let mut objects = objects();
+        objects[0].mesh.indices[2] = 3;
+        let (meshes, warnings) = read_meshes(objects, 1);
+        assert_eq!(
+            warnings,
+            ["triangle: index 3 but only 3 vertices, not loaded"]
+        );
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].name, "quad");
+    }
+}
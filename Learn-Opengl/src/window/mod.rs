@@ -72,6 +72,7 @@ impl Window {
             glfw.create_window(width, height, title, window_mode)
                 .map(|(mut window, events)| {
                     window.make_current();
+                    crate::gls::shader::forget_current_program();
                     window.set_key_polling(true);
                     window.set_framebuffer_size_polling(true);
                     window.set_cursor_pos_polling(true);
@@ -0,0 +1,4 @@
+layout (std140) uniform Camera {
+    mat4 projection;
+    mat4 view;
+};
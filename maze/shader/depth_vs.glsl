@@ -1,9 +1,8 @@
 #version 330 core
 layout (location = 0) in vec3 aPos;
-
-uniform mat4 model;
+layout (location = 3) in mat4 aModel;
 
 void main()
 {
-    gl_Position = model * vec4(aPos, 1.0);
+    gl_Position = aModel * vec4(aPos, 1.0);
 }
@@ -7,34 +7,26 @@ struct Material {
     float shininess;
 };
 
-struct PointLight {
-    vec3 position;
-    vec3 color;
-
-    float constant;
-    float linear;
-    float quadratic;
-
-    vec3 ambient;
-    vec3 diffuse;
-    vec3 specular;
-
-    samplerCube depthMap;
-};
+#include "point_light.glsl"
 
 in vec3 FragPos;
 in vec3 Normal;
 in vec2 TexCords;
-#define LIGHT_MAX 10
+// LIGHT_MAX, the number of lights in the level, and CHECK_LIGHTS, CHECK(I) for each of
+// them, are defined by the game
 
 uniform vec3 viewPos;
-uniform PointLight pointLight[LIGHT_MAX];
+layout (std140) uniform Lights {
+    PointLight pointLight[LIGHT_MAX];
+};
+// samplers can't be in a uniform block, so each light's shadow map is in here instead
+uniform samplerCube depthMaps[LIGHT_MAX];
 uniform int light_num;
 uniform Material material;
 uniform float far_plane;
 
 
-#define CHECK(I) if (I < light_num) {result += CalcPointLight(pointLight[I], norm, fragPos, viewDir);} else {return result;}
+#define CHECK(I) if (I < light_num) {result += CalcPointLight(pointLight[I], depthMaps[I], norm, fragPos, viewDir);} else {return result;}
 
 
 vec3 gridSamplingDisk[20] = vec3[](
@@ -45,21 +37,12 @@ vec3 gridSamplingDisk[20] = vec3[](
    vec3(0, 1,  1), vec3( 0, -1,  1), vec3( 0, -1, -1), vec3( 0, 1, -1)
 );
 
-vec3 CalcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir);
-float ShadowCalculations(PointLight light, vec3 fragPos);
+vec3 CalcPointLight(PointLight light, samplerCube depthMap, vec3 normal, vec3 fragPos, vec3 viewDir);
+float ShadowCalculations(PointLight light, samplerCube depthMap, vec3 fragPos);
 
 vec3 PointLoops(vec3 norm, vec3 fragPos, vec3 viewDir) {
     vec3 result = vec3(0.0);
-    CHECK(0)
-    CHECK(1)
-    CHECK(2)
-    CHECK(3)
-    CHECK(4)
-    CHECK(5)
-    CHECK(6)
-    CHECK(7)
-    CHECK(8)
-    CHECK(9)
+    CHECK_LIGHTS
     return result;
 
 }
@@ -78,7 +61,7 @@ void main()
 
 
 // calculates the color when using a point light.
-vec3 CalcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir)
+vec3 CalcPointLight(PointLight light, samplerCube depthMap, vec3 normal, vec3 fragPos, vec3 viewDir)
 {
     // return fragPos;
     vec3 lightDir = normalize(light.position - fragPos);
@@ -102,11 +85,11 @@ vec3 CalcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir)
     diffuse *= light.color;
     specular *= light.color;
 
-    float shadow = ShadowCalculations(light, fragPos);
+    float shadow = ShadowCalculations(light, depthMap, fragPos);
     return (ambient + (1.0 - shadow) * (diffuse + specular)) ;
 }
 
-float ShadowCalculations(PointLight light, vec3 fragPos){
+float ShadowCalculations(PointLight light, samplerCube depthMap, vec3 fragPos){
     // get vector between fragment position and light position
     vec3 fragToLight = fragPos - light.position;
     // use the fragment to light vector to sample from the depth map
@@ -156,7 +139,7 @@ float ShadowCalculations(PointLight light, vec3 fragPos){
     for(int i = 0; i < samples; ++i)
     {
         // this is the bad line of code
-        float closestDepth = texture(light.depthMap, fragToLight + gridSamplingDisk[i] * diskRadius).r;
+        float closestDepth = texture(depthMap, fragToLight + gridSamplingDisk[i] * diskRadius).r;
         closestDepth *= far_plane;   // undo mapping [0;1]
         if(currentDepth - bias > closestDepth) {
             shadow += 1.0;
@@ -2,6 +2,8 @@
 layout (location = 0) in vec3 aPos;
 layout (location = 1) in vec3 aNormal;
 layout (location = 2) in vec2 aTex;
+// per instance, one location per column
+layout (location = 3) in mat4 aModel;
 
 out vec4 ourColor;
 out vec3 FragPos;
@@ -9,15 +11,13 @@ out vec3 Normal;
 out vec2 TexCords;
 
 uniform vec4 uColor;
-uniform mat4 model;
-uniform mat4 view;
-uniform mat4 projection;
+#include "camera.glsl"
 
 void main()
 {
     ourColor = uColor;
     TexCords = aTex;
     Normal = aNormal;
-    FragPos = vec3(model * vec4(aPos, 1.0));
-	gl_Position = projection * view * model * vec4(aPos, 1.0);
+    FragPos = vec3(aModel * vec4(aPos, 1.0));
+	gl_Position = projection * view * aModel * vec4(aPos, 1.0);
 }
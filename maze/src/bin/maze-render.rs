@@ -0,0 +1,99 @@
+use std::{fs::read_to_string, path::PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use learn_opengl::window::Window;
+use maze::{
+    logic::Maze,
+    ray_trace::{scene, Lighting},
+};
+use ray_tracer::{
+    image::{save_image, ImageFormat},
+    ray::bvh::Bvh,
+    render::{render, Integrator, RenderSettings},
+};
+
+/// Path traces a maze level from the player's starting position.
+#[derive(Parser, Debug)]
+#[command(name = "maze-render")]
+struct Args {
+    /// Level to render
+    #[arg(default_value = "./maze.txt")]
+    maze: PathBuf,
+
+    /// Output image path
+    #[arg(short, long, default_value = "maze.png")]
+    output: PathBuf,
+
+    /// Output format (ppm, png or pfm), inferred from the output extension when omitted
+    #[arg(short, long)]
+    format: Option<ImageFormat>,
+
+    /// Image width in pixels
+    #[arg(short = 'W', long, default_value_t = 1600)]
+    width: u32,
+
+    /// Image height in pixels
+    #[arg(short = 'H', long, default_value_t = 1200)]
+    height: u32,
+
+    /// Samples per pixel
+    #[arg(long, default_value_t = 1000)]
+    spp: u32,
+
+    /// Maximum number of bounces, the level is closed so every path uses all of them
+    #[arg(long, default_value_t = 10)]
+    max_depth: i32,
+
+    /// How the light cells are lit: emissive or point
+    #[arg(long, default_value = "emissive")]
+    lighting: Lighting,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    if args.width < 2 || args.height < 2 || args.spp == 0 {
+        bail!("image must be at least 2x2 pixels with one or more samples per pixel");
+    }
+    let format = match args.format {
+        Some(format) => format,
+        None => ImageFormat::from_path(&args.output)?,
+    };
+
+    // the lights of a maze own shadow maps, so parsing one needs a GL context
+    let mut window = Window::new(1, 1, "maze-render", true, false)?;
+    window.window.hide();
+    let maze: Maze = read_to_string(&args.maze)
+        .with_context(|| format!("reading maze {}", args.maze.display()))?
+        .parse()
+        .context("parsing maze")?;
+
+    let scene = scene(&maze, args.lighting)?;
+    let settings = RenderSettings {
+        image_width: args.width,
+        image_height: args.height,
+        samples_per_pixel: args.spp,
+        max_depth: args.max_depth,
+        seed: None,
+        spectral: false,
+        integrator: Integrator::Path,
+        packets: false,
+        wavefront: false,
+    };
+    let cam = scene.camera.build(settings.aspect_ratio())?;
+    let world = Bvh::from(scene.world);
+    let (pixels, stats) = render(&world, &scene.lights, &cam, &settings);
+    save_image(
+        &args.output,
+        format,
+        settings.image_width,
+        settings.image_height,
+        &pixels,
+    )?;
+    eprintln!(
+        "rendered {} in {:.2}s",
+        args.output.display(),
+        stats.seconds
+    );
+    Ok(())
+}
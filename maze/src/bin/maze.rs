@@ -7,12 +7,15 @@ use learn_opengl::{
     gls::{
         buffers::{
             bindable::Bindable,
+            instance::InstanceBuffer,
             texture::{Tex2DTrait, Texture2D, Textures},
-            Attribute, VOs,
+            ubo::{Std140, UniformBuffer},
+            vertex::Vertex,
+            VOs,
         },
-        shader::{Shader, ShaderProgram},
+        shader::{preprocess::Preprocessor, reload::ReloadingProgram, Shader, ShaderProgram},
     },
-    lights::material::Material,
+    lights::{material::Material, point_lights::PointLight},
     window::Window,
 };
 
@@ -20,16 +23,67 @@ use maze::logic::{Maze, MazeEntry, MazeIndex};
 const SCR_WIDTH: u32 = 1600;
 const SCR_HEIGHT: u32 = 1200;
 
-const VERTEX_SHADER_SOURCE: &'static str = include_str!("../../shader/vert.glsl");
-const FRAGMENT_SHADER_SOURCE: &'static str = include_str!("../../shader/frag.glsl");
+const VERTEX_SHADER_PATH: &'static str = "./maze/shader/vert.glsl";
+const FRAGMENT_SHADER_PATH: &'static str = "./maze/shader/frag.glsl";
 
-const VERTEX_SHADER_SOURCE_LAMP: &'static str = include_str!("../../shader/lamp_vert.glsl");
-const FRAGMENT_SHADER_SOURCE_LAMP: &'static str = include_str!("../../shader/lamp_frag.glsl");
+const VERTEX_SHADER_PATH_LAMP: &'static str = "./maze/shader/lamp_vert.glsl";
+const FRAGMENT_SHADER_PATH_LAMP: &'static str = "./maze/shader/lamp_frag.glsl";
 
 const VERTEX_SHADER_SOURCE_DEPTH: &'static str = include_str!("../../shader/depth_vs.glsl");
 const FRAGMENT_SHADER_SOURCE_DEPTH: &'static str = include_str!("../../shader/depth_fs.glsl");
 const GEOMETRY_SHADER_SOURCE_DEPTH: &'static str = include_str!("../../shader/depth_ge.glsl");
 
+// the Camera uniform block shared by the main and lamp shaders
+#[derive(Std140)]
+struct CameraBlock {
+    projection: Matrix4<f32>,
+    view: Matrix4<f32>,
+}
+
+// a corner of one of the cube's faces, read by vert.glsl
+#[derive(Clone, Copy, Vertex)]
+struct CubeVertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+    tex_coords: [f32; 2],
+}
+
+impl CubeVertex {
+    fn new(position: [f32; 3], normal: [f32; 3], tex_coords: [f32; 2]) -> Self {
+        Self {
+            position,
+            normal,
+            tex_coords,
+        }
+    }
+}
+
+// where one wall, floor or ceiling cube goes, read by vert.glsl and depth_vs.glsl
+#[derive(Clone, Copy, Vertex)]
+struct TileInstance {
+    #[vertex(location = 3)]
+    model: Matrix4<f32>,
+}
+
+// a cube for every wall and a floor and a ceiling cube under and over every other tile
+fn tile_instances(maze: &Maze) -> Vec<TileInstance> {
+    let mut instances = vec![];
+    for (y, row) in maze.iter().enumerate() {
+        for (x, entry) in row.iter().enumerate() {
+            let heights: &[f32] = match entry {
+                MazeEntry::Wall => &[1.],
+                _ => &[0., 2.],
+            };
+            for &height in heights {
+                instances.push(TileInstance {
+                    model: Matrix4::from_translation(vec3(x as f32, height, y as f32)),
+                });
+            }
+        }
+    }
+    instances
+}
+
 fn main() {
     let mut window = Window::new(SCR_WIDTH, SCR_HEIGHT, "Learn Opengl", false, false).unwrap();
     window.window.set_framebuffer_size_polling(true);
@@ -46,17 +100,39 @@ fn main() {
         gl::Enable(gl::CULL_FACE);
     }
 
-    let shader = ShaderProgram::new([
-        Shader::new(VERTEX_SHADER_SOURCE, gl::VERTEX_SHADER).expect("Failed to Compile V Shader"),
-        Shader::new(FRAGMENT_SHADER_SOURCE, gl::FRAGMENT_SHADER)
-            .expect("Failed to Compile F Normal Shader"),
-    ])
+    // the shader is sized for the level's lights, with the light loop unrolled because the
+    // shadow maps can only be indexed by constants
+    let light_count = maze.get_lights().len();
+    // every light's shadow map takes a texture unit of the fragment shader next to the two
+    // floor textures
+    let mut texture_units = 0;
+    unsafe {
+        gl::GetIntegerv(gl::MAX_TEXTURE_IMAGE_UNITS, &mut texture_units);
+    }
+    let max_lights = (texture_units as usize).saturating_sub(2);
+    if light_count > max_lights {
+        panic!("There are too many lights: the level has {light_count}, at most {max_lights} fit in the shader")
+    }
+    let preprocessor = Preprocessor::new()
+        .define("LIGHT_MAX", light_count.max(1))
+        .define(
+            "CHECK_LIGHTS",
+            (0..light_count)
+                .map(|i| format!("CHECK({i})"))
+                .collect::<Vec<String>>()
+                .join(" "),
+        );
+    let mut shader = ReloadingProgram::with_preprocessor(
+        &[
+            (VERTEX_SHADER_PATH, gl::VERTEX_SHADER),
+            (FRAGMENT_SHADER_PATH, gl::FRAGMENT_SHADER),
+        ],
+        preprocessor,
+    )
     .expect("Failed to Create Shader Program");
-    let lamp_shader = ShaderProgram::new([
-        Shader::new(VERTEX_SHADER_SOURCE_LAMP, gl::VERTEX_SHADER)
-            .expect("Failed to Compile V Shader"),
-        Shader::new(FRAGMENT_SHADER_SOURCE_LAMP, gl::FRAGMENT_SHADER)
-            .expect("Failed to Compile F Lamp Shader"),
+    let mut lamp_shader = ReloadingProgram::new(&[
+        (VERTEX_SHADER_PATH_LAMP, gl::VERTEX_SHADER),
+        (FRAGMENT_SHADER_PATH_LAMP, gl::FRAGMENT_SHADER),
     ])
     .expect("Failed to Create Shader Program");
 
@@ -71,78 +147,53 @@ fn main() {
     .expect("Failed to Create Shader Program Depth");
 
     #[rustfmt::skip]
-    let cube_verts: [f32; 288] = [
-        // positions       // normals        // texture coords
-        -0.5, -0.5, -0.5,  0.0,  0.0, -1.0,  0.0,  0.0,
-         0.5,  0.5, -0.5,  0.0,  0.0, -1.0,  1.0,  1.0,
-         0.5, -0.5, -0.5,  0.0,  0.0, -1.0,  1.0,  0.0,
-         0.5,  0.5, -0.5,  0.0,  0.0, -1.0,  1.0,  1.0,
-        -0.5, -0.5, -0.5,  0.0,  0.0, -1.0,  0.0,  0.0,
-        -0.5,  0.5, -0.5,  0.0,  0.0, -1.0,  0.0,  1.0,
-
-        -0.5, -0.5,  0.5,  0.0,  0.0,  1.0,  0.0,  0.0,
-         0.5, -0.5,  0.5,  0.0,  0.0,  1.0,  1.0,  0.0,
-         0.5,  0.5,  0.5,  0.0,  0.0,  1.0,  1.0,  1.0,
-         0.5,  0.5,  0.5,  0.0,  0.0,  1.0,  1.0,  1.0,
-        -0.5,  0.5,  0.5,  0.0,  0.0,  1.0,  0.0,  1.0,
-        -0.5, -0.5,  0.5,  0.0,  0.0,  1.0,  0.0,  0.0,
-
-        -0.5,  0.5,  0.5, -1.0,  0.0,  0.0,  1.0,  0.0,
-        -0.5,  0.5, -0.5, -1.0,  0.0,  0.0,  1.0,  1.0,
-        -0.5, -0.5, -0.5, -1.0,  0.0,  0.0,  0.0,  1.0,
-        -0.5, -0.5, -0.5, -1.0,  0.0,  0.0,  0.0,  1.0,
-        -0.5, -0.5,  0.5, -1.0,  0.0,  0.0,  0.0,  0.0,
-        -0.5,  0.5,  0.5, -1.0,  0.0,  0.0,  1.0,  0.0,
-
-         0.5,  0.5,  0.5,  1.0,  0.0,  0.0,  1.0,  0.0,
-         0.5, -0.5, -0.5,  1.0,  0.0,  0.0,  0.0,  1.0,
-         0.5,  0.5, -0.5,  1.0,  0.0,  0.0,  1.0,  1.0,
-         0.5, -0.5, -0.5,  1.0,  0.0,  0.0,  0.0,  1.0,
-         0.5,  0.5,  0.5,  1.0,  0.0,  0.0,  1.0,  0.0,
-         0.5, -0.5,  0.5,  1.0,  0.0,  0.0,  0.0,  0.0,
-
-        -0.5, -0.5, -0.5,  0.0, -1.0,  0.0,  0.0,  1.0,
-         0.5, -0.5, -0.5,  0.0, -1.0,  0.0,  1.0,  1.0,
-         0.5, -0.5,  0.5,  0.0, -1.0,  0.0,  1.0,  0.0,
-         0.5, -0.5,  0.5,  0.0, -1.0,  0.0,  1.0,  0.0,
-        -0.5, -0.5,  0.5,  0.0, -1.0,  0.0,  0.0,  0.0,
-        -0.5, -0.5, -0.5,  0.0, -1.0,  0.0,  0.0,  1.0,
-
-        -0.5,  0.5, -0.5,  0.0,  1.0,  0.0,  0.0,  1.0,
-         0.5,  0.5,  0.5,  0.0,  1.0,  0.0,  1.0,  0.0,
-         0.5,  0.5, -0.5,  0.0,  1.0,  0.0,  1.0,  1.0,
-         0.5,  0.5,  0.5,  0.0,  1.0,  0.0,  1.0,  0.0,
-        -0.5,  0.5, -0.5,  0.0,  1.0,  0.0,  0.0,  1.0,
-        -0.5,  0.5,  0.5,  0.0,  1.0,  0.0,  0.0,  0.0,
-    ];
-    let attributes = [
-        Attribute {
-            // cords
-            location: 0,
-            size: 3,
-            normalized: false,
-            stride: 8,
-            offset: 0,
-        },
-        Attribute {
-            // normal
-            location: 1,
-            size: 3,
-            normalized: false,
-            stride: 8,
-            offset: 3,
-        },
-        Attribute {
-            // texture
-            location: 2,
-            size: 2,
-            normalized: false,
-            stride: 8,
-            offset: 6,
-        },
+    let cube_verts: [CubeVertex; 36] = [
+        CubeVertex::new([-0.5, -0.5, -0.5], [0.0, 0.0, -1.0], [0.0, 0.0]),
+        CubeVertex::new([0.5, 0.5, -0.5], [0.0, 0.0, -1.0], [1.0, 1.0]),
+        CubeVertex::new([0.5, -0.5, -0.5], [0.0, 0.0, -1.0], [1.0, 0.0]),
+        CubeVertex::new([0.5, 0.5, -0.5], [0.0, 0.0, -1.0], [1.0, 1.0]),
+        CubeVertex::new([-0.5, -0.5, -0.5], [0.0, 0.0, -1.0], [0.0, 0.0]),
+        CubeVertex::new([-0.5, 0.5, -0.5], [0.0, 0.0, -1.0], [0.0, 1.0]),
+
+        CubeVertex::new([-0.5, -0.5, 0.5], [0.0, 0.0, 1.0], [0.0, 0.0]),
+        CubeVertex::new([0.5, -0.5, 0.5], [0.0, 0.0, 1.0], [1.0, 0.0]),
+        CubeVertex::new([0.5, 0.5, 0.5], [0.0, 0.0, 1.0], [1.0, 1.0]),
+        CubeVertex::new([0.5, 0.5, 0.5], [0.0, 0.0, 1.0], [1.0, 1.0]),
+        CubeVertex::new([-0.5, 0.5, 0.5], [0.0, 0.0, 1.0], [0.0, 1.0]),
+        CubeVertex::new([-0.5, -0.5, 0.5], [0.0, 0.0, 1.0], [0.0, 0.0]),
+
+        CubeVertex::new([-0.5, 0.5, 0.5], [-1.0, 0.0, 0.0], [1.0, 0.0]),
+        CubeVertex::new([-0.5, 0.5, -0.5], [-1.0, 0.0, 0.0], [1.0, 1.0]),
+        CubeVertex::new([-0.5, -0.5, -0.5], [-1.0, 0.0, 0.0], [0.0, 1.0]),
+        CubeVertex::new([-0.5, -0.5, -0.5], [-1.0, 0.0, 0.0], [0.0, 1.0]),
+        CubeVertex::new([-0.5, -0.5, 0.5], [-1.0, 0.0, 0.0], [0.0, 0.0]),
+        CubeVertex::new([-0.5, 0.5, 0.5], [-1.0, 0.0, 0.0], [1.0, 0.0]),
+
+        CubeVertex::new([0.5, 0.5, 0.5], [1.0, 0.0, 0.0], [1.0, 0.0]),
+        CubeVertex::new([0.5, -0.5, -0.5], [1.0, 0.0, 0.0], [0.0, 1.0]),
+        CubeVertex::new([0.5, 0.5, -0.5], [1.0, 0.0, 0.0], [1.0, 1.0]),
+        CubeVertex::new([0.5, -0.5, -0.5], [1.0, 0.0, 0.0], [0.0, 1.0]),
+        CubeVertex::new([0.5, 0.5, 0.5], [1.0, 0.0, 0.0], [1.0, 0.0]),
+        CubeVertex::new([0.5, -0.5, 0.5], [1.0, 0.0, 0.0], [0.0, 0.0]),
+
+        CubeVertex::new([-0.5, -0.5, -0.5], [0.0, -1.0, 0.0], [0.0, 1.0]),
+        CubeVertex::new([0.5, -0.5, -0.5], [0.0, -1.0, 0.0], [1.0, 1.0]),
+        CubeVertex::new([0.5, -0.5, 0.5], [0.0, -1.0, 0.0], [1.0, 0.0]),
+        CubeVertex::new([0.5, -0.5, 0.5], [0.0, -1.0, 0.0], [1.0, 0.0]),
+        CubeVertex::new([-0.5, -0.5, 0.5], [0.0, -1.0, 0.0], [0.0, 0.0]),
+        CubeVertex::new([-0.5, -0.5, -0.5], [0.0, -1.0, 0.0], [0.0, 1.0]),
+
+        CubeVertex::new([-0.5, 0.5, -0.5], [0.0, 1.0, 0.0], [0.0, 1.0]),
+        CubeVertex::new([0.5, 0.5, 0.5], [0.0, 1.0, 0.0], [1.0, 0.0]),
+        CubeVertex::new([0.5, 0.5, -0.5], [0.0, 1.0, 0.0], [1.0, 1.0]),
+        CubeVertex::new([0.5, 0.5, 0.5], [0.0, 1.0, 0.0], [1.0, 0.0]),
+        CubeVertex::new([-0.5, 0.5, -0.5], [0.0, 1.0, 0.0], [0.0, 1.0]),
+        CubeVertex::new([-0.5, 0.5, 0.5], [0.0, 1.0, 0.0], [0.0, 0.0]),
     ];
-    let vbo_vba =
-        VOs::new(&cube_verts, &attributes, gl::TRIANGLES).expect("vbo or vba failed to bind");
+    let mut vbo_vba = VOs::new(&cube_verts, gl::TRIANGLES).expect("vbo or vba failed to bind");
+    // the walls never move, so every cube of the maze is drawn from one buffer made up front
+    let tiles = InstanceBuffer::new(&tile_instances(&maze), gl::STATIC_DRAW).unwrap();
+    vbo_vba.attach_instances(&tiles).unwrap();
 
     const SHADOW_WIDTH: i32 = 1024;
     const SHADOW_HEIGHT: i32 = 1024;
@@ -151,10 +202,19 @@ fn main() {
         .set_uniform("material", Material::new(0, 1, 0.5))
         .expect("error with material");
 
-    shader.set_uniform("pointLight", maze.get_lights()).unwrap();
-
-    if maze.get_lights().len() > 10 {
-        panic!("There are too many lights: value must be less than 10")
+    // the lights never change, so they are uploaded once, a level without lights has nothing
+    // to upload and never reads the block
+    let _lights_ubo = if maze.get_lights().is_empty() {
+        None
+    } else {
+        let lights_ubo = UniformBuffer::<[PointLight]>::new(maze.get_lights()).unwrap();
+        lights_ubo.bind_block(&shader, "Lights").unwrap();
+        Some(lights_ubo)
+    };
+    for (i, point_light) in maze.get_lights().iter().enumerate() {
+        shader
+            .set_uniform(&format!("depthMaps[{i}]"), point_light.get_depth_map())
+            .unwrap();
     }
 
     shader
@@ -172,8 +232,13 @@ fn main() {
     let mut projection: Matrix4<f32> =
         perspective(Deg(45.0), SCR_WIDTH as f32 / SCR_HEIGHT as f32, 0.1, 100.0);
 
-    shader.set_uniform("projection", projection).unwrap();
-    lamp_shader.set_uniform("projection", projection).unwrap();
+    let mut camera_ubo = UniformBuffer::new(&CameraBlock {
+        projection,
+        view: cam.get_view(),
+    })
+    .unwrap();
+    camera_ubo.bind_block(&shader, "Camera").unwrap();
+    camera_ubo.bind_block(&lamp_shader, "Camera").unwrap();
 
     let near_plane = 1.0f32;
     let far_plane = 10.0f32;
@@ -208,6 +273,11 @@ fn main() {
     textures.bind().unwrap();
 
     window.app_loop(|mut w| {
+        shader.update();
+        lamp_shader.update();
+        for warning in depth_shader.take_warnings() {
+            eprintln!("warning: {warning}");
+        }
         process_events(&mut w, &mut cam, &mut projection);
         let mut show_lamps = false;
         let dir = process_input(&mut w.window, &mut show_lamps);
@@ -234,6 +304,8 @@ fn main() {
             }
         }
         let view = cam.get_view();
+        // only the bytes that changed since the last frame are uploaded
+        camera_ubo.set(&CameraBlock { projection, view }).unwrap();
         for point_light in maze.get_lights().iter() {
             // 1. render scene to depth cubemap
             //
@@ -252,8 +324,7 @@ fn main() {
                 gl::Clear(gl::DEPTH_BUFFER_BIT);
             }
 
-            depth_shader.use_program();
-            render(&vbo_vba, &depth_shader, &maze);
+            render(&vbo_vba, &depth_shader, &tiles);
         }
         // // 2. render normally
         unsafe {
@@ -261,12 +332,10 @@ fn main() {
             gl::Viewport(0, 0, SCR_WIDTH as i32, SCR_HEIGHT as i32);
             gl::Clear(gl::DEPTH_BUFFER_BIT | gl::COLOR_BUFFER_BIT);
         }
-        shader.set_uniform("view", view.clone()).unwrap();
-        render(&vbo_vba, &shader, &maze);
+        render(&vbo_vba, &shader, &tiles);
 
         if show_lamps {
             lamp_shader.use_program();
-            lamp_shader.set_uniform("view", view).unwrap();
             for point_light in maze.get_lights().iter() {
                 lamp_shader
                     .set_uniform(
@@ -280,26 +349,12 @@ fn main() {
     });
 }
 
-fn render(vbo_vba: &VOs, shader: &ShaderProgram, maze: &Maze) {
-    for (y, row) in maze.iter().enumerate() {
-        for (x, entry) in row.iter().enumerate() {
-            let model: Matrix4<f32> = match entry {
-                maze::logic::MazeEntry::Wall => {
-                    Matrix4::from_translation(vec3(x as f32, 1., y as f32))
-                }
-                _ => {
-                    let model = Matrix4::from_translation(vec3(x as f32, 0., y as f32));
-                    shader
-                        .set_uniform("model", model * Matrix4::from_translation(vec3(0., 2., 0.)))
-                        .unwrap();
-                    vbo_vba.draw_arrays(0, 36).unwrap();
-                    model
-                }
-            };
-            shader.set_uniform("model", model).unwrap();
-            vbo_vba.draw_arrays(0, 36).unwrap();
-        }
-    }
+// every cube of the maze in one draw call
+fn render(vbo_vba: &VOs, shader: &ShaderProgram, tiles: &InstanceBuffer<TileInstance>) {
+    shader.use_program();
+    vbo_vba
+        .draw_arrays_instanced(0, 36, tiles.len() as u32)
+        .unwrap();
 }
 
 fn process_events(w: &mut Window, cam: &mut Camera, proj: &mut Matrix4<f32>) -> bool {
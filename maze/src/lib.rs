@@ -1 +1,2 @@
 pub mod logic;
+pub mod ray_trace;
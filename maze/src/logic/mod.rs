@@ -11,6 +11,18 @@ pub enum MazeEntry {
     End,
 }
 
+impl MazeEntry {
+    // color of the light placed on this cell, if it has one
+    pub fn light_color(&self) -> Option<Vector3<f32>> {
+        match self {
+            MazeEntry::Start => Some(vec3(0.0, 0.0, 1.0)),
+            MazeEntry::End => Some(vec3(0.0, 1.0, 1.0)),
+            MazeEntry::Empty(true) => Some(vec3(1.0, 1.0, 1.0)),
+            _ => None,
+        }
+    }
+}
+
 impl From<&MazeEntry> for char {
     fn from(entry: &MazeEntry) -> Self {
         match entry {
@@ -82,43 +94,25 @@ impl FromStr for Maze {
                 row.split(" ")
                     .enumerate()
                     .map(|(ci, entry)| {
-                        let entry = entry.parse()?;
+                        let entry: MazeEntry = entry.parse()?;
                         use MazeEntry::*;
-                        match entry {
-                            Start => {
-                                start = MazeIndex::new(ri as isize, ci as isize);
-                                has_start = true;
-                                lights.push(
-                                    PointLightBuilder::default()
-                                        .pos(start.into())
-                                        .color(vec3(0.0, 0.0, 1.0))
-                                        .depth_map(start_light)
-                                        .build()?,
-                                );
-                                start_light += 1;
-                            }
-                            End => {
-                                has_end = true;
-                                lights.push(
-                                    PointLightBuilder::default()
-                                        .pos(MazeIndex::new(ri as isize, ci as isize).into())
-                                        .color(vec3(0.0, 1.0, 1.0))
-                                        .depth_map(start_light)
-                                        .build()?,
-                                );
-                                start_light += 1;
-                            }
-                            Empty(true) => {
-                                lights.push(
-                                    PointLightBuilder::default()
-                                        .pos(MazeIndex::new(ri as isize, ci as isize).into())
-                                        .depth_map(start_light)
-                                        .build()?,
-                                );
-                                start_light += 1;
-                            }
-                            _ => {}
-                        };
+                        if entry == Start {
+                            start = MazeIndex::new(ri as isize, ci as isize);
+                            has_start = true;
+                        }
+                        if entry == End {
+                            has_end = true;
+                        }
+                        if let Some(color) = entry.light_color() {
+                            lights.push(
+                                PointLightBuilder::default()
+                                    .pos(MazeIndex::new(ri as isize, ci as isize).into())
+                                    .color(color)
+                                    .depth_map(start_light)
+                                    .build()?,
+                            );
+                            start_light += 1;
+                        }
                         return Ok(entry);
                     })
                     .collect::<Result<_>>()
@@ -187,7 +181,11 @@ impl Index<MazeIndex> for Maze {
 }
 
 impl Maze {
-    fn new(maze: Vec<Vec<MazeEntry>>, start: MazeIndex, lights: Vec<PointLight>) -> Self {
+    pub(crate) fn new(
+        maze: Vec<Vec<MazeEntry>>,
+        start: MazeIndex,
+        lights: Vec<PointLight>,
+    ) -> Self {
         Self {
             maze,
             player: start,
@@ -0,0 +1,278 @@
+use std::{str::FromStr, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use cgmath::{vec3, Deg, Point3, Vector3};
+use ray_tracer::{
+    animation::CameraAnimation,
+    camera::CameraSettings,
+    consts::PI,
+    light::Light,
+    material::{DiffuseLight, Lambertian, Material},
+    ray::{
+        hittable::{HittableList, Sphere},
+        mesh::Mesh,
+    },
+    scene::Scene,
+    texture::SolidColor,
+    Color, Float,
+};
+
+use crate::logic::{Maze, MazeEntry};
+
+// same size as the lamps the game draws
+const LAMP_RADIUS: Float = 0.1;
+// radiant intensity of a white light, the other colors are scaled by their channels
+const LIGHT_INTENSITY: Float = 2.;
+
+// Walls are unit cubes centered on their cell at y = 1, like in the game, so the floor
+// and ceiling are at the bottom and top of the walls
+const FLOOR: Float = 0.5;
+const CEILING: Float = 1.5;
+// lamps hang from the ceiling, out of the way of the camera on the start cell
+const LAMP_HEIGHT: Float = CEILING - 2. * LAMP_RADIUS;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Lighting {
+    // glowing lamps that light the level through the path tracer, slow to converge
+    #[default]
+    Emissive,
+    // point lights sampled directly, for quick previews
+    Point,
+}
+
+impl FromStr for Lighting {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "emissive" => Ok(Lighting::Emissive),
+            "point" => Ok(Lighting::Point),
+            _ => Err(anyhow!(
+                "unknown lighting {s:?}, expected emissive or point"
+            )),
+        }
+    }
+}
+
+fn to_color(v: Vector3<f32>) -> Color {
+    vec3(v.x as Float, v.y as Float, v.z as Float)
+}
+
+// Builds a ray tracer scene of the level seen from the player, looking down +z like the
+// game's starting camera
+pub fn scene(maze: &Maze, lighting: Lighting) -> Result<Scene> {
+    let rows: Vec<&Vec<MazeEntry>> = maze.iter().collect();
+    // outside of the level counts as wall, nothing inside can see the outer sides
+    let is_wall = |row: isize, col: isize| {
+        row < 0
+            || col < 0
+            || rows
+                .get(row as usize)
+                .and_then(|r| r.get(col as usize))
+                .is_none_or(|entry| *entry == MazeEntry::Wall)
+    };
+
+    let mut positions = vec![];
+    let mut indices = vec![];
+    let mut quad = |corners: [Point3<Float>; 4]| {
+        let i = positions.len() as u32;
+        positions.extend(corners);
+        indices.extend([[i, i + 1, i + 2], [i, i + 2, i + 3]]);
+    };
+
+    // only the sides of walls facing an open cell can be seen
+    for (ri, row) in rows.iter().enumerate() {
+        for (ci, entry) in row.iter().enumerate() {
+            if *entry != MazeEntry::Wall {
+                continue;
+            }
+            let (r, c) = (ri as isize, ci as isize);
+            let (x0, x1) = (ci as Float - 0.5, ci as Float + 0.5);
+            let (z0, z1) = (ri as Float - 0.5, ri as Float + 0.5);
+            let side = |x: Float, z: Float| [Point3::new(x, FLOOR, z), Point3::new(x, CEILING, z)];
+            let [p0, p1] = side(x0, z0);
+            let [p2, p3] = side(x1, z0);
+            let [p4, p5] = side(x1, z1);
+            let [p6, p7] = side(x0, z1);
+            if !is_wall(r - 1, c) {
+                quad([p0, p1, p3, p2]);
+            }
+            if !is_wall(r, c + 1) {
+                quad([p2, p3, p5, p4]);
+            }
+            if !is_wall(r + 1, c) {
+                quad([p4, p5, p7, p6]);
+            }
+            if !is_wall(r, c - 1) {
+                quad([p6, p7, p1, p0]);
+            }
+        }
+    }
+
+    let mut world = HittableList::default();
+    let wall_material: Arc<dyn Material> = Lambertian::new(vec3(0.6, 0.6, 0.6));
+    if !indices.is_empty() {
+        let walls = Arc::new(Mesh::new(positions, indices, wall_material)?);
+        for triangle in walls.triangles() {
+            world.add(triangle);
+        }
+    }
+
+    let width = rows.iter().map(|row| row.len()).max().unwrap_or(0) as Float;
+    let depth = rows.len() as Float;
+    let (x0, x1, z0, z1) = (-0.5, width - 0.5, -0.5, depth - 0.5);
+    let floor = Mesh::new(
+        vec![
+            Point3::new(x0, FLOOR, z0),
+            Point3::new(x0, FLOOR, z1),
+            Point3::new(x1, FLOOR, z1),
+            Point3::new(x1, FLOOR, z0),
+        ],
+        vec![[0, 1, 2], [0, 2, 3]],
+        Lambertian::new(vec3(0.4, 0.35, 0.3)),
+    )?;
+    let ceiling = Mesh::new(
+        vec![
+            Point3::new(x0, CEILING, z0),
+            Point3::new(x1, CEILING, z0),
+            Point3::new(x1, CEILING, z1),
+            Point3::new(x0, CEILING, z1),
+        ],
+        vec![[0, 1, 2], [0, 2, 3]],
+        Lambertian::new(vec3(0.7, 0.7, 0.7)),
+    )?;
+    for mesh in [floor, ceiling] {
+        for triangle in Arc::new(mesh).triangles() {
+            world.add(triangle);
+        }
+    }
+
+    let mut lights = vec![];
+    for (ri, row) in rows.iter().enumerate() {
+        for (ci, entry) in row.iter().enumerate() {
+            let Some(color) = entry.light_color() else {
+                continue;
+            };
+            let position = Point3::new(ci as Float, LAMP_HEIGHT, ri as Float);
+            let intensity = to_color(color) * LIGHT_INTENSITY;
+            match lighting {
+                Lighting::Emissive => {
+                    // a sphere's intensity is its radiance times its cross section
+                    let radiance = intensity / (PI * LAMP_RADIUS * LAMP_RADIUS);
+                    world.add(Arc::new(Sphere::new(
+                        position,
+                        LAMP_RADIUS,
+                        DiffuseLight::new(SolidColor::new(radiance)),
+                    )));
+                }
+                Lighting::Point => lights.push(Light::Point {
+                    position,
+                    intensity,
+                }),
+            }
+        }
+    }
+
+    let player = maze.get_player_loc();
+    let lookfrom = Point3::new(player.x as Float, player.y as Float, player.z as Float);
+    let camera = CameraSettings {
+        lookfrom,
+        lookat: lookfrom + vec3(0., 0., 1.),
+        vup: vec3(0., 1., 0.),
+        vfov: Deg(45.),
+        aperture: 0.,
+        focus_dist: 1.,
+        ..CameraSettings::default()
+    };
+
+    Ok(Scene {
+        world,
+        camera,
+        camera_animation: CameraAnimation::default(),
+        animated: vec![],
+        lights,
+        warnings: vec![],
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use ray_tracer::ray::hittable::Hittable;
+
+    use super::*;
+    use crate::logic::MazeIndex;
+
+    // three open cells in an L, each with a light. Parsing a maze makes the shadow maps
+    // of the game's lights, which needs a GL context, so the cells are parsed one by one.
+    fn maze() -> Maze {
+        let cells = "| | | |\n| S L |\n| | E |\n| | | |"
+            .lines()
+            .map(|row| row.split(' ').map(|entry| entry.parse().unwrap()).collect())
+            .collect();
+        Maze::new(cells, MazeIndex::new(1, 1), vec![])
+    }
+
+    #[test]
+    fn test_geometry() {
+        let maze = maze();
+        let level = scene(&maze, Lighting::Point).unwrap();
+        let (mut walls, mut floor, mut ceiling) = (0, 0, 0);
+        for object in level.world.into_objects() {
+            let bbox = object.bounding_box().unwrap();
+            // bounding boxes are padded a little
+            if bbox.max.y - bbox.min.y > 0.5 {
+                walls += 1;
+            } else if bbox.max.y < FLOOR + 1e-3 {
+                floor += 1;
+            } else if bbox.min.y > CEILING - 1e-3 {
+                ceiling += 1;
+            } else {
+                panic!("unexpected object {bbox:?}");
+            }
+        }
+        // the open cells see 3, 2 and 3 sides of walls, each two triangles
+        assert_eq!(walls, 2 * 8);
+        assert_eq!(floor, 2);
+        assert_eq!(ceiling, 2);
+    }
+
+    #[test]
+    fn test_lights() {
+        let maze = maze();
+        let point = scene(&maze, Lighting::Point).unwrap();
+        let lights: Vec<_> = point
+            .lights
+            .iter()
+            .map(|light| match *light {
+                Light::Point {
+                    position,
+                    intensity,
+                } => (position, intensity),
+                _ => panic!("expected point lights"),
+            })
+            .collect();
+        let at = |col: Float, row: Float| Point3::new(col, LAMP_HEIGHT, row);
+        let white = vec3(1., 1., 1.) * LIGHT_INTENSITY;
+        assert_eq!(
+            lights,
+            [
+                (at(1., 1.), vec3(0., 0., 1.) * LIGHT_INTENSITY),
+                (at(2., 1.), white),
+                (at(2., 2.), vec3(0., 1., 1.) * LIGHT_INTENSITY),
+            ]
+        );
+
+        // emissive lamps are spheres in the world instead
+        let emissive = scene(&maze, Lighting::Emissive).unwrap();
+        assert!(emissive.lights.is_empty());
+        let lamps: Vec<Point3<Float>> = emissive
+            .world
+            .into_objects()
+            .iter()
+            .map(|object| object.bounding_box().unwrap())
+            .filter(|bbox| (bbox.max.y - bbox.min.y - 2. * LAMP_RADIUS).abs() < 1e-3)
+            .map(|bbox| bbox.min + (bbox.max - bbox.min) / 2.)
+            .collect();
+        assert_eq!(lamps, [at(1., 1.), at(2., 1.), at(2., 2.)]);
+    }
+}
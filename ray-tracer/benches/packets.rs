@@ -0,0 +1,114 @@
+// Camera ray intersection one ray at a time against packets, and whole renders with and
+// without packets, on the random sphere scene. Run with `cargo bench`, and with
+// `cargo bench --features f32` for single precision.
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use ray_tracer::{
+    ray::{
+        bvh::Bvh,
+        hittable::Hittable,
+        packet::{PacketHits, RayPacket, LANES},
+        Ray,
+    },
+    render::{camera_ray, render, Integrator, RenderSettings},
+    scene::Scene,
+    seed_random, Float,
+};
+
+// best of a few runs, to keep other processes out of the numbers
+fn time<T>(runs: u32, mut f: impl FnMut() -> T) -> (Duration, T) {
+    let mut best = Duration::MAX;
+    let mut result = None;
+    for _ in 0..runs {
+        let start = Instant::now();
+        result = Some(black_box(f()));
+        best = best.min(start.elapsed());
+    }
+    (best, result.unwrap())
+}
+
+fn main() {
+    seed_random(1);
+    let scene = Scene::random(false);
+    let world = Bvh::from(scene.world);
+    let settings = RenderSettings {
+        image_width: 400,
+        image_height: 266,
+        samples_per_pixel: 8,
+        max_depth: 50,
+        seed: Some(1),
+        spectral: false,
+        integrator: Integrator::Path,
+        packets: false,
+        wavefront: false,
+    };
+    let cam = scene.camera.build(settings.aspect_ratio()).unwrap();
+    println!(
+        "{} precision, {LANES} rays per packet",
+        std::any::type_name::<Float>()
+    );
+
+    // the camera rays of every pixel, grouped by pixel like the renderer does
+    let mut rays: Vec<Ray> = vec![];
+    for j in 0..settings.image_height {
+        for i in 0..settings.image_width {
+            for _ in 0..settings.samples_per_pixel {
+                rays.extend(camera_ray(&cam, &settings, i, j));
+            }
+        }
+    }
+    let (scalar, scalar_hits) = time(5, || {
+        rays.iter()
+            .map(|r| world.hit(r, 0.001, Float::INFINITY).map(|rec| rec.t))
+            .collect::<Vec<_>>()
+    });
+    let (packet, packet_hits) = time(5, || {
+        rays.chunks(LANES)
+            .flat_map(|chunk| {
+                let packet = RayPacket::new(chunk);
+                let mut hits = PacketHits::new(&packet, Float::INFINITY);
+                world.hit_packet(&packet, 0.001, &mut hits);
+                hits.into_records(chunk.len())
+                    .map(|rec| rec.map(|rec| rec.t))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+    });
+    let mismatches = scalar_hits
+        .iter()
+        .zip(&packet_hits)
+        .filter(|(a, b)| match (a, b) {
+            (Some(a), Some(b)) => (a - b).abs() > 1e-4 * a.abs().max(1.),
+            (a, b) => a.is_some() != b.is_some(),
+        })
+        .count();
+    let rate = |d: Duration| rays.len() as f64 / d.as_secs_f64() / 1e6;
+    println!("camera rays: {}, mismatched hits: {mismatches}", rays.len());
+    println!("  single rays  {:>8.2} Mrays/s", rate(scalar));
+    println!(
+        "  packets      {:>8.2} Mrays/s  ({:.2}x)",
+        rate(packet),
+        scalar.as_secs_f64() / packet.as_secs_f64()
+    );
+
+    let frame = |packets| {
+        let settings = RenderSettings {
+            packets,
+            image_width: 200,
+            image_height: 133,
+            ..settings
+        };
+        time(3, || render(&world, &[], &cam, &settings).0.len()).0
+    };
+    let (single, packets) = (frame(false), frame(true));
+    println!("render 200x133 at 8 spp");
+    println!("  single rays  {:>8.3} s", single.as_secs_f64());
+    println!(
+        "  packets      {:>8.3} s  ({:.2}x)",
+        packets.as_secs_f64(),
+        single.as_secs_f64() / packets.as_secs_f64()
+    );
+}
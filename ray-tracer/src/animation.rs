@@ -0,0 +1,513 @@
+use std::{fs, path::PathBuf, str::FromStr, sync::Arc};
+
+use anyhow::{bail, Result};
+use cgmath::{
+    vec3, Deg, EuclideanSpace, InnerSpace, Matrix3, Matrix4, Point3, Quaternion, Rotation3, Vector3,
+};
+
+use crate::{
+    camera::CameraSettings,
+    image::{save_image, ImageFormat},
+    light::Light,
+    ray::{
+        aabb::Aabb,
+        hittable::{HitRecord, Hittable},
+        packet::{PacketHits, RayPacket},
+        transform::Transformed,
+        Ray,
+    },
+    render::{pixel_seed, render, RenderSettings},
+    stats::RenderStats,
+    Float,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    // Catmull-Rom through the keyframes
+    Spline,
+}
+
+impl FromStr for Interpolation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "linear" => Ok(Self::Linear),
+            "spline" => Ok(Self::Spline),
+            _ => bail!("unknown interpolation {s:?}, expected linear or spline"),
+        }
+    }
+}
+
+pub trait Interpolate: Copy {
+    fn lerp(a: Self, b: Self, t: Float) -> Self;
+    fn catmull_rom(p0: Self, p1: Self, p2: Self, p3: Self, t: Float) -> Self;
+}
+
+fn catmull_rom<T>(p0: T, p1: T, p2: T, p3: T, t: Float) -> T
+where
+    T: Copy
+        + std::ops::Add<Output = T>
+        + std::ops::Sub<Output = T>
+        + std::ops::Mul<Float, Output = T>,
+{
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (p1 * 2.
+        + (p2 - p0) * t
+        + (p0 * 2. - p1 * 5. + p2 * 4. - p3) * t2
+        + (p1 * 3. - p0 - p2 * 3. + p3) * t3)
+        * 0.5
+}
+
+impl Interpolate for Float {
+    fn lerp(a: Self, b: Self, t: Float) -> Self {
+        a + (b - a) * t
+    }
+    fn catmull_rom(p0: Self, p1: Self, p2: Self, p3: Self, t: Float) -> Self {
+        catmull_rom(p0, p1, p2, p3, t)
+    }
+}
+
+impl Interpolate for Vector3<Float> {
+    fn lerp(a: Self, b: Self, t: Float) -> Self {
+        a + (b - a) * t
+    }
+    fn catmull_rom(p0: Self, p1: Self, p2: Self, p3: Self, t: Float) -> Self {
+        catmull_rom(p0, p1, p2, p3, t)
+    }
+}
+
+impl Interpolate for Point3<Float> {
+    fn lerp(a: Self, b: Self, t: Float) -> Self {
+        Point3::from_vec(Vector3::lerp(a.to_vec(), b.to_vec(), t))
+    }
+    fn catmull_rom(p0: Self, p1: Self, p2: Self, p3: Self, t: Float) -> Self {
+        Point3::from_vec(catmull_rom(
+            p0.to_vec(),
+            p1.to_vec(),
+            p2.to_vec(),
+            p3.to_vec(),
+            t,
+        ))
+    }
+}
+
+impl Interpolate for Deg<Float> {
+    fn lerp(a: Self, b: Self, t: Float) -> Self {
+        Deg(Float::lerp(a.0, b.0, t))
+    }
+    fn catmull_rom(p0: Self, p1: Self, p2: Self, p3: Self, t: Float) -> Self {
+        Deg(catmull_rom(p0.0, p1.0, p2.0, p3.0, t))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe<T> {
+    pub time: Float,
+    pub value: T,
+}
+
+// Keyframes of a single value, kept sorted by time
+#[derive(Debug, Clone)]
+pub struct Track<T> {
+    keys: Vec<Keyframe<T>>,
+    pub interpolation: Interpolation,
+}
+
+impl<T> Default for Track<T> {
+    fn default() -> Self {
+        Self {
+            keys: vec![],
+            interpolation: Interpolation::default(),
+        }
+    }
+}
+
+impl<T: Interpolate> Track<T> {
+    pub fn new(interpolation: Interpolation) -> Self {
+        Self {
+            keys: vec![],
+            interpolation,
+        }
+    }
+
+    pub fn key(mut self, time: Float, value: T) -> Self {
+        self.insert(time, value);
+        self
+    }
+
+    // replaces an existing key at the same time
+    pub fn insert(&mut self, time: Float, value: T) {
+        match self.keys.binary_search_by(|key| key.time.total_cmp(&time)) {
+            Ok(idx) => self.keys[idx].value = value,
+            Err(idx) => self.keys.insert(idx, Keyframe { time, value }),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn duration(&self) -> Float {
+        self.keys.last().map_or(0., |key| key.time)
+    }
+
+    // None without keys, holds the first and last values outside the keyed range
+    pub fn sample(&self, time: Float) -> Option<T> {
+        let first = self.keys.first()?;
+        let last = self.keys.last()?;
+        if time <= first.time {
+            return Some(first.value);
+        }
+        if time >= last.time {
+            return Some(last.value);
+        }
+        let i = self.keys.partition_point(|key| key.time <= time) - 1;
+        let (k1, k2) = (&self.keys[i], &self.keys[i + 1]);
+        let t = (time - k1.time) / (k2.time - k1.time);
+        Some(match self.interpolation {
+            Interpolation::Linear => T::lerp(k1.value, k2.value, t),
+            Interpolation::Spline => {
+                let k0 = &self.keys[i.saturating_sub(1)];
+                let k3 = &self.keys[(i + 2).min(self.keys.len() - 1)];
+                T::catmull_rom(k0.value, k1.value, k2.value, k3.value, t)
+            }
+        })
+    }
+}
+
+// Keyframed overrides on top of a scene's `CameraSettings`
+#[derive(Debug, Clone, Default)]
+pub struct CameraAnimation {
+    pub lookfrom: Track<Point3<Float>>,
+    pub lookat: Track<Point3<Float>>,
+    pub vfov: Track<Deg<Float>>,
+    pub aperture: Track<Float>,
+    pub focus_dist: Track<Float>,
+    // rotation of lookfrom around lookat about the up vector
+    pub orbit: Track<Deg<Float>>,
+}
+
+impl CameraAnimation {
+    pub fn new(interpolation: Interpolation) -> Self {
+        Self {
+            lookfrom: Track::new(interpolation),
+            lookat: Track::new(interpolation),
+            vfov: Track::new(interpolation),
+            aperture: Track::new(interpolation),
+            focus_dist: Track::new(interpolation),
+            orbit: Track::new(interpolation),
+        }
+    }
+
+    // One full revolution around the look at point over `duration` seconds
+    pub fn turntable(duration: Float) -> Self {
+        let mut animation = Self::new(Interpolation::Linear);
+        animation.orbit = Track::new(Interpolation::Linear)
+            .key(0., Deg(0.))
+            .key(duration, Deg(360.));
+        animation
+    }
+
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.lookfrom.interpolation = interpolation;
+        self.lookat.interpolation = interpolation;
+        self.vfov.interpolation = interpolation;
+        self.aperture.interpolation = interpolation;
+        self.focus_dist.interpolation = interpolation;
+        self.orbit.interpolation = interpolation;
+    }
+
+    pub fn duration(&self) -> Float {
+        [
+            self.lookfrom.duration(),
+            self.lookat.duration(),
+            self.vfov.duration(),
+            self.aperture.duration(),
+            self.focus_dist.duration(),
+            self.orbit.duration(),
+        ]
+        .into_iter()
+        .fold(0., Float::max)
+    }
+
+    pub fn apply(&self, base: &CameraSettings, time: Float) -> CameraSettings {
+        let mut camera = base.clone();
+        camera.lookfrom = self.lookfrom.sample(time).unwrap_or(camera.lookfrom);
+        camera.lookat = self.lookat.sample(time).unwrap_or(camera.lookat);
+        camera.vfov = self.vfov.sample(time).unwrap_or(camera.vfov);
+        camera.aperture = self.aperture.sample(time).unwrap_or(camera.aperture);
+        camera.focus_dist = self.focus_dist.sample(time).unwrap_or(camera.focus_dist);
+        if let Some(angle) = self.orbit.sample(time) {
+            let rotation = Quaternion::from_axis_angle(camera.vup.normalize(), angle);
+            camera.lookfrom = camera.lookat + rotation * (camera.lookfrom - camera.lookat);
+        }
+        camera
+    }
+}
+
+// Keyframed translation, euler rotation (degrees, applied x then y then z) and uniform scale
+#[derive(Debug, Clone, Default)]
+pub struct TransformAnimation {
+    pub translation: Track<Vector3<Float>>,
+    pub rotation: Track<Vector3<Float>>,
+    pub scale: Track<Float>,
+}
+
+impl TransformAnimation {
+    pub fn new(interpolation: Interpolation) -> Self {
+        Self {
+            translation: Track::new(interpolation),
+            rotation: Track::new(interpolation),
+            scale: Track::new(interpolation),
+        }
+    }
+
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.translation.interpolation = interpolation;
+        self.rotation.interpolation = interpolation;
+        self.scale.interpolation = interpolation;
+    }
+
+    pub fn duration(&self) -> Float {
+        self.translation
+            .duration()
+            .max(self.rotation.duration())
+            .max(self.scale.duration())
+    }
+
+    pub fn matrix_at(&self, time: Float) -> Matrix4<Float> {
+        let translation = self.translation.sample(time).unwrap_or(vec3(0., 0., 0.));
+        let rotation = self.rotation.sample(time).unwrap_or(vec3(0., 0., 0.));
+        let scale = self.scale.sample(time).unwrap_or(1.);
+        let rotation = Matrix3::from_angle_z(Deg(rotation.z))
+            * Matrix3::from_angle_y(Deg(rotation.y))
+            * Matrix3::from_angle_x(Deg(rotation.x));
+        Matrix4::from_translation(translation)
+            * Matrix4::from(rotation)
+            * Matrix4::from_scale(scale)
+    }
+}
+
+// An object that moves over time, its geometry stays in object space
+pub struct AnimatedObject {
+    pub object: Arc<dyn Hittable>,
+    pub transform: TransformAnimation,
+}
+
+// The world at a single point in time: the static objects plus every animated object
+// placed with its transform for that time.
+pub struct Frame<'a> {
+    world: &'a dyn Hittable,
+    animated: Vec<Transformed<&'a dyn Hittable>>,
+    // animated objects left out of the frame, like ones scaled to 0
+    pub warnings: Vec<String>,
+}
+
+impl<'a> Frame<'a> {
+    pub fn new(world: &'a dyn Hittable, animated: &'a [AnimatedObject], time: Float) -> Self {
+        let mut warnings = vec![];
+        let animated = animated
+            .iter()
+            .enumerate()
+            .filter_map(|(i, a)| {
+                let transformed = Transformed::new(a.object.as_ref(), a.transform.matrix_at(time));
+                if transformed.is_none() {
+                    warnings.push(format!(
+                        "animated object {} is not drawn at t = {time:.3}s, its transform \
+                         can't be inverted",
+                        i + 1
+                    ));
+                }
+                transformed
+            })
+            .collect();
+        Self {
+            world,
+            animated,
+            warnings,
+        }
+    }
+}
+
+impl<'a> Hittable for Frame<'a> {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let mut closest = self.world.hit(r, t_min, t_max);
+        for object in self.animated.iter() {
+            let t_max = closest.as_ref().map_or(t_max, |rec| rec.t);
+            if let Some(rec) = object.hit(r, t_min, t_max) {
+                closest = Some(rec);
+            }
+        }
+        closest
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.animated
+            .iter()
+            .try_fold(self.world.bounding_box()?, |acc, object| {
+                Some(acc.surrounding(&object.bounding_box()?))
+            })
+    }
+
+    fn specular_bounds(&self, bounds: &mut Vec<Aabb>) {
+        self.world.specular_bounds(bounds);
+        for object in self.animated.iter() {
+            object.specular_bounds(bounds);
+        }
+    }
+
+    fn hit_packet<'b>(&'b self, packet: &RayPacket, t_min: Float, hits: &mut PacketHits<'b>) {
+        self.world.hit_packet(packet, t_min, hits);
+        for object in self.animated.iter() {
+            object.hit_packet(packet, t_min, hits);
+        }
+    }
+}
+
+pub struct Sequence {
+    pub frames: u32,
+    pub fps: Float,
+    pub dir: PathBuf,
+    pub format: ImageFormat,
+}
+
+impl Sequence {
+    pub fn frame_path(&self, frame: u32) -> PathBuf {
+        self.dir
+            .join(format!("frame_{frame:04}.{}", self.format.extension()))
+    }
+
+    pub fn frame_time(&self, frame: u32) -> Float {
+        (frame - 1) as Float / self.fps
+    }
+}
+
+// Renders frames 1..=frames into numbered images. `world` holds the static objects and is
+// shared by every frame, so an acceleration structure built for it is only built once.
+// Returns the statistics of all frames combined.
+pub fn render_sequence(
+    world: &dyn Hittable,
+    lights: &[Light],
+    animated: &[AnimatedObject],
+    camera: &CameraSettings,
+    camera_animation: &CameraAnimation,
+    settings: &RenderSettings,
+    sequence: &Sequence,
+) -> Result<RenderStats> {
+    fs::create_dir_all(&sequence.dir)?;
+    let mut stats = RenderStats::default();
+    for frame in 1..=sequence.frames {
+        let time = sequence.frame_time(frame);
+        eprintln!("frame {}/{} (t = {:.3}s)", frame, sequence.frames, time);
+        let frame_world = Frame::new(world, animated, time);
+        for warning in &frame_world.warnings {
+            eprintln!("warning: {warning}");
+        }
+        let cam = camera_animation
+            .apply(camera, time)
+            .build(settings.aspect_ratio())?;
+        let frame_settings = RenderSettings {
+            seed: settings.seed.map(|seed| pixel_seed(seed, frame as u64)),
+            ..*settings
+        };
+        let (pixels, frame_stats) = render(&frame_world, lights, &cam, &frame_settings);
+        stats.merge(&frame_stats);
+        save_image(
+            &sequence.frame_path(frame),
+            sequence.format,
+            settings.image_width,
+            settings.image_height,
+            &pixels,
+        )?;
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        material::Lambertian,
+        ray::hittable::{HittableList, Sphere},
+    };
+
+    use super::*;
+
+    fn track(interpolation: Interpolation) -> Track<Float> {
+        Track::new(interpolation)
+            .key(2., 4.)
+            .key(0., 0.)
+            .key(1., 1.)
+            .key(3., 9.)
+    }
+
+    #[test]
+    fn test_empty_track() {
+        assert_eq!(Track::<Float>::default().sample(1.), None);
+        assert_eq!(Track::default().key(1., 5.).sample(0.), Some(5.));
+    }
+
+    #[test]
+    fn test_linear() {
+        let track = track(Interpolation::Linear);
+        // keys are hit exactly, whatever order they were inserted in
+        for (time, value) in [(0., 0.), (1., 1.), (2., 4.), (3., 9.)] {
+            assert_eq!(track.sample(time), Some(value));
+        }
+        assert_eq!(track.sample(1.5), Some(2.5));
+        assert_eq!(track.sample(2.25), Some(5.25));
+        // held outside the keyed range
+        assert_eq!(track.sample(-1.), Some(0.));
+        assert_eq!(track.sample(10.), Some(9.));
+        assert_eq!(track.duration(), 3.);
+    }
+
+    #[test]
+    fn test_spline() {
+        let track = track(Interpolation::Spline);
+        for (time, value) in [(0., 0.), (1., 1.), (2., 4.), (3., 9.)] {
+            assert!((track.sample(time).unwrap() - value).abs() < 1e-6);
+        }
+        // Catmull-Rom reproduces t² between interior keys
+        assert!((track.sample(1.5).unwrap() - 2.25).abs() < 1e-6);
+        // the first segment repeats the first key as the one before it
+        assert!((track.sample(0.5).unwrap() - 0.3125).abs() < 1e-6);
+        assert_eq!(track.sample(-1.), Some(0.));
+        assert_eq!(track.sample(10.), Some(9.));
+    }
+
+    #[test]
+    fn test_replace_key() {
+        let track = Track::new(Interpolation::Linear)
+            .key(0., 0.)
+            .key(1., 1.)
+            .key(1., 3.);
+        assert_eq!(track.sample(0.5), Some(1.5));
+    }
+
+    #[test]
+    fn test_frame_warns_about_degenerate_transforms() {
+        let world = HittableList::default();
+        let sphere = Sphere::new(
+            Point3::new(0., 0., 0.),
+            1.,
+            Lambertian::new(vec3(1., 1., 1.)),
+        );
+        let animated = [AnimatedObject {
+            object: Arc::new(sphere),
+            transform: TransformAnimation {
+                scale: Track::new(Interpolation::Linear).key(0., 0.).key(1., 1.),
+                ..TransformAnimation::default()
+            },
+        }];
+        let frame = Frame::new(&world, &animated, 0.);
+        assert_eq!(frame.animated.len(), 0);
+        assert_eq!(frame.warnings.len(), 1);
+        let frame = Frame::new(&world, &animated, 0.5);
+        assert_eq!(frame.animated.len(), 1);
+        assert!(frame.warnings.is_empty());
+    }
+}
@@ -0,0 +1,140 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use clap::{Parser, Subcommand};
+use ray_tracer::{
+    image::{
+        compare::{false_color, metrics},
+        load_image, save_image,
+        tonemap::ToneMap,
+        ImageFormat,
+    },
+    Color, Float,
+};
+
+/// Compares renders and tone maps linear HDR renders (.pfm) for display.
+///
+/// PNG, JPEG and PPM images are read as gamma 2 encoded, like the ray tracer writes them.
+#[derive(Parser, Debug)]
+#[command(name = "image-tool", version)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the RMSE, PSNR and SSIM of an image against a reference
+    Compare {
+        reference: PathBuf,
+        image: PathBuf,
+
+        /// Write a false color image of the per pixel differences
+        #[arg(long)]
+        diff: Option<PathBuf>,
+
+        /// Difference shown in red in the diff image [default: the largest one]
+        #[arg(long, requires = "diff")]
+        diff_scale: Option<Float>,
+
+        #[command(flatten)]
+        display: Display,
+    },
+    /// Tone map a linear image for display
+    Tonemap {
+        input: PathBuf,
+
+        /// Output image path
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Output format (ppm, png or pfm), inferred from the output extension when omitted
+        #[arg(short, long)]
+        format: Option<ImageFormat>,
+
+        #[command(flatten)]
+        display: Display,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+struct Display {
+    /// Tone mapping operator: exposure, reinhard or aces
+    #[arg(long, default_value = "exposure")]
+    operator: ToneMap,
+
+    /// Exposure adjustment in stops, applied before the operator
+    #[arg(long, default_value_t = 0., allow_hyphen_values = true)]
+    exposure: Float,
+
+    /// Luminance that maps to white with the reinhard operator [default: infinite]
+    #[arg(long)]
+    white: Option<Float>,
+}
+
+impl Display {
+    // linear colors mapped into 0..1
+    fn apply(&self, pixels: &[Color]) -> Vec<Color> {
+        let operator = match self.operator {
+            ToneMap::Reinhard { .. } => ToneMap::Reinhard { white: self.white },
+            operator => operator,
+        };
+        pixels
+            .iter()
+            .map(|&c| operator.apply(c, self.exposure))
+            .collect()
+    }
+}
+
+fn main() -> Result<()> {
+    match Args::parse().command {
+        Command::Compare {
+            reference,
+            image,
+            diff,
+            diff_scale,
+            display,
+        } => {
+            let (width, height, a) = load_image(&reference)?;
+            let (b_width, b_height, b) = load_image(&image)?;
+            if (width, height) != (b_width, b_height) {
+                bail!("cannot compare a {width}x{height} image with a {b_width}x{b_height} one");
+            }
+            // metrics are taken on the gamma encoded values that end up on screen
+            let encode = |pixels: &[Color]| -> Vec<Color> {
+                display
+                    .apply(pixels)
+                    .into_iter()
+                    .map(|c| c.map(Float::sqrt))
+                    .collect()
+            };
+            let (a, b) = (encode(&a), encode(&b));
+            print!("{}", metrics(width, height, &a, &b)?);
+            if let Some(path) = diff {
+                let (pixels, scale) = false_color(&a, &b, diff_scale);
+                save_image(
+                    &path,
+                    ImageFormat::from_path(&path)?,
+                    width,
+                    height,
+                    &pixels,
+                )?;
+                println!("diff scale {scale:.6}");
+            }
+        }
+        Command::Tonemap {
+            input,
+            output,
+            format,
+            display,
+        } => {
+            let format = match format {
+                Some(format) => format,
+                None => ImageFormat::from_path(&output)?,
+            };
+            let (width, height, pixels) = load_image(&input)?;
+            save_image(&output, format, width, height, &display.apply(&pixels))?;
+        }
+    }
+    Ok(())
+}
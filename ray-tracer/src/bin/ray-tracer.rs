@@ -1,108 +1,422 @@
-use anyhow::Result;
-use cgmath::{vec3, Deg, ElementWise, EuclideanSpace, InnerSpace, Point3, Vector3};
+use std::{env, fs, iter, mem, path::PathBuf, sync::Arc};
+
+use anyhow::{bail, Context, Result};
+use cgmath::{Deg, Point3, Vector3};
+use clap::Parser;
 use ray_tracer::{
-    camera::Camera,
-    image::PPMImageWriter,
-    material::{Dielectric, Lambertian, Metal},
-    random,
-    ray::hittable::{HittableList, Sphere},
-    vector_additon::VectorAdditions,
+    animation::{render_sequence, CameraAnimation, Frame, Sequence},
+    camera::{aperture::Aperture, lens::LensSystem, CameraSettings},
+    distributed::{Coordinator, Worker},
+    image::{save_image, ImageFormat},
+    ray::bvh::Bvh,
+    render::{render, render_tile_stats, Integrator, RenderSettings},
+    scene::Scene,
+    seed_random,
+    texture::ImageTexture,
+    Float,
 };
-use rayon::prelude::*;
-
-fn random_scene() -> HittableList {
-    let mut world = HittableList::default();
-    let ground_material = Lambertian::new(vec3(0.5, 0.5, 0.5));
-    world.add(Box::new(Sphere::new(
-        Point3::new(0., -1000., 0.),
-        1000.,
-        ground_material,
-    )));
-
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = random(0. ..1.);
-            let center = Point3::new(
-                a as f64 + 0.9 * random(0. ..1.),
-                0.2,
-                b as f64 + 0.9 * random(0. ..1.),
-            );
-            if (center.to_vec() - vec3(4., 0.2, 0.)).magnitude() > 0.9 {
-                if choose_mat < 0.8 {
-                    // diffuse
-                    let albedo: Vector3<f64> =
-                        Vector3::random(0. ..1.).mul_element_wise(Vector3::random(0. ..1.));
-                    world.add(Box::new(Sphere::new(center, 0.2, Lambertian::new(albedo))));
-                } else if choose_mat < 0.95 {
-                    // metal
-                    let albedo = Vector3::random(0. ..0.5);
-                    let fuzz = random(0. ..0.5);
-                    world.add(Box::new(Sphere::new(center, 0.2, Metal::new(albedo, fuzz))));
-                } else {
-                    // glass
-                    world.add(Box::new(Sphere::new(center, 0.2, Dielectric::new(1.5))));
-                }
-            }
+
+/// Path traces a scene and writes it to an image.
+///
+/// Without --scene the built in random sphere scene is rendered.
+#[derive(Parser, Debug)]
+#[command(name = "ray-tracer", version)]
+struct Args {
+    /// Scene description or glTF (.gltf, .glb) file to render
+    #[arg(short, long)]
+    scene: Option<PathBuf>,
+
+    /// Output image path, or the directory frames are written to with --frames
+    #[arg(short, long, default_value = "image.ppm")]
+    output: PathBuf,
+
+    /// Output format (ppm or png), inferred from the output extension when omitted
+    /// [default with --frames: png]
+    #[arg(short, long)]
+    format: Option<ImageFormat>,
+
+    /// Render an animation of this many frames as frame_0001, frame_0002, ...
+    #[arg(long)]
+    frames: Option<u32>,
+
+    /// Frames per second of the animation
+    #[arg(long, default_value_t = 24.)]
+    fps: Float,
+
+    /// Orbit the camera once around its look at point over the animation
+    #[arg(long, requires = "frames")]
+    turntable: bool,
+
+    /// Image width in pixels
+    #[arg(short = 'W', long, default_value_t = 1200)]
+    width: u32,
+
+    /// Image height in pixels [default: width / 1.5]
+    #[arg(short = 'H', long)]
+    height: Option<u32>,
+
+    /// Samples per pixel
+    #[arg(long, default_value_t = 500)]
+    spp: u32,
+
+    /// Maximum number of bounces per path
+    #[arg(long, default_value_t = 50)]
+    max_depth: i32,
+
+    /// Seed for scene generation and sampling, makes renders reproducible
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Number of render threads [default: one per core]
+    #[arg(short = 'j', long)]
+    threads: Option<usize>,
+
+    /// Override the camera position, as x,y,z
+    #[arg(long, value_parser = parse_vector, allow_hyphen_values = true)]
+    lookfrom: Option<Vector3<Float>>,
+
+    /// Override the camera target, as x,y,z
+    #[arg(long, value_parser = parse_vector, allow_hyphen_values = true)]
+    lookat: Option<Vector3<Float>>,
+
+    /// Override the camera up vector, as x,y,z
+    #[arg(long, value_parser = parse_vector, allow_hyphen_values = true)]
+    vup: Option<Vector3<Float>>,
+
+    /// Override the vertical field of view, in degrees
+    #[arg(long)]
+    vfov: Option<Float>,
+
+    /// Override the lens aperture
+    #[arg(long)]
+    aperture: Option<Float>,
+
+    /// Override the focus distance
+    #[arg(long)]
+    focus_dist: Option<Float>,
+
+    /// Polygonal aperture with this many blades, for shaped bokeh
+    #[arg(long)]
+    aperture_blades: Option<u32>,
+
+    /// Rotation of the aperture blades, in degrees
+    #[arg(long, default_value_t = 0., allow_hyphen_values = true)]
+    aperture_rotation: Float,
+
+    /// Image whose brightness is the transmission of the aperture
+    #[arg(long, conflicts_with = "aperture_blades")]
+    aperture_mask: Option<PathBuf>,
+
+    /// Trace rays through a lens prescription (pbrt .dat format) instead of a thin lens
+    #[arg(long)]
+    lens: Option<PathBuf>,
+
+    /// Film diagonal in millimeters, used with --lens
+    #[arg(long, requires = "lens")]
+    film_diagonal: Option<Float>,
+
+    /// Scene units per millimeter of the lens, used with --lens
+    #[arg(long, requires = "lens")]
+    lens_scale: Option<Float>,
+
+    /// Quick look: quarter resolution, at most 8 samples and 8 bounces
+    #[arg(long)]
+    preview: bool,
+
+    /// Trace a single wavelength per sample so dispersive glass splits light
+    #[arg(long)]
+    spectral: bool,
+
+    /// Light transport algorithm: path, or photon for progressive photon mapping, which
+    /// renders caustics from small lights and the sky. With photon every sample is a pass.
+    #[arg(long, default_value = "path")]
+    integrator: Integrator,
+
+    /// Photons traced per pass with --integrator photon
+    #[arg(long, default_value_t = Integrator::PHOTONS_PER_PASS)]
+    photons: u32,
+
+    /// Starting photon gather radius with --integrator photon [default: a twentieth of the
+    /// largest glass or metal object]
+    #[arg(long)]
+    photon_radius: Option<Float>,
+
+    /// Intersect the camera rays of a pixel in packets of 4 (8 with the f32 feature), which
+    /// is faster but draws random numbers in a different order than without it
+    #[arg(long)]
+    packets: bool,
+
+    /// Trace the paths of several tiles together in stages (camera rays, intersection,
+    /// shading, shadow rays) instead of one at a time; with a seed the image is the same as
+    /// without it, with or without --packets
+    #[arg(long)]
+    wavefront: bool,
+
+    /// Print ray counts and timings after rendering
+    #[arg(long)]
+    stats: bool,
+
+    /// Write the render statistics as JSON to this file
+    #[arg(long)]
+    stats_json: Option<PathBuf>,
+
+    /// Render as a coordinator: hand out tiles to workers connecting to this address
+    #[arg(long, conflicts_with = "frames")]
+    listen: Option<String>,
+
+    /// Start this many worker processes on this machine [implies --listen 127.0.0.1:0]
+    #[arg(long, conflicts_with = "frames")]
+    workers: Option<usize>,
+
+    /// Run as a worker for the coordinator at this address, which sends every other option.
+    /// Scene files are read by the worker, so they need to exist at the same path on its host.
+    #[arg(long, conflicts_with_all = ["listen", "workers"])]
+    connect: Option<String>,
+}
+
+fn parse_vector(s: &str) -> Result<Vector3<Float>> {
+    let parts = s
+        .split(',')
+        .map(|p| p.trim().parse::<Float>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("invalid vector {s:?}"))?;
+    match parts[..] {
+        [x, y, z] => Ok(Vector3::new(x, y, z)),
+        _ => bail!("expected three comma separated numbers, got {s:?}"),
+    }
+}
+
+// Everything a render needs, built the same way from the same arguments in every process
+struct Setup {
+    settings: RenderSettings,
+    scene: Scene,
+    world: Bvh,
+    camera: CameraSettings,
+}
+
+impl Setup {
+    fn new(args: &Args) -> Result<Self> {
+        if let Some(seed) = args.seed {
+            seed_random(seed);
+        }
+        let mut settings = RenderSettings {
+            image_width: args.width,
+            image_height: args.height.unwrap_or((args.width as Float / 1.5) as u32),
+            samples_per_pixel: args.spp,
+            max_depth: args.max_depth,
+            seed: args.seed,
+            spectral: args.spectral,
+            integrator: match args.integrator {
+                Integrator::Photon { .. } => Integrator::Photon {
+                    photons_per_pass: args.photons,
+                    radius: args.photon_radius,
+                },
+                integrator => integrator,
+            },
+            packets: args.packets,
+            wavefront: args.wavefront,
+        };
+        if args.preview {
+            settings.image_width = (settings.image_width / 4).max(2);
+            settings.image_height = (settings.image_height / 4).max(2);
+            settings.samples_per_pixel = settings.samples_per_pixel.min(8);
+            settings.max_depth = settings.max_depth.min(8);
+        }
+        if settings.image_width < 2 || settings.image_height < 2 || settings.samples_per_pixel == 0
+        {
+            bail!("image must be at least 2x2 pixels with one or more samples per pixel");
+        }
+
+        // World
+        let mut scene = match &args.scene {
+            Some(path) => Scene::load(path)?,
+            None => Scene::random(args.spectral),
+        };
+        // Camera
+        let mut camera = scene.camera.clone();
+        if let Some(lookfrom) = args.lookfrom {
+            camera.lookfrom = Point3::new(lookfrom.x, lookfrom.y, lookfrom.z);
         }
+        if let Some(lookat) = args.lookat {
+            camera.lookat = Point3::new(lookat.x, lookat.y, lookat.z);
+        }
+        if let Some(vup) = args.vup {
+            camera.vup = vup;
+        }
+        if let Some(vfov) = args.vfov {
+            camera.vfov = Deg(vfov);
+        }
+        if let Some(aperture) = args.aperture {
+            camera.aperture = aperture;
+        }
+        if let Some(focus_dist) = args.focus_dist {
+            camera.focus_dist = focus_dist;
+        }
+        if let Some(blades) = args.aperture_blades {
+            camera.aperture_shape = Aperture::Polygon {
+                blades,
+                rotation: Deg(args.aperture_rotation),
+            };
+        }
+        if let Some(mask) = &args.aperture_mask {
+            camera.aperture_shape = Aperture::Mask(ImageTexture::load(mask)?);
+        }
+        if let Some(path) = &args.lens {
+            camera.lens = Some(Arc::new(LensSystem::load(path)?));
+        }
+        if let Some(lens) = &mut camera.lens {
+            let lens = Arc::make_mut(lens);
+            lens.film_diagonal = args.film_diagonal.unwrap_or(lens.film_diagonal);
+            lens.scale = args.lens_scale.unwrap_or(lens.scale);
+        }
+        // built once and shared by every frame, animated objects stay outside of it
+        let world = Bvh::from(mem::take(&mut scene.world));
+
+        Ok(Self {
+            settings,
+            scene,
+            world,
+            camera,
+        })
     }
-    let material1 = Dielectric::new(1.5);
-    world.add(Box::new(Sphere::new(
-        Point3::new(0., 1., 0.),
-        1.,
-        material1,
-    )));
-    let material2 = Lambertian::new(vec3(0.4, 0.2, 0.1));
-    world.add(Box::new(Sphere::new(
-        Point3::new(-4., 1., 0.),
-        1.,
-        material2,
-    )));
-    let material3 = Metal::new(vec3(0.7, 0.6, 0.5), 0.);
-    world.add(Box::new(Sphere::new(
-        Point3::new(4., 1., 0.),
-        1.,
-        material3,
-    )));
-    return world;
+}
+
+// Joins the render of a coordinator, with the options it sends instead of our own
+fn run_worker(address: &str) -> Result<()> {
+    let mut worker = Worker::connect(address)?;
+    let job = worker.job()?;
+    let setup = Args::try_parse_from(iter::once("ray-tracer".to_string()).chain(job))
+        .map_err(anyhow::Error::from)
+        .and_then(|args| Setup::new(&args));
+    let setup = match setup {
+        Ok(setup) => setup,
+        Err(err) => {
+            worker.fail(&err)?;
+            return Err(err);
+        }
+    };
+    let cam = setup
+        .scene
+        .camera_animation
+        .apply(&setup.camera, 0.)
+        .build(setup.settings.aspect_ratio())?;
+    let frame = Frame::new(&setup.world, &setup.scene.animated, 0.);
+    for warning in &frame.warnings {
+        eprintln!("warning: {warning}");
+    }
+    worker.serve(rayon::current_num_threads(), |tile| {
+        render_tile_stats(&frame, &setup.scene.lights, &cam, &setup.settings, tile)
+    })
 }
 
 fn main() -> Result<()> {
-    const ASPECT_RATIO: f64 = 3. / 2.;
-    const IMAGE_WIDTH: u32 = 1200;
-    const SAMPLES_PER_PIXEL: u32 = 500;
-    const MAX_DEPTH: i32 = 50;
-
-    let image = PPMImageWriter::new("image.ppm", IMAGE_WIDTH, ASPECT_RATIO, SAMPLES_PER_PIXEL)?;
-
-    // World
-    let world = random_scene();
-    // Camera
-    let lookfrom = Point3::new(13., 2., 3.);
-    let lookat = Point3::new(0., 0., 0.);
-    let cam = Camera::new(
-        lookfrom,
-        lookat,
-        vec3(0., 1., 0.),
-        Deg(20.).into(),
-        ASPECT_RATIO,
-        0.1,
-        10.,
+    let mut args = Args::parse();
+
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()?;
+    }
+    if let Some(address) = &args.connect {
+        return run_worker(address);
+    }
+    let format = match (args.format, args.frames) {
+        (Some(format), _) => format,
+        (None, Some(_)) => ImageFormat::Png,
+        (None, None) => ImageFormat::from_path(&args.output)?,
+    };
+    let distributed = args.listen.is_some() || args.workers.is_some();
+    if distributed && args.integrator != Integrator::Path {
+        bail!("distributed renders only support the path integrator");
+    }
+    // animations always render locally, the workers would never be started
+    if distributed && args.frames.is_some() {
+        bail!("--frames can't be combined with --listen or --workers");
+    }
+    // workers are sent our own arguments, with a seed so they all build the same scene
+    let mut job: Vec<String> = env::args().skip(1).collect();
+    if distributed && args.seed.is_none() {
+        let seed = rand::random::<u64>();
+        job.extend(["--seed".to_string(), seed.to_string()]);
+        args.seed = Some(seed);
+    }
+
+    let Setup {
+        settings,
+        scene,
+        world,
+        camera,
+    } = Setup::new(&args)?;
+    for warning in &scene.warnings {
+        eprintln!("warning: {warning}");
+    }
+
+    eprintln!(
+        "width: {}, height: {}, total: {}",
+        settings.image_width,
+        settings.image_height,
+        settings.image_height * settings.image_width
     );
+    let stats = if let Some(frames) = args.frames {
+        if frames == 0 || args.fps <= 0. {
+            bail!("an animation needs at least one frame and a positive frame rate");
+        }
+        let sequence = Sequence {
+            frames,
+            fps: args.fps,
+            dir: args.output,
+            format,
+        };
+        let camera_animation = if args.turntable {
+            CameraAnimation::turntable(frames as Float / args.fps)
+        } else {
+            scene.camera_animation
+        };
+        render_sequence(
+            &world,
+            &scene.lights,
+            &scene.animated,
+            &camera,
+            &camera_animation,
+            &settings,
+            &sequence,
+        )?
+    } else {
+        let (pixels, stats) = if distributed {
+            let address = args.listen.as_deref().unwrap_or("127.0.0.1:0");
+            let mut coordinator = Coordinator::bind(address)?;
+            if let Some(workers) = args.workers {
+                coordinator.spawn_local(&env::current_exe()?, workers)?;
+            }
+            coordinator.render(&job, &settings)?
+        } else {
+            let cam = scene
+                .camera_animation
+                .apply(&camera, 0.)
+                .build(settings.aspect_ratio())?;
+            let frame = Frame::new(&world, &scene.animated, 0.);
+            for warning in &frame.warnings {
+                eprintln!("warning: {warning}");
+            }
+            render(&frame, &scene.lights, &cam, &settings)
+        };
+        save_image(
+            &args.output,
+            format,
+            settings.image_width,
+            settings.image_height,
+            &pixels,
+        )?;
+        stats
+    };
 
-    image.write(|j, i, w| {
-        (0..w.samples_per_pixel)
-            .into_par_iter()
-            .fold(
-                || vec3(0., 0., 0.),
-                |acc, _| {
-                    let u = (i as f64 + random(0. ..1.)) / (w.image_width - 1) as f64;
-                    let v = (j as f64 + random(0. ..1.)) / (w.image_height - 1) as f64;
-                    let color = cam.get_ray(u, v).color(&world, MAX_DEPTH);
-                    acc + color
-                },
-            )
-            .sum()
-    })?;
+    if args.stats {
+        eprint!("{stats}");
+    }
+    if let Some(path) = &args.stats_json {
+        fs::write(path, stats.to_json()?)
+            .with_context(|| format!("writing stats to {}", path.display()))?;
+    }
 
-    return Ok(());
+    Ok(())
 }
@@ -0,0 +1,80 @@
+use std::sync::Arc;
+
+use cgmath::{vec3, Deg, InnerSpace, Point3, Rad, Vector3};
+
+use crate::{consts::PI, random, texture::Texture, vector_additon::VectorAdditions, Float};
+
+// Shape of the lens opening, which is the shape out of focus highlights take. Points are
+// in the unit disk, scaled by the lens radius by the camera.
+#[derive(Clone, Default)]
+pub enum Aperture {
+    #[default]
+    Circle,
+    // regular polygon inscribed in the unit circle
+    Polygon {
+        blades: u32,
+        rotation: Deg<Float>,
+    },
+    // the texture over [-1, 1]² is the transmission of the opening
+    Mask(Arc<dyn Texture>),
+}
+
+impl Aperture {
+    fn blade_vertex(k: u32, blades: u32, rotation: Deg<Float>) -> Vector3<Float> {
+        let angle = Rad::from(rotation).0 + 2. * PI * k as Float / blades as Float;
+        vec3(angle.cos(), angle.sin(), 0.)
+    }
+
+    fn transmission(texture: &dyn Texture, p: Vector3<Float>) -> Float {
+        let c = texture.value(
+            0.5 * (p.x + 1.),
+            0.5 * (p.y + 1.),
+            Point3::new(p.x, p.y, 0.),
+        );
+        ((c.x + c.y + c.z) / 3.).clamp(0., 1.)
+    }
+
+    pub fn sample(&self) -> Vector3<Float> {
+        match self {
+            Aperture::Circle => Vector3::random_in_unit_disk(),
+            Aperture::Polygon { blades, rotation } => {
+                // uniform point in the triangle between the center and a random edge
+                let k = random(0..*blades);
+                let a = Self::blade_vertex(k, *blades, *rotation);
+                let b = Self::blade_vertex(k + 1, *blades, *rotation);
+                let (mut s, mut t) = (random(0. ..1.), random(0. ..1.));
+                if s + t > 1. {
+                    s = 1. - s;
+                    t = 1. - t;
+                }
+                a * s + b * t
+            }
+            Aperture::Mask(texture) => {
+                for _ in 0..64 {
+                    let p = vec3(random(-1. ..1.), random(-1. ..1.), 0.);
+                    if random(0. ..1.) < Self::transmission(texture.as_ref(), p) {
+                        return p;
+                    }
+                }
+                vec3(0., 0., 0.)
+            }
+        }
+    }
+
+    // whether a point in the unit disk passes through the opening
+    pub fn contains(&self, p: Vector3<Float>) -> bool {
+        match self {
+            Aperture::Circle => p.magnitude2() <= 1.,
+            Aperture::Polygon { blades, rotation } => (0..*blades).all(|k| {
+                let a = Self::blade_vertex(k, *blades, *rotation);
+                let b = Self::blade_vertex(k + 1, *blades, *rotation);
+                (b - a).cross(p - a).z >= 0.
+            }),
+            Aperture::Mask(texture) => {
+                p.x.abs() <= 1.
+                    && p.y.abs() <= 1.
+                    && random(0. ..1.) < Self::transmission(texture.as_ref(), p)
+            }
+        }
+    }
+}
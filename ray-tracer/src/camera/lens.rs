@@ -0,0 +1,301 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use cgmath::{vec3, InnerSpace, Point3, Vector3};
+
+use crate::{vector_additon::VectorAdditions, Float};
+
+use super::aperture::Aperture;
+
+// One spherical interface of a lens prescription, in millimeters. Elements are listed
+// from the scene side to the film side, like in lens design tables and pbrt's .dat files.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LensElement {
+    // 0 marks the aperture stop
+    pub curvature_radius: Float,
+    // distance along the axis to the next element, or to the film for the last one
+    pub thickness: Float,
+    // index of refraction behind the interface, 0 or 1 for air
+    pub ior: Float,
+    pub aperture_radius: Float,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LensRay {
+    o: Point3<Float>,
+    d: Vector3<Float>,
+}
+
+impl LensRay {
+    fn at(&self, t: Float) -> Point3<Float> {
+        self.o + self.d * t
+    }
+
+    // camera space has +z towards the scene, lens space has the film at z = 0 and the
+    // elements at negative z
+    fn flip_z(self) -> Self {
+        Self {
+            o: Point3::new(self.o.x, self.o.y, -self.o.z),
+            d: vec3(self.d.x, self.d.y, -self.d.z),
+        }
+    }
+}
+
+fn refract(wi: Vector3<Float>, n: Vector3<Float>, eta: Float) -> Option<Vector3<Float>> {
+    let cos_i = n.dot(wi);
+    let sin2_t = eta * eta * (1. - cos_i * cos_i).max(0.);
+    if sin2_t >= 1. {
+        return None;
+    }
+    let cos_t = (1. - sin2_t).sqrt();
+    Some(-wi * eta + n * (eta * cos_i - cos_t))
+}
+
+fn intersect_spherical(
+    radius: Float,
+    z_center: Float,
+    ray: &LensRay,
+) -> Option<(Float, Vector3<Float>)> {
+    let o = ray.o - Point3::new(0., 0., z_center);
+    let a = ray.d.magnitude2();
+    let b = 2. * ray.d.dot(o);
+    let c = o.magnitude2() - radius * radius;
+    let discriminant = b * b - 4. * a * c;
+    if discriminant < 0. {
+        return None;
+    }
+    let sqrtd = discriminant.sqrt();
+    let (t0, t1) = ((-b - sqrtd) / (2. * a), (-b + sqrtd) / (2. * a));
+    let use_closer = (ray.d.z > 0.) ^ (radius < 0.);
+    let t = if use_closer { t0.min(t1) } else { t0.max(t1) };
+    if t < 0. {
+        return None;
+    }
+    let n = (o + ray.d * t).normalize();
+    let n = if n.dot(-ray.d) < 0. { -n } else { n };
+    Some((t, n))
+}
+
+// A lens prescription traced ray by ray, which gives real vignetting and distortion
+#[derive(Debug, Clone)]
+pub struct LensSystem {
+    elements: Vec<LensElement>,
+    // film diagonal in millimeters
+    pub film_diagonal: Float,
+    // scene units per millimeter
+    pub scale: Float,
+}
+
+impl LensSystem {
+    pub fn new(elements: Vec<LensElement>) -> Result<Self> {
+        if elements.is_empty() {
+            bail!("a lens needs at least one element");
+        }
+        Ok(Self {
+            elements,
+            film_diagonal: 35.,
+            scale: 0.001,
+        })
+    }
+
+    // pbrt style prescription: one "radius thickness ior aperture_diameter" row per line
+    pub fn load(path: &Path) -> Result<Self> {
+        let source =
+            fs::read_to_string(path).with_context(|| format!("reading lens {}", path.display()))?;
+        let elements = source
+            .lines()
+            .enumerate()
+            .map(|(ln, line)| (ln, line.split('#').next().unwrap_or_default()))
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(ln, line)| {
+                let values = line
+                    .split_whitespace()
+                    .map(|v| v.parse::<Float>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .with_context(|| format!("line {}", ln + 1))?;
+                match values[..] {
+                    [curvature_radius, thickness, ior, aperture] => Ok(LensElement {
+                        curvature_radius,
+                        thickness,
+                        ior,
+                        aperture_radius: aperture / 2.,
+                    }),
+                    _ => bail!("line {}: expected 4 values, found {}", ln + 1, values.len()),
+                }
+            })
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("parsing lens {}", path.display()))?;
+        Self::new(elements)
+    }
+
+    fn front_z(&self) -> Float {
+        self.elements.iter().map(|e| e.thickness).sum()
+    }
+
+    fn rear(&self) -> &LensElement {
+        self.elements.last().unwrap()
+    }
+
+    // camera space ray leaving the film towards the scene
+    fn trace_from_film(&self, ray: LensRay, aperture: &Aperture) -> Option<LensRay> {
+        let mut element_z = 0.;
+        let mut ray = ray.flip_z();
+        for (i, element) in self.elements.iter().enumerate().rev() {
+            element_z -= element.thickness;
+            let is_stop = element.curvature_radius == 0.;
+            let (t, n) = if is_stop {
+                if ray.d.z >= 0. {
+                    return None;
+                }
+                ((element_z - ray.o.z) / ray.d.z, None)
+            } else {
+                let radius = element.curvature_radius;
+                let (t, n) = intersect_spherical(radius, element_z + radius, &ray)?;
+                (t, Some(n))
+            };
+            let p = ray.at(t);
+            if is_stop {
+                let local = vec3(p.x, p.y, 0.) / element.aperture_radius;
+                if !aperture.contains(local) {
+                    return None;
+                }
+            } else if p.x * p.x + p.y * p.y > element.aperture_radius * element.aperture_radius {
+                return None;
+            }
+            ray.o = p;
+            if let Some(n) = n {
+                let eta_i = element.ior.max(1.);
+                let eta_t = if i > 0 {
+                    self.elements[i - 1].ior.max(1.)
+                } else {
+                    1.
+                };
+                ray.d = refract(-ray.d.normalize(), n, eta_i / eta_t)?;
+            }
+        }
+        Some(ray.flip_z())
+    }
+
+    // camera space ray entering the front element, towards the film
+    fn trace_from_scene(&self, ray: LensRay) -> Option<LensRay> {
+        let mut element_z = -self.front_z();
+        let mut ray = ray.flip_z();
+        for (i, element) in self.elements.iter().enumerate() {
+            let is_stop = element.curvature_radius == 0.;
+            let (t, n) = if is_stop {
+                ((element_z - ray.o.z) / ray.d.z, None)
+            } else {
+                let radius = element.curvature_radius;
+                let (t, n) = intersect_spherical(radius, element_z + radius, &ray)?;
+                (t, Some(n))
+            };
+            let p = ray.at(t);
+            if p.x * p.x + p.y * p.y > element.aperture_radius * element.aperture_radius {
+                return None;
+            }
+            ray.o = p;
+            if let Some(n) = n {
+                let eta_i = if i == 0 {
+                    1.
+                } else {
+                    self.elements[i - 1].ior.max(1.)
+                };
+                let eta_t = element.ior.max(1.);
+                ray.d = refract(-ray.d.normalize(), n, eta_i / eta_t)?;
+            }
+            element_z += element.thickness;
+        }
+        Some(ray.flip_z())
+    }
+
+    // lens space z of the principal plane and focal point, from a ray parallel to the axis
+    // and where it leaves the lens
+    fn cardinal_points(r_in: LensRay, r_out: LensRay) -> (Float, Float) {
+        let tf = -r_out.o.x / r_out.d.x;
+        let tp = (r_in.o.x - r_out.o.x) / r_out.d.x;
+        (-r_out.at(tp).z, -r_out.at(tf).z)
+    }
+
+    fn thick_lens_approximation(&self) -> Option<([Float; 2], [Float; 2])> {
+        let x = 0.001 * self.film_diagonal;
+        let scene_ray = LensRay {
+            o: Point3::new(x, 0., self.front_z() + 1.),
+            d: vec3(0., 0., -1.),
+        };
+        let (pz0, fz0) = Self::cardinal_points(scene_ray, self.trace_from_scene(scene_ray)?);
+        let film_ray = LensRay {
+            o: Point3::new(x, 0., self.rear().thickness - 1.),
+            d: vec3(0., 0., 1.),
+        };
+        let (pz1, fz1) =
+            Self::cardinal_points(film_ray, self.trace_from_film(film_ray, &Aperture::Circle)?);
+        Some(([pz0, pz1], [fz0, fz1]))
+    }
+
+    // Copy of the lens with the film moved so objects `distance` millimeters away are sharp
+    pub fn focused(&self, distance: Float) -> Result<Self> {
+        let Some((pz, fz)) = self.thick_lens_approximation() else {
+            bail!("paraxial rays do not make it through the lens");
+        };
+        let f = fz[0] - pz[0];
+        let z = -distance;
+        let c = (pz[1] - z - pz[0]) * (pz[1] - z - 4. * f - pz[0]);
+        if c < 0. {
+            bail!("cannot focus at {distance}mm, closer than the lens can focus");
+        }
+        let delta = 0.5 * (pz[1] - z + pz[0] - c.sqrt());
+        let mut lens = self.clone();
+        let rear = lens.elements.last_mut().unwrap();
+        rear.thickness += delta;
+        Ok(lens)
+    }
+
+    pub fn film_size(&self, aspect_ratio: Float) -> (Float, Float) {
+        let height = self.film_diagonal / (1. + aspect_ratio * aspect_ratio).sqrt();
+        (height * aspect_ratio, height)
+    }
+
+    // Camera space ray through the lens for film point (x, y), sampling the rear element
+    pub fn generate(
+        &self,
+        x: Float,
+        y: Float,
+        aperture: &Aperture,
+    ) -> Option<(Point3<Float>, Vector3<Float>)> {
+        let rear = self.rear();
+        let disk = Vector3::random_in_unit_disk() * rear.aperture_radius;
+        let film = Point3::new(x, y, 0.);
+        let ray = LensRay {
+            o: film,
+            d: Point3::new(disk.x, disk.y, rear.thickness) - film,
+        };
+        self.trace_from_film(ray, aperture)
+            .map(|ray| (ray.o, ray.d.normalize()))
+    }
+
+    // Fraction of rays from the film center that make it through, found on a grid over the
+    // rear element. Dividing by it keeps the image center at the brightness of a thin lens.
+    pub fn transmission(&self, aperture: &Aperture) -> Float {
+        const N: i32 = 64;
+        let rear = self.rear();
+        let (mut total, mut passed) = (0, 0);
+        for i in 0..N {
+            for j in 0..N {
+                let x = (2. * (i as Float + 0.5) / N as Float - 1.) * rear.aperture_radius;
+                let y = (2. * (j as Float + 0.5) / N as Float - 1.) * rear.aperture_radius;
+                if x * x + y * y > rear.aperture_radius * rear.aperture_radius {
+                    continue;
+                }
+                total += 1;
+                let ray = LensRay {
+                    o: Point3::new(0., 0., 0.),
+                    d: vec3(x, y, rear.thickness),
+                };
+                if self.trace_from_film(ray, aperture).is_some() {
+                    passed += 1;
+                }
+            }
+        }
+        passed as Float / total.max(1) as Float
+    }
+}
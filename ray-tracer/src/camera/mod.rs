@@ -0,0 +1,173 @@
+pub mod aperture;
+pub mod lens;
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use cgmath::{vec3, Angle, Deg, InnerSpace, Point3, Rad, Vector3};
+
+use crate::{ray::Ray, Float};
+
+use self::{aperture::Aperture, lens::LensSystem};
+
+// lens system already focused, with the film size for the image's aspect ratio
+struct RealisticLens {
+    system: LensSystem,
+    film_width: Float,
+    film_height: Float,
+}
+
+pub struct Camera {
+    origin: Point3<Float>,
+    lower_left_corner: Point3<Float>,
+    horizontal: Vector3<Float>,
+    vertical: Vector3<Float>,
+    u: Vector3<Float>,
+    v: Vector3<Float>,
+    w: Vector3<Float>,
+    lens_radius: Float,
+    aperture: Aperture,
+    lens: Option<RealisticLens>,
+    // brightness correction for light lost in a lens system
+    exposure: Float,
+}
+
+impl Camera {
+    pub fn new(
+        lookfrom: Point3<Float>,
+        lookat: Point3<Float>,
+        vup: Vector3<Float>,
+        theta: Rad<Float>,
+        aspect_ratio: Float,
+        apperature: Float,
+        focus_dist: Float,
+    ) -> Self {
+        let h = (theta / 2.).tan();
+        let viewport_height = 2. * h;
+        let viewport_width = aspect_ratio * viewport_height;
+
+        let w = (lookfrom - lookat).normalize();
+        let u = vup.cross(w).normalize();
+        let v = w.cross(u);
+
+        let horizontal = focus_dist * viewport_width * u;
+        let vertical = focus_dist * viewport_height * v;
+        let lower_left_corner = lookfrom - horizontal / 2. - vertical / 2. - focus_dist * w;
+        let lens_radius = apperature / 2.;
+
+        return Self {
+            origin: lookfrom,
+            lower_left_corner,
+            horizontal,
+            vertical,
+            u,
+            v,
+            w,
+            lens_radius,
+            aperture: Aperture::Circle,
+            lens: None,
+            exposure: 1.,
+        };
+    }
+
+    pub fn with_aperture(mut self, aperture: Aperture) -> Self {
+        self.aperture = aperture;
+        self
+    }
+
+    // Traces rays through `lens` instead of the thin lens model, vfov and aperture size are
+    // then given by the lens itself
+    pub fn with_lens(
+        mut self,
+        lens: &LensSystem,
+        focus_dist: Float,
+        aspect_ratio: Float,
+    ) -> Result<Self> {
+        let system = lens.focused(focus_dist / lens.scale)?;
+        let (film_width, film_height) = system.film_size(aspect_ratio);
+        let transmission = system.transmission(&self.aperture);
+        self.exposure = if transmission > 0. {
+            1. / transmission
+        } else {
+            1.
+        };
+        self.lens = Some(RealisticLens {
+            system,
+            film_width,
+            film_height,
+        });
+        Ok(self)
+    }
+
+    pub fn exposure(&self) -> Float {
+        self.exposure
+    }
+
+    // None when a lens system blocks the ray
+    pub fn get_ray(&self, s: Float, t: Float) -> Option<Ray> {
+        if let Some(lens) = &self.lens {
+            // the image on the film is upside down
+            let x = -(s - 0.5) * lens.film_width;
+            let y = -(t - 0.5) * lens.film_height;
+            let (o, d) = lens.system.generate(x, y, &self.aperture)?;
+            let to_world = |v: Vector3<Float>| self.u * v.x + self.v * v.y - self.w * v.z;
+            return Some(Ray::new(
+                self.origin + to_world(vec3(o.x, o.y, o.z)) * lens.system.scale,
+                to_world(d),
+            ));
+        }
+        let rd = self.lens_radius * self.aperture.sample();
+        let offset = self.u * rd.x + self.v * rd.y;
+        return Some(Ray::new(
+            self.origin + offset,
+            self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
+        ));
+    }
+}
+
+// Everything needed to build a `Camera` once the image aspect ratio is known
+#[derive(Clone)]
+pub struct CameraSettings {
+    pub lookfrom: Point3<Float>,
+    pub lookat: Point3<Float>,
+    pub vup: Vector3<Float>,
+    pub vfov: Deg<Float>,
+    pub aperture: Float,
+    pub focus_dist: Float,
+    pub aperture_shape: Aperture,
+    pub lens: Option<Arc<LensSystem>>,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            lookfrom: Point3::new(13., 2., 3.),
+            lookat: Point3::new(0., 0., 0.),
+            vup: vec3(0., 1., 0.),
+            vfov: Deg(20.),
+            aperture: 0.1,
+            focus_dist: 10.,
+            aperture_shape: Aperture::Circle,
+            lens: None,
+        }
+    }
+}
+
+impl CameraSettings {
+    pub fn build(&self, aspect_ratio: Float) -> Result<Camera> {
+        let camera = Camera::new(
+            self.lookfrom,
+            self.lookat,
+            self.vup,
+            self.vfov.into(),
+            aspect_ratio,
+            self.aperture,
+            self.focus_dist,
+        )
+        .with_aperture(self.aperture_shape.clone());
+        match &self.lens {
+            Some(lens) => camera.with_lens(lens, self.focus_dist, aspect_ratio),
+            None => Ok(camera),
+        }
+    }
+}
@@ -0,0 +1,428 @@
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    path::Path,
+    process::{Child, Command},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use cgmath::vec3;
+use rayon::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    render::{assemble, report_progress, RenderSettings, RenderedTile, Tile},
+    stats::{Counters, RenderStats, TileStats},
+    Color, Float,
+};
+
+// Messages are single lines of JSON in both directions
+#[derive(Debug, Serialize, Deserialize)]
+enum ToWorker {
+    // command line arguments that reproduce the coordinator's scene, camera and settings
+    Job { args: Vec<String> },
+    Render { tiles: Vec<Tile> },
+    Done,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum ToCoordinator {
+    Ready {
+        threads: usize,
+    },
+    Tile {
+        stats: TileStats,
+        counters: Counters,
+        pixels: Vec<[Float; 3]>,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> Result<Self> {
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        })
+    }
+
+    fn send<T: Serialize>(&mut self, message: &T) -> Result<()> {
+        let mut line = serde_json::to_vec(message)?;
+        line.push(b'\n');
+        self.writer.write_all(&line)?;
+        Ok(())
+    }
+
+    fn receive<T: DeserializeOwned>(&mut self) -> Result<T> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            bail!("connection closed");
+        }
+        Ok(serde_json::from_str(&line)?)
+    }
+}
+
+// Hands out tiles to worker processes and merges what they send back. Workers render with
+// the seed of the job, so the image is the same as a local render no matter which worker
+// rendered which tile.
+pub struct Coordinator {
+    listener: TcpListener,
+    children: Vec<Child>,
+}
+
+impl Coordinator {
+    pub fn bind(addr: impl ToSocketAddrs) -> Result<Self> {
+        let listener = TcpListener::bind(addr).context("starting the coordinator")?;
+        Ok(Self {
+            listener,
+            children: vec![],
+        })
+    }
+
+    pub fn address(&self) -> Result<String> {
+        Ok(self.listener.local_addr()?.to_string())
+    }
+
+    // Starts `count` worker processes of `exe` on this machine, connected with `--connect`
+    pub fn spawn_local(&mut self, exe: &Path, count: usize) -> Result<()> {
+        let address = self.address()?;
+        for _ in 0..count {
+            let child = Command::new(exe)
+                .arg("--connect")
+                .arg(&address)
+                .spawn()
+                .with_context(|| format!("starting worker {}", exe.display()))?;
+            self.children.push(child);
+        }
+        Ok(())
+    }
+
+    // Renders `settings.tiles()` on every worker that connects until all tiles are back
+    pub fn render(
+        mut self,
+        args: &[String],
+        settings: &RenderSettings,
+    ) -> Result<(Vec<Color>, RenderStats)> {
+        if settings.seed.is_none() {
+            bail!("distributed renders need a seed so every worker traces the same scene");
+        }
+        let start = Instant::now();
+        let tiles = settings.tiles();
+        let total = tiles.len();
+        let queue = Mutex::new(VecDeque::from(tiles));
+        let rendered = Mutex::new(Vec::with_capacity(total));
+        let active = AtomicUsize::new(0);
+        let finished = || rendered.lock().unwrap().len() == total;
+
+        self.listener.set_nonblocking(true)?;
+        eprintln!("waiting for workers on {}", self.address()?);
+        thread::scope(|scope| -> Result<()> {
+            while !finished() {
+                match self.listener.accept() {
+                    Ok((stream, peer)) => {
+                        stream.set_nonblocking(false)?;
+                        active.fetch_add(1, Ordering::SeqCst);
+                        let (queue, rendered, active) = (&queue, &rendered, &active);
+                        scope.spawn(move || {
+                            let result = Connection::new(stream)
+                                .and_then(|conn| serve_worker(conn, args, queue, rendered, total));
+                            if let Err(err) = result {
+                                eprintln!("\nworker {peer}: {err:#}");
+                            }
+                            active.fetch_sub(1, Ordering::SeqCst);
+                        });
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(20));
+                    }
+                    Err(err) => return Err(err.into()),
+                }
+                if !self.children.is_empty() && active.load(Ordering::SeqCst) == 0 {
+                    let exited = self
+                        .children
+                        .iter_mut()
+                        .all(|child| matches!(child.try_wait(), Ok(Some(_))));
+                    if exited && !finished() {
+                        bail!("all local workers exited before the render was done");
+                    }
+                }
+            }
+            Ok(())
+        })?;
+        eprintln!("\nDone. ");
+
+        for child in self.children.iter_mut() {
+            child.wait()?;
+        }
+        Ok(assemble(
+            settings,
+            rendered.into_inner().unwrap(),
+            start.elapsed(),
+        ))
+    }
+}
+
+impl Drop for Coordinator {
+    fn drop(&mut self) {
+        for child in self.children.iter_mut() {
+            if let Ok(None) = child.try_wait() {
+                let _ = child.kill();
+                let _ = child.wait();
+            }
+        }
+    }
+}
+
+// Feeds one worker batches of tiles. Tiles of a batch that does not come back go back in the
+// queue for the other workers.
+fn serve_worker(
+    mut conn: Connection,
+    args: &[String],
+    queue: &Mutex<VecDeque<Tile>>,
+    rendered: &Mutex<Vec<RenderedTile>>,
+    total: usize,
+) -> Result<()> {
+    conn.send(&ToWorker::Job {
+        args: args.to_vec(),
+    })?;
+    let threads = match conn.receive()? {
+        ToCoordinator::Ready { threads } => threads.max(1),
+        ToCoordinator::Failed { error } => bail!("{error}"),
+        message => bail!("unexpected message {message:?}"),
+    };
+    loop {
+        let batch: Vec<Tile> = {
+            let mut queue = queue.lock().unwrap();
+            let n = threads.min(queue.len());
+            queue.drain(..n).collect()
+        };
+        if batch.is_empty() {
+            if rendered.lock().unwrap().len() == total {
+                conn.send(&ToWorker::Done)?;
+                return Ok(());
+            }
+            // the last tiles are still out, wait in case their worker fails
+            thread::sleep(Duration::from_millis(20));
+            continue;
+        }
+        let mut pending = batch.clone();
+        let result = (|| -> Result<()> {
+            conn.send(&ToWorker::Render { tiles: batch })?;
+            while !pending.is_empty() {
+                let (stats, counters, pixels) = match conn.receive()? {
+                    ToCoordinator::Tile {
+                        stats,
+                        counters,
+                        pixels,
+                    } => (stats, counters, pixels),
+                    ToCoordinator::Failed { error } => bail!("{error}"),
+                    message => bail!("unexpected message {message:?}"),
+                };
+                let Some(i) = pending
+                    .iter()
+                    .position(|t| (t.x, t.y) == (stats.x, stats.y))
+                else {
+                    bail!(
+                        "received tile ({}, {}) that was not asked for",
+                        stats.x,
+                        stats.y
+                    );
+                };
+                let tile = pending.swap_remove(i);
+                if pixels.len() != (tile.width * tile.height) as usize {
+                    bail!("tile ({}, {}) has {} pixels", tile.x, tile.y, pixels.len());
+                }
+                let mut rendered = rendered.lock().unwrap();
+                rendered.push(RenderedTile {
+                    pixels: pixels.into_iter().map(|[r, g, b]| vec3(r, g, b)).collect(),
+                    stats,
+                    counters,
+                });
+                report_progress(rendered.len() as u32, total);
+            }
+            Ok(())
+        })();
+        if let Err(err) = result {
+            queue.lock().unwrap().extend(pending);
+            return Err(err);
+        }
+    }
+}
+
+// The worker side of a connection to a `Coordinator`
+pub struct Worker {
+    conn: Connection,
+}
+
+impl Worker {
+    // Connects to the coordinator, retrying for a few seconds while it starts up
+    pub fn connect(addr: &str) -> Result<Self> {
+        let mut attempts = 0;
+        let stream = loop {
+            match TcpStream::connect(addr) {
+                Ok(stream) => break stream,
+                Err(_) if attempts < 50 => {
+                    attempts += 1;
+                    thread::sleep(Duration::from_millis(100));
+                }
+                Err(err) => {
+                    return Err(err).with_context(|| format!("connecting to {addr}"));
+                }
+            }
+        };
+        Ok(Self {
+            conn: Connection::new(stream)?,
+        })
+    }
+
+    // The command line arguments of the render to join
+    pub fn job(&mut self) -> Result<Vec<String>> {
+        match self.conn.receive()? {
+            ToWorker::Job { args } => Ok(args),
+            message => Err(anyhow!("expected a job, got {message:?}")),
+        }
+    }
+
+    // Tells the coordinator this worker cannot take part, for example when the scene is missing
+    pub fn fail(mut self, error: &anyhow::Error) -> Result<()> {
+        self.conn.send(&ToCoordinator::Failed {
+            error: format!("{error:#}"),
+        })
+    }
+
+    // Renders the tiles the coordinator asks for, `threads` at a time, until it is done
+    pub fn serve(
+        mut self,
+        threads: usize,
+        render: impl Fn(Tile) -> RenderedTile + Sync,
+    ) -> Result<()> {
+        self.conn.send(&ToCoordinator::Ready { threads })?;
+        loop {
+            match self.conn.receive()? {
+                ToWorker::Render { tiles } => {
+                    let rendered: Vec<RenderedTile> = tiles.into_par_iter().map(&render).collect();
+                    for tile in rendered {
+                        self.conn.send(&ToCoordinator::Tile {
+                            stats: tile.stats,
+                            counters: tile.counters,
+                            pixels: tile.pixels.iter().map(|c| [c.x, c.y, c.z]).collect(),
+                        })?;
+                    }
+                }
+                ToWorker::Done => return Ok(()),
+                message => bail!("unexpected message {message:?}"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::Point3;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        camera::CameraSettings,
+        material::{Dielectric, Lambertian, Metal},
+        ray::hittable::{HittableList, Sphere},
+        render::{render, render_tile_stats, Integrator},
+    };
+
+    fn scene() -> HittableList {
+        let mut world = HittableList::default();
+        let ground = Lambertian::new(vec3(0.5, 0.5, 0.5));
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0., -1000., 0.),
+            1000.,
+            ground,
+        )));
+        let glass = Dielectric::new(1.5);
+        world.add(Arc::new(Sphere::new(Point3::new(0., 1., 0.), 1., glass)));
+        let steel = Metal::new(vec3(0.7, 0.6, 0.5), 0.1);
+        world.add(Arc::new(Sphere::new(Point3::new(4., 1., 0.), 1., steel)));
+        world
+    }
+
+    fn settings() -> RenderSettings {
+        // not a multiple of the tile size, so some tiles are partial
+        RenderSettings {
+            image_width: 40,
+            image_height: 27,
+            samples_per_pixel: 4,
+            max_depth: 5,
+            seed: Some(7),
+            spectral: false,
+            integrator: Integrator::Path,
+            packets: false,
+            wavefront: false,
+        }
+    }
+
+    #[test]
+    fn test_loopback_matches_local_render() {
+        let world = scene();
+        let settings = settings();
+        let cam = CameraSettings::default()
+            .build(settings.aspect_ratio())
+            .unwrap();
+        let (local, _) = render(&world, &[], &cam, &settings);
+
+        let coordinator = Coordinator::bind("127.0.0.1:0").unwrap();
+        let address = coordinator.address().unwrap();
+        let args = vec!["--seed".to_string(), "7".to_string()];
+        let (distributed, stats) = thread::scope(|scope| {
+            // One worker that can't take part and two that render the tiles between them.
+            // A worker the coordinator only gets to after the last tile is back sees the
+            // connection close instead of a job.
+            scope.spawn(|| {
+                let mut worker = Worker::connect(&address).unwrap();
+                if let Ok(job) = worker.job() {
+                    assert_eq!(job, args);
+                    worker.fail(&anyhow!("no scene here")).unwrap();
+                }
+            });
+            for threads in [1, 3] {
+                let (world, cam, address) = (&world, &cam, &address);
+                scope.spawn(move || {
+                    let mut worker = Worker::connect(address).unwrap();
+                    if worker.job().is_ok() {
+                        worker
+                            .serve(threads, |tile| {
+                                render_tile_stats(world, &[], cam, &settings, tile)
+                            })
+                            .unwrap();
+                    }
+                });
+            }
+            coordinator.render(&args, &settings).unwrap()
+        });
+        assert_eq!(stats.tiles.len(), settings.tiles().len());
+        assert!(local == distributed, "distributed render differs");
+    }
+
+    #[test]
+    fn test_needs_a_seed() {
+        let coordinator = Coordinator::bind("127.0.0.1:0").unwrap();
+        let settings = RenderSettings {
+            seed: None,
+            ..settings()
+        };
+        assert!(coordinator.render(&[], &settings).is_err());
+    }
+}
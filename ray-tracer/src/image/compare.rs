@@ -0,0 +1,201 @@
+use std::fmt::Display;
+
+use anyhow::{bail, Result};
+use cgmath::{vec3, InnerSpace};
+
+use crate::{material::luminance, Color, Float};
+
+// Differences between two images of display values in the 0..1 range
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Metrics {
+    pub rmse: Float,
+    // in decibels, infinite for identical images
+    pub psnr: Float,
+    // mean structural similarity of the luma, 1 for identical images
+    pub ssim: Float,
+}
+
+impl Display for Metrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:<6}{:>12.6}", "rmse", self.rmse)?;
+        writeln!(f, "{:<6}{:>12.3} dB", "psnr", self.psnr)?;
+        writeln!(f, "{:<6}{:>12.6}", "ssim", self.ssim)
+    }
+}
+
+// Gaussian blur with σ = 1.5 over an 11x11 window, renormalized at the borders
+fn blur(width: usize, height: usize, values: &[Float]) -> Vec<Float> {
+    const RADIUS: isize = 5;
+    let weights: Vec<Float> = (-RADIUS..=RADIUS)
+        .map(|i| (-(i * i) as Float / (2. * 1.5 * 1.5)).exp())
+        .collect();
+    let pass = |values: &[Float], step: (usize, usize)| -> Vec<Float> {
+        let mut out = vec![0.; values.len()];
+        for y in 0..height {
+            for x in 0..width {
+                let (mut sum, mut total) = (0., 0.);
+                for (k, w) in (-RADIUS..=RADIUS).zip(&weights) {
+                    let (sx, sy) = (
+                        x as isize + k * step.0 as isize,
+                        y as isize + k * step.1 as isize,
+                    );
+                    if sx < 0 || sy < 0 || sx >= width as isize || sy >= height as isize {
+                        continue;
+                    }
+                    sum += w * values[sy as usize * width + sx as usize];
+                    total += w;
+                }
+                out[y * width + x] = sum / total;
+            }
+        }
+        out
+    };
+    pass(&pass(values, (1, 0)), (0, 1))
+}
+
+// SSIM of Wang et al. 2004 on the luma of `a` and `b`
+fn ssim(width: usize, height: usize, a: &[Color], b: &[Color]) -> Float {
+    const C1: Float = 0.01 * 0.01;
+    const C2: Float = 0.03 * 0.03;
+    let x: Vec<Float> = a.iter().map(|&c| luminance(c)).collect();
+    let y: Vec<Float> = b.iter().map(|&c| luminance(c)).collect();
+    let product =
+        |p: &[Float], q: &[Float]| -> Vec<Float> { p.iter().zip(q).map(|(p, q)| p * q).collect() };
+    let mu_x = blur(width, height, &x);
+    let mu_y = blur(width, height, &y);
+    let xx = blur(width, height, &product(&x, &x));
+    let yy = blur(width, height, &product(&y, &y));
+    let xy = blur(width, height, &product(&x, &y));
+    let total: Float = (0..x.len())
+        .map(|i| {
+            let (mx, my) = (mu_x[i], mu_y[i]);
+            let var_x = xx[i] - mx * mx;
+            let var_y = yy[i] - my * my;
+            let cov = xy[i] - mx * my;
+            ((2. * mx * my + C1) * (2. * cov + C2))
+                / ((mx * mx + my * my + C1) * (var_x + var_y + C2))
+        })
+        .sum();
+    total / x.len() as Float
+}
+
+// Compares two images of display values in row major order
+pub fn metrics(width: u32, height: u32, a: &[Color], b: &[Color]) -> Result<Metrics> {
+    let len = (width * height) as usize;
+    if a.len() != len || b.len() != len {
+        bail!(
+            "both images need {len} pixels, found {} and {}",
+            a.len(),
+            b.len()
+        );
+    }
+    let squared: Float = a.iter().zip(b).map(|(a, b)| (a - b).magnitude2()).sum();
+    let rmse = (squared / (3 * len) as Float).sqrt();
+    Ok(Metrics {
+        rmse,
+        psnr: -20. * rmse.log10(),
+        ssim: ssim(width as usize, height as usize, a, b),
+    })
+}
+
+// black, blue, cyan, yellow, red
+const RAMP: [[Float; 3]; 5] = [
+    [0., 0., 0.],
+    [0., 0., 1.],
+    [0., 1., 1.],
+    [1., 1., 0.],
+    [1., 0., 0.],
+];
+
+fn ramp(t: Float) -> Color {
+    let x = t.clamp(0., 1.) * (RAMP.len() - 1) as Float;
+    let i = (x as usize).min(RAMP.len() - 2);
+    let f = x - i as Float;
+    let [a, b] = [RAMP[i], RAMP[i + 1]].map(|[r, g, b]| vec3(r, g, b));
+    a + (b - a) * f
+}
+
+// Per pixel mean absolute difference colored from black (none) to red (`scale` or more),
+// as linear colors for `save_image`. Without a scale the largest difference is red.
+// Returns the scale used.
+pub fn false_color(a: &[Color], b: &[Color], scale: Option<Float>) -> (Vec<Color>, Float) {
+    let errors: Vec<Float> = a
+        .iter()
+        .zip(b)
+        .map(|(a, b)| ((a.x - b.x).abs() + (a.y - b.y).abs() + (a.z - b.z).abs()) / 3.)
+        .collect();
+    let scale = scale.unwrap_or_else(|| errors.iter().copied().fold(0., Float::max));
+    let scale = if scale > 0. { scale } else { 1. };
+    let pixels = errors
+        .iter()
+        .map(|e| ramp(e / scale).map(|c| c * c))
+        .collect();
+    (pixels, scale)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn gradient(width: u32, height: u32) -> Vec<Color> {
+        (0..width * height)
+            .map(|i| {
+                let (x, y) = ((i % width) as Float, (i / width) as Float);
+                vec3(x / width as Float, y / height as Float, 0.5)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_identical() {
+        let a = gradient(16, 12);
+        let m = metrics(16, 12, &a, &a).unwrap();
+        assert_eq!(m.rmse, 0.);
+        assert_eq!(m.psnr, Float::INFINITY);
+        assert!((m.ssim - 1.).abs() < 1e-6, "{}", m.ssim);
+    }
+
+    #[test]
+    fn test_offset() {
+        let a = gradient(16, 12);
+        let b: Vec<Color> = a.iter().map(|c| c + vec3(0.1, 0.1, 0.1)).collect();
+        let m = metrics(16, 12, &a, &b).unwrap();
+        assert!((m.rmse - 0.1).abs() < 1e-5, "{}", m.rmse);
+        assert!((m.psnr - 20.).abs() < 1e-3, "{}", m.psnr);
+        assert!(m.ssim < 1.);
+    }
+
+    #[test]
+    fn test_size_mismatch() {
+        let a = gradient(16, 12);
+        assert!(metrics(16, 12, &a, &a[1..]).is_err());
+        assert!(metrics(16, 13, &a, &a).is_err());
+    }
+
+    #[test]
+    fn test_blur_constant() {
+        let values = vec![0.25; 7 * 5];
+        for v in blur(7, 5, &values) {
+            assert!((v - 0.25).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_false_color() {
+        let a = vec![vec3(0., 0., 0.); 3];
+        let b = vec![vec3(0., 0., 0.), vec3(0.1, 0.1, 0.1), vec3(0.4, 0.4, 0.4)];
+        let (pixels, scale) = false_color(&a, &b, None);
+        assert!((scale - 0.4).abs() < 1e-6);
+        assert_eq!(pixels[0], vec3(0., 0., 0.));
+        assert_eq!(pixels[2], vec3(1., 0., 0.));
+
+        // errors past a given scale stay red
+        let (pixels, scale) = false_color(&a, &b, Some(0.1));
+        assert_eq!(scale, 0.1);
+        assert_eq!(pixels[1], vec3(1., 0., 0.));
+        assert_eq!(pixels[2], vec3(1., 0., 0.));
+
+        let (pixels, _) = false_color(&a, &a, None);
+        assert!(pixels.iter().all(|&c| c == vec3(0., 0., 0.)));
+    }
+}
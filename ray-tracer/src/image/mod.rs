@@ -1,26 +1,32 @@
-use anyhow::Result;
+pub mod compare;
+pub mod tonemap;
+
+use anyhow::{anyhow, bail, Context, Result};
+use cgmath::vec3;
 use rayon::prelude::*;
 use std::{
-    fs::{File, OpenOptions},
-    io::Write,
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Write},
+    path::Path,
+    str::FromStr,
     sync::atomic::AtomicU32,
 };
 
-use crate::Color;
+use crate::{Color, Float};
 
 pub struct PPMImageWriter {
     file: File,
     pub image_width: u32,
     pub image_height: u32,
     pub samples_per_pixel: u32,
-    pub aspect_ratio: f64,
+    pub aspect_ratio: Float,
 }
 
 impl PPMImageWriter {
     pub fn new(
         file_name: &str,
         image_width: u32,
-        aspect_ratio: f64,
+        aspect_ratio: Float,
         samples_per_pixel: u32,
     ) -> Result<Self> {
         let file = OpenOptions::new()
@@ -31,7 +37,7 @@ impl PPMImageWriter {
 
         return Ok(Self {
             file,
-            image_height: (image_width as f64 / aspect_ratio) as u32,
+            image_height: (image_width as Float / aspect_ratio) as u32,
             image_width,
             aspect_ratio,
             samples_per_pixel,
@@ -39,7 +45,7 @@ impl PPMImageWriter {
     }
 
     fn write_color(&mut self, color: Color) -> Result<()> {
-        let scale = 1. / self.samples_per_pixel as f64;
+        let scale = 1. / self.samples_per_pixel as Float;
         let r = (256. * ((scale * color.x).sqrt().clamp(0., 0.999))) as u32;
         let g = (256. * ((scale * color.y).sqrt().clamp(0., 0.999))) as u32;
         let b = (256. * ((scale * color.z).sqrt().clamp(0., 0.999))) as u32;
@@ -75,7 +81,8 @@ impl PPMImageWriter {
                 let prev = counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
                 eprint!(
                     "\r{:.2}%",
-                    (((prev + 1) as f64 / (self.image_width * self.image_height) as f64) * 100.)
+                    (((prev + 1) as Float / (self.image_width * self.image_height) as Float)
+                        * 100.)
                 );
                 std::io::stderr().flush().unwrap();
                 res
@@ -97,6 +104,180 @@ impl PPMImageWriter {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Ppm,
+    Png,
+    // portable float map, keeps the linear colors without clamping
+    Pfm,
+}
+
+impl FromStr for ImageFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "ppm" => Ok(Self::Ppm),
+            "png" => Ok(Self::Png),
+            "pfm" => Ok(Self::Pfm),
+            _ => Err(anyhow!(
+                "unsupported image format {s:?}, expected ppm, png or pfm"
+            )),
+        }
+    }
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Ppm => "ppm",
+            Self::Png => "png",
+            Self::Pfm => "pfm",
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Result<Self> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) => ext.parse(),
+            None => bail!("cannot infer image format of {}", path.display()),
+        }
+    }
+}
+
+// gamma 2 encoding, matching `PPMImageWriter`
+pub fn to_rgb8(color: Color) -> [u8; 3] {
+    let channel = |c: Float| (256. * c.sqrt().clamp(0., 0.999)) as u8;
+    [channel(color.x), channel(color.y), channel(color.z)]
+}
+
+// Writes averaged linear colors in row major order from the top row
+pub fn save_image(
+    path: &Path,
+    format: ImageFormat,
+    width: u32,
+    height: u32,
+    pixels: &[Color],
+) -> Result<()> {
+    if pixels.len() != (width * height) as usize {
+        bail!(
+            "expected {} pixels for a {width}x{height} image, got {}",
+            width * height,
+            pixels.len()
+        );
+    }
+    match format {
+        ImageFormat::Ppm => {
+            let mut file = BufWriter::new(File::create(path)?);
+            writeln!(file, "P3\n{} {}\n255", width, height)?;
+            for color in pixels {
+                let [r, g, b] = to_rgb8(*color);
+                writeln!(file, "{} {} {}", r, g, b)?;
+            }
+            file.flush()?;
+        }
+        ImageFormat::Png => {
+            let buf = pixels.iter().flat_map(|c| to_rgb8(*c)).collect();
+            ::image::RgbImage::from_raw(width, height, buf)
+                .ok_or_else(|| anyhow!("pixel buffer does not match image size"))?
+                .save_with_format(path, ::image::ImageFormat::Png)?;
+        }
+        ImageFormat::Pfm => {
+            // little endian, rows from the bottom up
+            let mut file = BufWriter::new(File::create(path)?);
+            write!(file, "PF\n{} {}\n-1.0\n", width, height)?;
+            for row in pixels.chunks(width as usize).rev() {
+                for color in row {
+                    for c in [color.x, color.y, color.z] {
+                        #[allow(clippy::unnecessary_cast)]
+                        file.write_all(&(c as f32).to_le_bytes())?;
+                    }
+                }
+            }
+            file.flush()?;
+        }
+    }
+    Ok(())
+}
+
+// Reads a color PFM into linear colors in row major order from the top row
+fn load_pfm(bytes: &[u8]) -> Result<(u32, u32, Vec<Color>)> {
+    // the header is three whitespace separated tokens after the magic, then a single
+    // whitespace character before the data
+    let mut fields = vec![];
+    let mut pos = 0;
+    while fields.len() < 4 {
+        while bytes.get(pos).is_some_and(u8::is_ascii_whitespace) {
+            pos += 1;
+        }
+        let start = pos;
+        while bytes.get(pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+            pos += 1;
+        }
+        if start == pos {
+            bail!("truncated header");
+        }
+        fields.push(std::str::from_utf8(&bytes[start..pos])?);
+    }
+    let data = &bytes[pos + 1..];
+    if fields[0] != "PF" {
+        bail!(
+            "only color PFM images (PF) are supported, found {:?}",
+            fields[0]
+        );
+    }
+    let width: u32 = fields[1].parse().context("width")?;
+    let height: u32 = fields[2].parse().context("height")?;
+    let scale: Float = fields[3].parse().context("scale")?;
+    let len = (width * height) as usize;
+    if data.len() < len * 12 {
+        bail!(
+            "expected {} bytes of pixels, found {}",
+            len * 12,
+            data.len()
+        );
+    }
+    let value = |i: usize| {
+        let b = [
+            data[4 * i],
+            data[4 * i + 1],
+            data[4 * i + 2],
+            data[4 * i + 3],
+        ];
+        let v = if scale < 0. {
+            f32::from_le_bytes(b)
+        } else {
+            f32::from_be_bytes(b)
+        };
+        v as Float * scale.abs()
+    };
+    let mut pixels = Vec::with_capacity(len);
+    for y in (0..height as usize).rev() {
+        for x in 0..width as usize {
+            let i = 3 * (y * width as usize + x);
+            pixels.push(vec3(value(i), value(i + 1), value(i + 2)));
+        }
+    }
+    Ok((width, height, pixels))
+}
+
+// Reads an image as linear colors in row major order from the top row. PNG, JPEG and PPM
+// files are taken to be gamma 2 encoded like the ones `save_image` writes.
+pub fn load_image(path: &Path) -> Result<(u32, u32, Vec<Color>)> {
+    let load = || -> Result<_> {
+        if ImageFormat::from_path(path).ok() == Some(ImageFormat::Pfm) {
+            return load_pfm(&fs::read(path)?);
+        }
+        let img = ::image::open(path)?.into_rgb8();
+        let channel = |c: u8| (c as Float / 255.).powi(2);
+        let pixels = img
+            .pixels()
+            .map(|p| vec3(channel(p[0]), channel(p[1]), channel(p[2])))
+            .collect();
+        Ok((img.width(), img.height(), pixels))
+    };
+    load().with_context(|| format!("reading image {}", path.display()))
+}
+
 #[cfg(test)]
 mod test {
     use cgmath::vec3;
@@ -109,8 +290,8 @@ mod test {
         assert!(writer
             .write(|j, i, writer| {
                 return vec3(
-                    (i as f64) / (writer.image_width - 1) as f64,
-                    (j as f64) / (writer.image_height - 1) as f64,
+                    (i as Float) / (writer.image_width - 1) as Float,
+                    (j as Float) / (writer.image_height - 1) as Float,
                     0.25,
                 );
             })
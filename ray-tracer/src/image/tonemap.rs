@@ -0,0 +1,107 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use cgmath::vec3;
+
+use crate::{material::luminance, Color, Float};
+
+// Maps linear scene colors into the displayable 0..1 range
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMap {
+    // scales by the exposure and clips
+    Exposure,
+    // extended Reinhard on luminance, colors as bright as `white` map to 1
+    Reinhard { white: Option<Float> },
+    // Narkowicz's fit of the ACES filmic curve
+    Aces,
+}
+
+impl FromStr for ToneMap {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "exposure" => Ok(Self::Exposure),
+            "reinhard" => Ok(Self::Reinhard { white: None }),
+            "aces" => Ok(Self::Aces),
+            _ => Err(anyhow!(
+                "unknown tone mapping operator {s:?}, expected exposure, reinhard or aces"
+            )),
+        }
+    }
+}
+
+impl ToneMap {
+    // `exposure` is in stops and applied before the operator
+    pub fn apply(&self, color: Color, exposure: Float) -> Color {
+        let color = color * Float::powf(2., exposure);
+        let mapped = match *self {
+            ToneMap::Exposure => color,
+            ToneMap::Reinhard { white } => {
+                let l = luminance(color);
+                if l <= 0. {
+                    return vec3(0., 0., 0.);
+                }
+                let white2 = white.map_or(Float::INFINITY, |w| w * w);
+                color * ((1. + l / white2) / (1. + l))
+            }
+            ToneMap::Aces => {
+                let curve = |x: Float| (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+                vec3(curve(color.x), curve(color.y), curve(color.z))
+            }
+        };
+        mapped.map(|c| c.clamp(0., 1.))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("exposure".parse::<ToneMap>().unwrap(), ToneMap::Exposure);
+        assert_eq!(
+            "Reinhard".parse::<ToneMap>().unwrap(),
+            ToneMap::Reinhard { white: None }
+        );
+        assert_eq!("ACES".parse::<ToneMap>().unwrap(), ToneMap::Aces);
+        assert!("filmic".parse::<ToneMap>().is_err());
+    }
+
+    #[test]
+    fn test_exposure() {
+        let map = ToneMap::Exposure;
+        assert_eq!(map.apply(vec3(0.25, 0.5, 2.), 0.), vec3(0.25, 0.5, 1.));
+        assert_eq!(map.apply(vec3(0.25, 0.5, 2.), 1.), vec3(0.5, 1., 1.));
+        assert_eq!(map.apply(vec3(-1., 0., 0.), 0.), vec3(0., 0., 0.));
+    }
+
+    #[test]
+    fn test_reinhard() {
+        let map = ToneMap::Reinhard { white: Some(4.) };
+        let white = map.apply(vec3(4., 4., 4.), 0.);
+        for c in [white.x, white.y, white.z] {
+            assert!((c - 1.).abs() < 1e-5, "{white:?}");
+        }
+        assert_eq!(map.apply(vec3(0., 0., 0.), 0.), vec3(0., 0., 0.));
+        // without a white point bright colors approach 1
+        let map = ToneMap::Reinhard { white: None };
+        let bright = map.apply(vec3(100., 100., 100.), 0.);
+        assert!(bright.x < 1. && bright.x > 0.98);
+    }
+
+    #[test]
+    fn test_aces() {
+        let map = ToneMap::Aces;
+        assert_eq!(map.apply(vec3(0., 0., 0.), 0.), vec3(0., 0., 0.));
+        let mut last = 0.;
+        for i in 1..=200 {
+            let c = map.apply(vec3(1., 1., 1.), i as Float / 20. - 5.).x;
+            assert!((0. ..=1.).contains(&c));
+            assert!(c >= last, "not monotonic at {i}");
+            last = c;
+        }
+        assert!(last > 0.99);
+    }
+}
@@ -1,17 +1,82 @@
-use cgmath::Vector3;
+use std::cell::RefCell;
+
+use cgmath::{BaseFloat, Vector3};
 use rand::{
     distributions::uniform::{SampleRange, SampleUniform},
-    thread_rng, Rng,
+    rngs::StdRng,
+    Rng, SeedableRng,
 };
 
+pub mod animation;
 pub mod camera;
+pub mod distributed;
 pub mod image;
+pub mod light;
 pub mod material;
+pub mod photon;
 pub mod ray;
+pub mod render;
+pub mod scene;
+pub mod spectral;
+pub mod stats;
+pub mod texture;
 pub mod vector_additon;
+pub mod wavefront;
+
+// Precision the geometry core (`Ray`, `Aabb`, `RayPacket` and the tests in
+// `ray::intersect`) is generic over
+pub trait Real: BaseFloat + Default + Send + Sync {
+    // rays per packet, as many as fit in a 256 bit vector register
+    const LANES: usize = 32 / std::mem::size_of::<Self>();
 
-pub type Color = Vector3<f64>;
+    // for constants in generic code
+    fn of(x: f64) -> Self;
+}
+
+impl Real for f32 {
+    fn of(x: f64) -> Self {
+        x as f32
+    }
+}
+
+impl Real for f64 {
+    fn of(x: f64) -> Self {
+        x
+    }
+}
+
+// Precision the renderer is built with, f32 with the `f32` feature. Materials, textures,
+// lights and cameras work in it, and the geometry core is used at it.
+#[cfg(not(feature = "f32"))]
+pub type Float = f64;
+#[cfg(feature = "f32")]
+pub type Float = f32;
+
+// Constants like PI in the precision of `Float`
+pub mod consts {
+    #[cfg(feature = "f32")]
+    pub use std::f32::consts::*;
+    #[cfg(not(feature = "f32"))]
+    pub use std::f64::consts::*;
+}
+
+pub type Color = Vector3<Float>;
+
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
 
 pub fn random<T: SampleUniform, R: SampleRange<T>>(range: R) -> T {
-    thread_rng().gen_range(range)
+    RNG.with(|rng| rng.borrow_mut().gen_range(range))
+}
+
+// Reseeds the current thread's generator used by `random`
+pub fn seed_random(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+// Exchanges the current thread's generator with `rng`, so work that is interleaved on one
+// thread can keep drawing from separate sequences
+pub fn swap_random(rng: &mut StdRng) {
+    RNG.with(|current| std::mem::swap(&mut *current.borrow_mut(), rng));
 }
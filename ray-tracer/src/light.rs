@@ -0,0 +1,138 @@
+use cgmath::{vec3, ElementWise, InnerSpace, Point3, Vector3};
+
+use crate::{
+    ray::{
+        hittable::{HitRecord, Hittable},
+        Ray,
+    },
+    stats::{self, Counter},
+    Color, Float,
+};
+
+// Infinitely small or infinitely far lights. Rays can never hit them, so they are sampled
+// directly with a shadow ray from every surface that can scatter their light.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Light {
+    Point {
+        position: Point3<Float>,
+        intensity: Color,
+    },
+    // a point light restricted to a cone around `direction`, fading out between the
+    // inner and outer cone angles
+    Spot {
+        position: Point3<Float>,
+        direction: Vector3<Float>,
+        intensity: Color,
+        cos_inner: Float,
+        cos_outer: Float,
+    },
+    // light traveling along `direction` from infinitely far away, like the sun
+    Directional {
+        direction: Vector3<Float>,
+        irradiance: Color,
+    },
+}
+
+pub struct LightSample {
+    // unit direction from the shaded point towards the light
+    pub wi: Vector3<Float>,
+    pub distance: Float,
+    // irradiance at the shaded point on a surface facing the light
+    pub irradiance: Color,
+}
+
+fn smoothstep(edge0: Float, edge1: Float, x: Float) -> Float {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0., 1.);
+    t * t * (3. - 2. * t)
+}
+
+impl Light {
+    pub fn sample(&self, p: Point3<Float>) -> Option<LightSample> {
+        match *self {
+            Light::Point {
+                position,
+                intensity,
+            } => {
+                let to_light = position - p;
+                let distance = to_light.magnitude();
+                Some(LightSample {
+                    wi: to_light / distance,
+                    distance,
+                    irradiance: intensity / (distance * distance),
+                })
+            }
+            Light::Spot {
+                position,
+                direction,
+                intensity,
+                cos_inner,
+                cos_outer,
+            } => {
+                let to_light = position - p;
+                let distance = to_light.magnitude();
+                let wi = to_light / distance;
+                let falloff = smoothstep(cos_outer, cos_inner, (-wi).dot(direction));
+                if falloff <= 0. {
+                    return None;
+                }
+                Some(LightSample {
+                    wi,
+                    distance,
+                    irradiance: intensity * (falloff / (distance * distance)),
+                })
+            }
+            Light::Directional {
+                direction,
+                irradiance,
+            } => Some(LightSample {
+                wi: -direction,
+                distance: Float::INFINITY,
+                irradiance,
+            }),
+        }
+    }
+}
+
+// A shadow ray towards a light, with the light it brings back if nothing blocks it
+pub struct ShadowRay {
+    pub ray: Ray,
+    pub t_max: Float,
+    pub light: Color,
+}
+
+// Shadow rays from `rec` towards the lights that can be reflected back along `r_in`
+pub fn shadow_rays<'a>(
+    lights: &'a [Light],
+    r_in: &'a Ray,
+    rec: &'a HitRecord,
+) -> impl Iterator<Item = ShadowRay> + 'a {
+    lights.iter().filter_map(move |light| {
+        let sample = light.sample(rec.p)?;
+        let f = rec.mat_ptr.eval(r_in, rec, sample.wi);
+        if f == vec3(0., 0., 0.) {
+            return None;
+        }
+        stats::count(Counter::ShadowRays);
+        Some(ShadowRay {
+            ray: r_in.bounce(rec.p, sample.wi),
+            t_max: sample.distance - 0.001,
+            light: f.mul_element_wise(sample.irradiance),
+        })
+    })
+}
+
+// Light from `lights` reflected at `rec` back along `r_in`, with shadow rays through `world`
+pub fn direct_lighting(
+    world: &dyn Hittable,
+    lights: &[Light],
+    r_in: &Ray,
+    rec: &HitRecord,
+) -> Color {
+    let mut total = vec3(0., 0., 0.);
+    for shadow in shadow_rays(lights, r_in, rec) {
+        if world.hit(&shadow.ray, 0.001, shadow.t_max).is_none() {
+            total += shadow.light;
+        }
+    }
+    total
+}
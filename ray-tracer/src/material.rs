@@ -1,16 +1,36 @@
-use std::{cell::RefCell, rc::Rc};
+use std::sync::Arc;
 
-use cgmath::{vec3, InnerSpace, Vector3};
+use cgmath::{vec3, ElementWise, InnerSpace, Vector3};
 
 use crate::{
+    consts::PI,
     random,
     ray::{hittable::HitRecord, Ray},
+    spectral::Dispersion,
+    texture::Texture,
     vector_additon::VectorAdditions,
-    Color,
+    Color, Float,
 };
 
 pub trait Material: Send + Sync {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)>;
+
+    // Light arriving from the unit direction `wi` that is scattered back along `r_in`, times
+    // the cosine at the surface. Only used for lights that rays cannot hit, so materials
+    // that reflect in a single direction keep the default.
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _wi: Vector3<Float>) -> Color {
+        return vec3(0., 0., 0.);
+    }
+
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        return vec3(0., 0., 0.);
+    }
+
+    // Whether light only leaves in the direction `scatter` picks, like mirrors and glass.
+    // These are the surfaces that focus light into caustics.
+    fn is_specular(&self) -> bool {
+        return true;
+    }
 }
 
 pub struct Lambertian {
@@ -18,28 +38,36 @@ pub struct Lambertian {
 }
 
 impl Lambertian {
-    pub fn new(albedo: Color) -> Box<Self> {
-        return Box::new(Self { albedo });
+    pub fn new(albedo: Color) -> Arc<Self> {
+        return Arc::new(Self { albedo });
     }
 }
 impl Material for Lambertian {
-    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
         let mut scatter_direction = rec.normal + Vector3::random_in_unit_sphere().normalize();
         if scatter_direction.near_zero() {
             scatter_direction = rec.normal;
         }
-        return Some((self.albedo, Ray::new(rec.p, scatter_direction)));
+        return Some((self.albedo, r_in.bounce(rec.p, scatter_direction)));
+    }
+
+    fn eval(&self, _r_in: &Ray, rec: &HitRecord, wi: Vector3<Float>) -> Color {
+        return self.albedo * (rec.normal.dot(wi).max(0.) / PI);
+    }
+
+    fn is_specular(&self) -> bool {
+        return false;
     }
 }
 
 pub struct Metal {
     albedo: Color,
-    fuzz: f64,
+    fuzz: Float,
 }
 
 impl Metal {
-    pub fn new(albedo: Color, fuzz: f64) -> Box<Self> {
-        return Box::new(Self {
+    pub fn new(albedo: Color, fuzz: Float) -> Arc<Self> {
+        return Arc::new(Self {
             albedo,
             fuzz: if fuzz < 1. { fuzz } else { 1. },
         });
@@ -48,7 +76,7 @@ impl Metal {
 impl Material for Metal {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
         let reflected = r_in.direction().normalize().reflect(rec.normal);
-        let scattered = Ray::new(
+        let scattered = r_in.bounce(
             rec.p,
             reflected + (self.fuzz * Vector3::random_in_unit_sphere()),
         );
@@ -60,14 +88,30 @@ impl Material for Metal {
 }
 
 pub struct Dielectric {
-    ir: f64,
+    ir: Float,
+    dispersion: Option<Dispersion>,
 }
 
 impl Dielectric {
-    pub fn new(ir: f64) -> Box<Self> {
-        return Box::new(Self { ir });
+    pub fn new(ir: Float) -> Arc<Self> {
+        return Arc::new(Self {
+            ir,
+            dispersion: None,
+        });
+    }
+    pub fn dispersive(dispersion: Dispersion) -> Arc<Self> {
+        return Arc::new(Self {
+            ir: dispersion.ior(Dispersion::D_LINE),
+            dispersion: Some(dispersion),
+        });
     }
-    fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
+    fn ior(&self, wavelength: Option<Float>) -> Float {
+        match (self.dispersion, wavelength) {
+            (Some(dispersion), Some(wavelength)) => dispersion.ior(wavelength),
+            _ => self.ir,
+        }
+    }
+    fn reflectance(cosine: Float, ref_idx: Float) -> Float {
         let mut r0 = (1. - ref_idx) / (1. + ref_idx);
         r0 = r0 * r0;
         return r0 + (1. - r0) * (1. - cosine).powi(5);
@@ -77,11 +121,8 @@ impl Dielectric {
 impl Material for Dielectric {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
         let attenuation = vec3(1., 1., 1.);
-        let refraction_ratio = if rec.front_face {
-            1. / self.ir
-        } else {
-            self.ir
-        };
+        let ir = self.ior(r_in.wavelength());
+        let refraction_ratio = if rec.front_face { 1. / ir } else { ir };
         let unit_direction = r_in.direction().normalize();
         let cos_theta = (-unit_direction).dot(rec.normal).min(1.);
         let sin_theta = (1. - cos_theta * cos_theta).sqrt();
@@ -92,6 +133,267 @@ impl Material for Dielectric {
             } else {
                 unit_direction.refract(rec.normal, refraction_ratio)
             };
-        return Some((attenuation, Ray::new(rec.p, direction)));
+        return Some((attenuation, r_in.bounce(rec.p, direction)));
+    }
+}
+
+// Shading normal from the geometric one and a tangent space normal, keeping it on the
+// geometric side of the surface
+fn perturbed(rec: &HitRecord, tangent_space: Vector3<Float>) -> Vector3<Float> {
+    if tangent_space.magnitude2() < 1e-16 {
+        return rec.normal;
+    }
+    let n = rec.to_world(tangent_space).normalize();
+    if n.dot(rec.normal) <= 1e-4 {
+        return rec.normal;
+    }
+    return n;
+}
+
+// Tangent space normal map on top of another material. Colors are mapped from [0, 1] to
+// [-1, 1], so (0.5, 0.5, 1) is the unperturbed normal.
+pub struct NormalMap {
+    inner: Arc<dyn Material>,
+    map: Arc<dyn Texture>,
+    strength: Float,
+}
+
+impl NormalMap {
+    pub fn new(inner: Arc<dyn Material>, map: Arc<dyn Texture>, strength: Float) -> Arc<Self> {
+        return Arc::new(Self {
+            inner,
+            map,
+            strength,
+        });
+    }
+}
+
+impl NormalMap {
+    fn shade<'a>(&self, rec: &HitRecord<'a>) -> HitRecord<'a> {
+        let c = self.map.value(rec.u, rec.v, rec.p);
+        let n = vec3(
+            self.strength * (2. * c.x - 1.),
+            self.strength * (2. * c.y - 1.),
+            2. * c.z - 1.,
+        );
+        let mut shaded = rec.clone();
+        shaded.normal = perturbed(rec, n);
+        return shaded;
+    }
+}
+
+impl Material for NormalMap {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        return self.inner.scatter(r_in, &self.shade(rec));
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: Vector3<Float>) -> Color {
+        return self.inner.eval(r_in, &self.shade(rec), wi);
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        return self.inner.emitted(rec);
+    }
+
+    fn is_specular(&self) -> bool {
+        return self.inner.is_specular();
+    }
+}
+
+// Bump map on top of another material, the height is the average of the texture's channels
+pub struct BumpMap {
+    inner: Arc<dyn Material>,
+    height: Arc<dyn Texture>,
+    strength: Float,
+}
+
+impl BumpMap {
+    pub fn new(inner: Arc<dyn Material>, height: Arc<dyn Texture>, strength: Float) -> Arc<Self> {
+        return Arc::new(Self {
+            inner,
+            height,
+            strength,
+        });
+    }
+
+    fn height(&self, u: Float, v: Float, rec: &HitRecord) -> Float {
+        let c = self.height.value(u, v, rec.p);
+        return (c.x + c.y + c.z) / 3.;
+    }
+
+    fn shade<'a>(&self, rec: &HitRecord<'a>) -> HitRecord<'a> {
+        let (du, dv) = self.height.texel_size();
+        let dh_du = self.height(rec.u + du, rec.v, rec) - self.height(rec.u - du, rec.v, rec);
+        let dh_dv = self.height(rec.u, rec.v + dv, rec) - self.height(rec.u, rec.v - dv, rec);
+        let n = vec3(
+            -0.5 * self.strength * dh_du,
+            -0.5 * self.strength * dh_dv,
+            1.,
+        );
+        let mut shaded = rec.clone();
+        shaded.normal = perturbed(rec, n);
+        return shaded;
+    }
+}
+
+impl Material for BumpMap {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        return self.inner.scatter(r_in, &self.shade(rec));
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: Vector3<Float>) -> Color {
+        return self.inner.eval(r_in, &self.shade(rec), wi);
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        return self.inner.emitted(rec);
+    }
+
+    fn is_specular(&self) -> bool {
+        return self.inner.is_specular();
+    }
+}
+
+// Emits the texture's color from both sides and absorbs everything that hits it
+pub struct DiffuseLight {
+    emit: Arc<dyn Texture>,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Arc<dyn Texture>) -> Arc<Self> {
+        return Arc::new(Self { emit });
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<(Color, Ray)> {
+        return None;
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        return self.emit.value(rec.u, rec.v, rec.p);
+    }
+
+    fn is_specular(&self) -> bool {
+        return false;
+    }
+}
+
+fn schlick(f0: Color, cosine: Float) -> Color {
+    let f = (1. - cosine.clamp(0., 1.)).powi(5);
+    return f0 + (vec3(1., 1., 1.) - f0) * f;
+}
+
+pub(crate) fn luminance(c: Color) -> Float {
+    return 0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z;
+}
+
+// glTF's metallic-roughness model: a diffuse base under a dielectric coat that turns into
+// a tinted mirror as `metallic` goes to 1. Reflections are sampled like fuzzy `Metal`, and
+// lights that can only be sampled directly see a GGX highlight. Textures multiply the
+// factors, the blue and green channels of `metallic_roughness` hold metallic and roughness.
+pub struct MetallicRoughness {
+    pub base_color: Color,
+    pub base_color_texture: Option<Arc<dyn Texture>>,
+    pub metallic: Float,
+    pub roughness: Float,
+    pub metallic_roughness_texture: Option<Arc<dyn Texture>>,
+    pub emissive: Color,
+    pub emissive_texture: Option<Arc<dyn Texture>>,
+}
+
+impl Default for MetallicRoughness {
+    // the glTF default material
+    fn default() -> Self {
+        return Self {
+            base_color: vec3(1., 1., 1.),
+            base_color_texture: None,
+            metallic: 1.,
+            roughness: 1.,
+            metallic_roughness_texture: None,
+            emissive: vec3(0., 0., 0.),
+            emissive_texture: None,
+        };
+    }
+}
+
+impl MetallicRoughness {
+    // base color, metallic and roughness at the hit point
+    fn sample(&self, rec: &HitRecord) -> (Color, Float, Float) {
+        let texture = |t: &Option<Arc<dyn Texture>>| match t {
+            Some(t) => t.value(rec.u, rec.v, rec.p),
+            None => vec3(1., 1., 1.),
+        };
+        let base = self
+            .base_color
+            .mul_element_wise(texture(&self.base_color_texture));
+        let mr = texture(&self.metallic_roughness_texture);
+        let metallic = (self.metallic * mr.z).clamp(0., 1.);
+        let roughness = (self.roughness * mr.y).clamp(0., 1.);
+        return (base, metallic, roughness);
+    }
+
+    fn f0(base: Color, metallic: Float) -> Color {
+        return vec3(0.04, 0.04, 0.04) * (1. - metallic) + base * metallic;
+    }
+}
+
+impl Material for MetallicRoughness {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let (base, metallic, roughness) = self.sample(rec);
+        let unit_direction = r_in.direction().normalize();
+        let fresnel = schlick(Self::f0(base, metallic), (-unit_direction).dot(rec.normal));
+        let p_specular = (luminance(fresnel) * (1. - metallic) + metallic).clamp(0.05, 1.);
+        if random(0. ..1.) < p_specular {
+            let fuzz = roughness * roughness;
+            let reflected =
+                unit_direction.reflect(rec.normal) + fuzz * Vector3::random_in_unit_sphere();
+            if reflected.dot(rec.normal) <= 0. {
+                return None;
+            }
+            return Some((fresnel / p_specular, r_in.bounce(rec.p, reflected)));
+        }
+        let mut scatter_direction = rec.normal + Vector3::random_in_unit_sphere().normalize();
+        if scatter_direction.near_zero() {
+            scatter_direction = rec.normal;
+        }
+        let diffuse = (vec3(1., 1., 1.) - fresnel).mul_element_wise(base) * (1. - metallic);
+        return Some((
+            diffuse / (1. - p_specular),
+            r_in.bounce(rec.p, scatter_direction),
+        ));
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: Vector3<Float>) -> Color {
+        let cos_i = rec.normal.dot(wi);
+        let wo = -r_in.direction().normalize();
+        let cos_o = rec.normal.dot(wo);
+        if cos_i <= 0. || cos_o <= 0. {
+            return vec3(0., 0., 0.);
+        }
+        let (base, metallic, roughness) = self.sample(rec);
+        let h = (wi + wo).normalize();
+        let fresnel = schlick(Self::f0(base, metallic), wo.dot(h));
+        let diffuse = (vec3(1., 1., 1.) - fresnel).mul_element_wise(base) * (1. - metallic) / PI;
+
+        // GGX distribution with the separable Smith masking term
+        let alpha = (roughness * roughness).max(1e-3);
+        let a2 = alpha * alpha;
+        let cos_h = rec.normal.dot(h);
+        let d = a2 / (PI * (cos_h * cos_h * (a2 - 1.) + 1.).powi(2));
+        let g1 = |cos: Float| 2. * cos / (cos + (a2 + (1. - a2) * cos * cos).sqrt());
+        let specular = fresnel * (d * g1(cos_i) * g1(cos_o) / (4. * cos_i * cos_o));
+        return (diffuse + specular) * cos_i;
+    }
+
+    fn is_specular(&self) -> bool {
+        return false;
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        return match &self.emissive_texture {
+            Some(t) => self.emissive.mul_element_wise(t.value(rec.u, rec.v, rec.p)),
+            None => self.emissive,
+        };
     }
 }
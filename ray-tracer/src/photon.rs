@@ -0,0 +1,661 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Instant,
+};
+
+use cgmath::{vec3, ElementWise, InnerSpace, Point3, Vector3};
+use rayon::prelude::*;
+
+use crate::{
+    camera::Camera,
+    consts::PI,
+    light::{direct_lighting, Light},
+    material::luminance,
+    random,
+    ray::{
+        aabb::Aabb,
+        hittable::{any_perpendicular, HitRecord, Hittable},
+        sky, Ray,
+    },
+    render::{assemble, camera_ray, pixel_seed, report_progress, RenderSettings, RenderedTile},
+    seed_random,
+    spectral::{self, spectrum_to_rgb},
+    stats::{self, Counter, Counters, RenderStats, TileStats},
+    vector_additon::VectorAdditions,
+    Color, Float,
+};
+
+// radius reduction of progressive photon mapping, (Knaus & Zwicker 2011)
+const ALPHA: Float = 2. / 3.;
+// specular objects beyond this many are lumped together before photons are aimed at them
+const MAX_TARGETS: usize = 64;
+// photons traced per task
+const CHUNK: u32 = 4096;
+
+// A photon that reached a diffuse surface over one or more specular bounces
+struct Photon {
+    p: Point3<Float>,
+    normal: Vector3<Float>,
+    // unit direction towards where the photon came from
+    wi: Vector3<Float>,
+    flux: Color,
+}
+
+// Sphere around a specular object that photons are aimed at
+struct Target {
+    center: Point3<Float>,
+    radius: Float,
+    // distance along a direction from the center that is outside of the scene
+    reach: Float,
+}
+
+enum Source {
+    Sky,
+    Light(Light),
+}
+
+// Where the photons of a pass come from. Every source spreads its photons over the targets
+// by the power it sends towards them, and a photon's flux is weighed by all the ways it could
+// have been sampled, so targets seen through each other are not counted twice.
+struct Emitters {
+    sources: Vec<Source>,
+    targets: Vec<Target>,
+    // photons per pass of each source aimed at each target
+    counts: Vec<Vec<u32>>,
+    // (source, target, end of its photons in the pass)
+    schedule: Vec<(usize, usize, u32)>,
+}
+
+fn frame(w: Vector3<Float>) -> (Vector3<Float>, Vector3<Float>) {
+    let u = any_perpendicular(w);
+    (u, w.cross(u))
+}
+
+fn uniform_sphere() -> Vector3<Float> {
+    let z: Float = random(-1. ..1.);
+    let r = (1. - z * z).max(0.).sqrt();
+    let phi = random(0. ..2. * PI);
+    vec3(r * phi.cos(), r * phi.sin(), z)
+}
+
+// Bounding spheres of the specular objects, many small ones are merged on a coarse grid
+fn targets(world: &dyn Hittable) -> Vec<Target> {
+    let mut bounds = vec![];
+    world.specular_bounds(&mut bounds);
+    let Some(all) = bounds.iter().copied().reduce(|acc, b| acc.surrounding(&b)) else {
+        return vec![];
+    };
+    if bounds.len() > MAX_TARGETS {
+        let size = all.max - all.min;
+        let mut cells: BTreeMap<[i64; 3], Aabb> = BTreeMap::new();
+        for bbox in bounds {
+            let c = bbox.centroid() - all.min;
+            let key = [0, 1, 2].map(|a| (4. * c[a] / size[a].max(1e-9)).floor().min(3.) as i64);
+            cells
+                .entry(key)
+                .and_modify(|acc| *acc = acc.surrounding(&bbox))
+                .or_insert(bbox);
+        }
+        bounds = cells.into_values().collect();
+    }
+    let scene = world.bounding_box().unwrap_or(all);
+    let scene_radius = (scene.max - scene.min).magnitude() / 2.;
+    bounds
+        .iter()
+        .map(|bbox| {
+            let center = bbox.centroid();
+            let radius = (bbox.max - bbox.min).magnitude() / 2.;
+            let reach = (scene.centroid() - center).magnitude() + scene_radius + radius;
+            Target {
+                center,
+                radius,
+                reach: if world.bounding_box().is_some() {
+                    reach
+                } else {
+                    reach.max(1e4)
+                },
+            }
+        })
+        .collect()
+}
+
+// Directions from `position` into `target`: the axis of the cone and the cosine of its angle
+fn cone(position: Point3<Float>, target: &Target) -> (Vector3<Float>, Float) {
+    let to_center = target.center - position;
+    let distance = to_center.magnitude();
+    if distance <= target.radius {
+        return (vec3(0., 0., 1.), -1.);
+    }
+    let sin_max = target.radius / distance;
+    (to_center / distance, (1. - sin_max * sin_max).sqrt())
+}
+
+impl Emitters {
+    fn new(world: &dyn Hittable, lights: &[Light], photons_per_pass: u32) -> Self {
+        let targets = targets(world);
+        let mut sources = vec![Source::Sky];
+        sources.extend(lights.iter().map(|&light| Source::Light(light)));
+
+        // the sky only depends on the height of a direction, which is uniform over the sphere
+        let sky_average = (0..32)
+            .map(|i| luminance(sky(vec3(1., (i as Float + 0.5) / 16. - 1., 0.))))
+            .sum::<Float>()
+            / 32.;
+        let power = |source: &Source, target: &Target| {
+            let disk = PI * target.radius * target.radius;
+            match *source {
+                Source::Sky => sky_average * 4. * PI * disk,
+                Source::Light(Light::Directional { irradiance, .. }) => {
+                    luminance(irradiance) * disk
+                }
+                Source::Light(
+                    Light::Point {
+                        position,
+                        intensity,
+                    }
+                    | Light::Spot {
+                        position,
+                        intensity,
+                        ..
+                    },
+                ) => luminance(intensity) * 2. * PI * (1. - cone(position, target).1),
+            }
+        };
+        let powers: Vec<Vec<Float>> = sources
+            .iter()
+            .map(|source| targets.iter().map(|t| power(source, t).max(0.)).collect())
+            .collect();
+        let total: Float = powers.iter().flatten().sum();
+        let counts: Vec<Vec<u32>> = powers
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|p| (photons_per_pass as Float * p / total).round() as u32)
+                    .collect()
+            })
+            .collect();
+
+        let mut schedule = vec![];
+        let mut end = 0;
+        for (source, row) in counts.iter().enumerate() {
+            for (target, &count) in row.iter().enumerate() {
+                if count > 0 {
+                    end += count;
+                    schedule.push((source, target, end));
+                }
+            }
+        }
+        Self {
+            sources,
+            targets,
+            counts,
+            schedule,
+        }
+    }
+
+    fn photons_per_pass(&self) -> u32 {
+        self.schedule.last().map_or(0, |&(_, _, end)| end)
+    }
+
+    // a twentieth of the largest specular object
+    fn default_radius(&self) -> Float {
+        self.targets
+            .iter()
+            .map(|t| t.radius / 20.)
+            .fold(0., Float::max)
+            .max(1e-4)
+    }
+
+    // Photon traveling along `d` towards the disk of `target` facing it, starting outside of
+    // the scene. Returns its origin and the density of such lines over all targets.
+    fn parallel(&self, source: usize, target: usize, d: Vector3<Float>) -> (Point3<Float>, Float) {
+        let t = &self.targets[target];
+        let (u, v) = frame(d);
+        let disk = Vector3::random_in_unit_disk() * t.radius;
+        let p = t.center + u * disk.x + v * disk.y;
+        let density = self
+            .targets
+            .iter()
+            .zip(&self.counts[source])
+            .filter(|(t, &n)| {
+                let to_center = t.center - p;
+                n > 0 && (to_center - d * to_center.dot(d)).magnitude2() <= t.radius * t.radius
+            })
+            .map(|(t, &n)| n as Float / (PI * t.radius * t.radius))
+            .sum::<Float>();
+        (p - d * t.reach, density)
+    }
+
+    fn emit(&self, source: usize, target: usize) -> Option<(Ray, Color)> {
+        match self.sources[source] {
+            Source::Sky => {
+                let d = uniform_sphere();
+                let (origin, density) = self.parallel(source, target, d);
+                (density > 0.).then(|| (Ray::new(origin, d), sky(-d) * (4. * PI / density)))
+            }
+            Source::Light(Light::Directional {
+                direction,
+                irradiance,
+            }) => {
+                let d = direction.normalize();
+                let (origin, density) = self.parallel(source, target, d);
+                (density > 0.).then(|| (Ray::new(origin, d), irradiance / density))
+            }
+            Source::Light(
+                light @ (Light::Point { position, .. } | Light::Spot { position, .. }),
+            ) => {
+                let (axis, cos_max) = cone(position, &self.targets[target]);
+                let cos_theta = 1. - random(0. ..1.) * (1. - cos_max);
+                let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+                let phi = random(0. ..2. * PI);
+                let (u, v) = frame(axis);
+                let d =
+                    u * (sin_theta * phi.cos()) + v * (sin_theta * phi.sin()) + axis * cos_theta;
+                let density = self
+                    .targets
+                    .iter()
+                    .zip(&self.counts[source])
+                    .filter(|&(_, &n)| n > 0)
+                    .map(|(t, &n)| {
+                        let (axis, cos_max) = cone(position, t);
+                        if d.dot(axis) >= cos_max {
+                            n as Float / (2. * PI * (1. - cos_max))
+                        } else {
+                            0.
+                        }
+                    })
+                    .sum::<Float>();
+                // radiant intensity along `d` is the irradiance at unit distance
+                let intensity = light.sample(position + d)?.irradiance;
+                (density > 0.).then(|| (Ray::new(position, d), intensity / density))
+            }
+        }
+    }
+
+    // Traces the photons of one pass, keeping the ones that land on diffuse surfaces after
+    // a specular bounce
+    fn trace(
+        &self,
+        world: &dyn Hittable,
+        settings: &RenderSettings,
+        seed: Option<u64>,
+    ) -> (Vec<Photon>, Counters) {
+        let total = self.photons_per_pass();
+        let chunks: Vec<(Vec<Photon>, Counters)> = (0..total.div_ceil(CHUNK))
+            .into_par_iter()
+            .map(|chunk| {
+                if let Some(seed) = seed {
+                    seed_random(pixel_seed(seed, chunk as u64));
+                }
+                stats::take();
+                let mut photons = vec![];
+                for k in chunk * CHUNK..((chunk + 1) * CHUNK).min(total) {
+                    let i = self.schedule.partition_point(|&(_, _, end)| end <= k);
+                    let (source, target, _) = self.schedule[i];
+                    if let Some((ray, flux)) = self.emit(source, target) {
+                        trace_photon(world, settings, ray, flux, &mut photons);
+                    }
+                }
+                (photons, stats::take())
+            })
+            .collect();
+        let mut counters = Counters::default();
+        let mut photons = vec![];
+        for (chunk, chunk_counters) in chunks {
+            photons.extend(chunk);
+            counters += chunk_counters;
+        }
+        (photons, counters)
+    }
+}
+
+fn trace_photon(
+    world: &dyn Hittable,
+    settings: &RenderSettings,
+    mut ray: Ray,
+    flux: Color,
+    photons: &mut Vec<Photon>,
+) {
+    if settings.spectral {
+        ray = ray.with_wavelength(spectral::sample_wavelength());
+    }
+    let mut flux = ray.project(flux);
+    for bounce in 0..settings.max_depth {
+        let Some(rec) = world.hit(&ray, 0.001, Float::INFINITY) else {
+            return;
+        };
+        if !rec.mat_ptr.is_specular() {
+            if bounce > 0 {
+                photons.push(Photon {
+                    p: rec.p,
+                    normal: rec.normal,
+                    wi: -ray.direction().normalize(),
+                    flux: match ray.wavelength() {
+                        Some(wavelength) => spectrum_to_rgb(flux.x, wavelength),
+                        None => flux,
+                    },
+                });
+            }
+            return;
+        }
+        let Some((attenuation, scattered)) = rec.mat_ptr.scatter(&ray, &rec) else {
+            return;
+        };
+        stats::count(Counter::BounceRays);
+        flux = flux.mul_element_wise(ray.project(attenuation));
+        ray = scattered;
+    }
+}
+
+// Photons of one pass in a hash grid with cells the size of the gather radius
+struct PhotonMap {
+    photons: Vec<Photon>,
+    cells: HashMap<[i64; 3], Vec<usize>>,
+    radius: Float,
+}
+
+impl PhotonMap {
+    fn new(photons: Vec<Photon>, radius: Float) -> Self {
+        let mut map = Self {
+            photons: vec![],
+            cells: HashMap::new(),
+            radius,
+        };
+        for (i, photon) in photons.iter().enumerate() {
+            map.cells.entry(map.cell(photon.p)).or_default().push(i);
+        }
+        map.photons = photons;
+        map
+    }
+
+    fn cell(&self, p: Point3<Float>) -> [i64; 3] {
+        [p.x, p.y, p.z].map(|c| (c / self.radius).floor() as i64)
+    }
+
+    // caustic light reflected at `rec` back along `r_in`
+    fn estimate(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        let r2 = self.radius * self.radius;
+        let [x, y, z] = self.cell(rec.p);
+        let mut total = vec3(0., 0., 0.);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let Some(indices) = self.cells.get(&[x + dx, y + dy, z + dz]) else {
+                        continue;
+                    };
+                    for photon in indices.iter().map(|&i| &self.photons[i]) {
+                        // photons on the other side of a thin wall or around a corner
+                        if (photon.p - rec.p).magnitude2() > r2
+                            || photon.normal.dot(rec.normal) < 0.5
+                        {
+                            continue;
+                        }
+                        let cos = rec.normal.dot(photon.wi);
+                        if cos <= 0. {
+                            continue;
+                        }
+                        let f = rec.mat_ptr.eval(r_in, rec, photon.wi) / cos;
+                        total += f.mul_element_wise(photon.flux);
+                    }
+                }
+            }
+        }
+        total / (PI * r2)
+    }
+}
+
+// Which vertices of an eye path have been diffuse, to leave out the paths the photons cover
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PathState {
+    Camera,
+    AfterDiffuse,
+    // specular bounces after the first diffuse one, light reaching them from the sky is a
+    // caustic that the photon map already has
+    Caustic,
+    Free,
+}
+
+impl PathState {
+    fn next(self, specular: bool) -> Self {
+        match (self, specular) {
+            (PathState::Camera, true) => PathState::Camera,
+            (PathState::Camera, false) => PathState::AfterDiffuse,
+            (PathState::AfterDiffuse | PathState::Caustic, true) => PathState::Caustic,
+            _ => PathState::Free,
+        }
+    }
+}
+
+// `Ray::color` with the caustics of `photons` at the first diffuse surface
+fn radiance(
+    ray: Ray,
+    world: &dyn Hittable,
+    lights: &[Light],
+    photons: &PhotonMap,
+    max_depth: i32,
+) -> Color {
+    let mut ray = ray;
+    let mut color = vec3(0., 0., 0.);
+    let mut throughput = vec3(1., 1., 1.);
+    let mut state = PathState::Camera;
+    for depth in (1..=max_depth).rev() {
+        let Some(rec) = world.hit(&ray, 0.001, Float::INFINITY) else {
+            if state != PathState::Caustic {
+                color += throughput.mul_element_wise(ray.project(sky(ray.direction())));
+            }
+            break;
+        };
+        let mut light = rec.mat_ptr.emitted(&rec);
+        if !lights.is_empty() {
+            light += direct_lighting(world, lights, &ray, &rec);
+        }
+        let specular = rec.mat_ptr.is_specular();
+        if state == PathState::Camera && !specular {
+            light += photons.estimate(&ray, &rec);
+        }
+        color += throughput.mul_element_wise(ray.project(light));
+        state = state.next(specular);
+
+        let Some((attenuation, scattered)) = rec.mat_ptr.scatter(&ray, &rec) else {
+            break;
+        };
+        if depth > 1 {
+            stats::count(Counter::BounceRays);
+        }
+        throughput = throughput.mul_element_wise(ray.project(attenuation));
+        ray = scattered;
+    }
+    color
+}
+
+// squared gather radius of the pass after `pass`
+fn shrink(radius2: Float, pass: u32) -> Float {
+    radius2 * (pass as Float + 1. + ALPHA) / (pass as Float + 2.)
+}
+
+// Renders with probabilistic progressive photon mapping: each of the `samples_per_pixel`
+// passes traces new photons and one eye path per pixel with a smaller gather radius, and
+// the image is the average of the passes.
+pub fn render(
+    world: &dyn Hittable,
+    lights: &[Light],
+    cam: &Camera,
+    settings: &RenderSettings,
+    photons_per_pass: u32,
+    radius: Option<Float>,
+) -> (Vec<Color>, RenderStats) {
+    let start = Instant::now();
+    let emitters = Emitters::new(world, lights, photons_per_pass);
+    if emitters.targets.is_empty() {
+        eprintln!("no specular objects in the scene, photons have nothing to focus");
+    }
+    let mut radius2 = radius.unwrap_or_else(|| emitters.default_radius()).powi(2);
+
+    let tiles = settings.tiles();
+    let mut rendered: Vec<RenderedTile> = tiles
+        .iter()
+        .map(|tile| RenderedTile {
+            pixels: vec![vec3(0., 0., 0.); (tile.width * tile.height) as usize],
+            stats: TileStats {
+                x: tile.x,
+                y: tile.y,
+                width: tile.width,
+                height: tile.height,
+                seconds: 0.,
+            },
+            counters: Counters::default(),
+        })
+        .collect();
+    let mut photon_counters = Counters::default();
+    let passes = settings.samples_per_pixel;
+    for pass in 0..passes {
+        let photon_seed = settings.seed.map(|seed| pixel_seed(seed, 2 * pass as u64));
+        let eye_seed = settings
+            .seed
+            .map(|seed| pixel_seed(seed, 2 * pass as u64 + 1));
+        let (photons, counters) = emitters.trace(world, settings, photon_seed);
+        photon_counters += counters;
+        let photons = PhotonMap::new(photons, radius2.sqrt());
+
+        rendered
+            .par_iter_mut()
+            .zip(&tiles)
+            .for_each(|(rendered, tile)| {
+                stats::take();
+                let tile_start = Instant::now();
+                let mut pixels = rendered.pixels.iter_mut();
+                for y in tile.y..tile.y + tile.height {
+                    for x in tile.x..tile.x + tile.width {
+                        let pixel = pixels.next().unwrap();
+                        if let Some(seed) = eye_seed {
+                            let idx = y as u64 * settings.image_width as u64 + x as u64;
+                            seed_random(pixel_seed(seed, idx));
+                        }
+                        let j = settings.image_height - 1 - y;
+                        let Some(ray) = camera_ray(cam, settings, x, j) else {
+                            continue;
+                        };
+                        let trace =
+                            |ray| radiance(ray, world, lights, &photons, settings.max_depth);
+                        let color = if settings.spectral {
+                            spectral::trace(ray, trace)
+                        } else {
+                            trace(ray)
+                        };
+                        *pixel += color * cam.exposure();
+                    }
+                }
+                rendered.stats.seconds += tile_start.elapsed().as_secs_f64();
+                rendered.counters += stats::take();
+            });
+        report_progress(pass + 1, passes as usize);
+        radius2 = shrink(radius2, pass);
+    }
+    eprintln!("\nDone. ");
+
+    for tile in rendered.iter_mut() {
+        for pixel in tile.pixels.iter_mut() {
+            *pixel /= passes as Float;
+        }
+    }
+    if let Some(first) = rendered.first_mut() {
+        first.counters += photon_counters;
+    }
+    assemble(settings, rendered, start.elapsed())
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        camera::CameraSettings,
+        material::Lambertian,
+        ray::hittable::{HittableList, Sphere},
+        render::Integrator,
+    };
+
+    #[test]
+    fn test_shrink() {
+        let mut radius2 = 4.;
+        for pass in 0..100 {
+            let next = shrink(radius2, pass);
+            assert!(next < radius2);
+            let ratio = (pass as Float + 1. + ALPHA) / (pass as Float + 2.);
+            assert!((next / radius2 - ratio).abs() < 1e-6);
+            radius2 = next;
+        }
+        // the radius shrinks slowly enough for the estimate to converge, with the squared
+        // radius going as pass^(ALPHA - 1)
+        let at_100 = radius2;
+        for pass in 100..1000 {
+            radius2 = shrink(radius2, pass);
+        }
+        let expected = at_100 * (10. as Float).powf(ALPHA - 1.);
+        assert!(
+            (radius2 / expected - 1.).abs() < 0.01,
+            "{radius2} {expected}"
+        );
+    }
+
+    // without specular objects there are no caustics, so the photon integrator only differs
+    // from the path integrator in how it draws random numbers
+    #[test]
+    fn test_diffuse_matches_path() {
+        let mut world = HittableList::default();
+        let ground = Lambertian::new(vec3(0.5, 0.5, 0.5));
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0., -1000., 0.),
+            1000.,
+            ground,
+        )));
+        let red = Lambertian::new(vec3(0.7, 0.2, 0.2));
+        world.add(Arc::new(Sphere::new(Point3::new(0., 1., 0.), 1., red)));
+        let lights = vec![Light::Point {
+            position: Point3::new(2., 5., 3.),
+            intensity: vec3(20., 20., 20.),
+        }];
+        let settings = RenderSettings {
+            image_width: 24,
+            image_height: 16,
+            samples_per_pixel: 64,
+            max_depth: 5,
+            seed: Some(3),
+            spectral: false,
+            integrator: Integrator::Path,
+            packets: false,
+            wavefront: false,
+        };
+        let cam = CameraSettings::default()
+            .build(settings.aspect_ratio())
+            .unwrap();
+        let (path, _) = crate::render::render(&world, &lights, &cam, &settings);
+        let photon_settings = RenderSettings {
+            integrator: Integrator::Photon {
+                photons_per_pass: 100,
+                radius: Some(0.1),
+            },
+            ..settings
+        };
+        let (photon, _) = crate::render::render(&world, &lights, &cam, &photon_settings);
+        assert_eq!(photon.len(), path.len());
+
+        let mean = |image: &[Color]| {
+            image.iter().fold(vec3(0., 0., 0.), |acc, &c| acc + c) / image.len() as Float
+        };
+        let (path_mean, photon_mean) = (mean(&path), mean(&photon));
+        for axis in 0..3 {
+            let error = (photon_mean[axis] - path_mean[axis]).abs() / path_mean[axis];
+            assert!(error < 0.03, "{photon_mean:?} vs {path_mean:?}");
+        }
+        let rmse = (path
+            .iter()
+            .zip(&photon)
+            .map(|(a, b)| (a - b).magnitude2())
+            .sum::<Float>()
+            / path.len() as Float)
+            .sqrt();
+        assert!(rmse < 0.1 * path_mean.magnitude(), "rmse {rmse}");
+    }
+}
@@ -0,0 +1,99 @@
+use cgmath::{Matrix4, Point3, Transform};
+
+use crate::{Float, Real};
+
+use super::{
+    packet::{Lanes, RayPacket},
+    Ray,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb<F: Real = Float> {
+    pub min: Point3<F>,
+    pub max: Point3<F>,
+}
+
+impl<F: Real> Aabb<F> {
+    pub fn new(min: Point3<F>, max: Point3<F>) -> Self {
+        Self { min, max }
+    }
+
+    pub fn hit(&self, r: &Ray<F>, mut t_min: F, mut t_max: F) -> bool {
+        for a in 0..3 {
+            let inv_d = F::one() / r.dir[a];
+            let mut t0 = (self.min[a] - r.orig[a]) * inv_d;
+            let mut t1 = (self.max[a] - r.orig[a]) * inv_d;
+            if inv_d < F::zero() {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = t0.max(t_min);
+            t_max = t1.min(t_max);
+            if t_max <= t_min {
+                return false;
+            }
+        }
+        true
+    }
+
+    // whether any ray of the packet enters the box before its `t_max`
+    pub fn hit_packet<const N: usize>(
+        &self,
+        packet: &RayPacket<F, N>,
+        t_min: F,
+        t_max: &Lanes<F, N>,
+    ) -> bool {
+        let mut any = false;
+        for (i, &t_max) in t_max.iter().enumerate() {
+            let (mut t0, mut t1) = (t_min, t_max);
+            for a in 0..3 {
+                let inv_d = packet.inv_direction[a][i];
+                let near = (self.min[a] - packet.origin[a][i]) * inv_d;
+                let far = (self.max[a] - packet.origin[a][i]) * inv_d;
+                t0 = t0.max(near.min(far));
+                t1 = t1.min(near.max(far));
+            }
+            any |= t0 < t1;
+        }
+        any
+    }
+
+    pub fn surrounding(&self, other: &Self) -> Self {
+        Aabb::new(
+            Point3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            Point3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        )
+    }
+
+    pub fn centroid(&self) -> Point3<F> {
+        let half = F::of(0.5);
+        Point3::new(
+            half * (self.min.x + self.max.x),
+            half * (self.min.y + self.max.y),
+            half * (self.min.z + self.max.z),
+        )
+    }
+
+    // box around the transformed corners of this box
+    pub fn transform(&self, m: &Matrix4<F>) -> Self {
+        let corner = |i: usize| {
+            Point3::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            )
+        };
+        let first = m.transform_point(corner(0));
+        (1..8).fold(Aabb::new(first, first), |acc, i| {
+            let p = m.transform_point(corner(i));
+            acc.surrounding(&Aabb::new(p, p))
+        })
+    }
+}
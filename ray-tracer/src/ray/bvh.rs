@@ -0,0 +1,284 @@
+use std::sync::Arc;
+
+use crate::{
+    stats::{self, Counter},
+    Float,
+};
+
+use super::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable, HittableList},
+    packet::{PacketHits, RayPacket},
+    Ray,
+};
+
+enum BvhNode {
+    Leaf(Arc<dyn Hittable>, Aabb),
+    Branch {
+        bbox: Aabb,
+        left: Box<BvhNode>,
+        right: Box<BvhNode>,
+    },
+}
+
+impl BvhNode {
+    fn build(mut objects: Vec<(Arc<dyn Hittable>, Aabb)>) -> Self {
+        if objects.len() == 1 {
+            let (object, bbox) = objects.pop().unwrap();
+            return BvhNode::Leaf(object, bbox);
+        }
+
+        // split along the axis the centroids are most spread out on
+        let centroids = objects
+            .iter()
+            .map(|(_, bbox)| {
+                let c = bbox.centroid();
+                Aabb::new(c, c)
+            })
+            .reduce(|a, b| a.surrounding(&b))
+            .unwrap();
+        let extent = centroids.max - centroids.min;
+        let axis = if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        };
+        objects.sort_by(|(_, a), (_, b)| a.centroid()[axis].total_cmp(&b.centroid()[axis]));
+
+        let right = objects.split_off(objects.len() / 2);
+        let left = Box::new(BvhNode::build(objects));
+        let right = Box::new(BvhNode::build(right));
+        BvhNode::Branch {
+            bbox: left.bbox().surrounding(&right.bbox()),
+            left,
+            right,
+        }
+    }
+
+    fn bbox(&self) -> Aabb {
+        match self {
+            BvhNode::Leaf(_, bbox) => *bbox,
+            BvhNode::Branch { bbox, .. } => *bbox,
+        }
+    }
+
+    fn specular_bounds(&self, bounds: &mut Vec<Aabb>) {
+        match self {
+            BvhNode::Leaf(object, _) => object.specular_bounds(bounds),
+            BvhNode::Branch { left, right, .. } => {
+                left.specular_bounds(bounds);
+                right.specular_bounds(bounds);
+            }
+        }
+    }
+
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        stats::count(Counter::BvhNodeVisits);
+        match self {
+            BvhNode::Leaf(object, bbox) => {
+                if !bbox.hit(r, t_min, t_max) {
+                    return None;
+                }
+                object.hit(r, t_min, t_max)
+            }
+            BvhNode::Branch { bbox, left, right } => {
+                if !bbox.hit(r, t_min, t_max) {
+                    return None;
+                }
+                let hit_left = left.hit(r, t_min, t_max);
+                let closest = hit_left.as_ref().map_or(t_max, |rec| rec.t);
+                right.hit(r, t_min, closest).or(hit_left)
+            }
+        }
+    }
+
+    // visits the nodes that any ray of the packet enters, once for the whole packet
+    fn hit_packet<'a>(&'a self, packet: &RayPacket, t_min: Float, hits: &mut PacketHits<'a>) {
+        stats::count(Counter::BvhNodeVisits);
+        if !self.bbox().hit_packet(packet, t_min, &hits.t_max) {
+            return;
+        }
+        match self {
+            BvhNode::Leaf(object, _) => object.hit_packet(packet, t_min, hits),
+            BvhNode::Branch { left, right, .. } => {
+                left.hit_packet(packet, t_min, hits);
+                right.hit_packet(packet, t_min, hits);
+            }
+        }
+    }
+}
+
+// Bounding volume hierarchy over a set of objects. Objects without a bounding box are
+// kept in a plain list next to the tree.
+pub struct Bvh {
+    root: Option<BvhNode>,
+    unbounded: HittableList,
+}
+
+impl Bvh {
+    pub fn new(objects: Vec<Arc<dyn Hittable>>) -> Self {
+        let mut unbounded = HittableList::default();
+        let mut bounded = vec![];
+        for object in objects {
+            match object.bounding_box() {
+                Some(bbox) => bounded.push((object, bbox)),
+                None => unbounded.add(object),
+            }
+        }
+        let root = if bounded.is_empty() {
+            None
+        } else {
+            Some(BvhNode::build(bounded))
+        };
+        Self { root, unbounded }
+    }
+}
+
+impl From<HittableList> for Bvh {
+    fn from(list: HittableList) -> Self {
+        Self::new(list.into_objects())
+    }
+}
+
+impl Hittable for Bvh {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let hit_tree = self
+            .root
+            .as_ref()
+            .and_then(|root| root.hit(r, t_min, t_max));
+        let closest = hit_tree.as_ref().map_or(t_max, |rec| rec.t);
+        self.unbounded.hit(r, t_min, closest).or(hit_tree)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        self.root.as_ref().map(|root| root.bbox())
+    }
+
+    fn specular_bounds(&self, bounds: &mut Vec<Aabb>) {
+        if let Some(root) = &self.root {
+            root.specular_bounds(bounds);
+        }
+        self.unbounded.specular_bounds(bounds);
+    }
+
+    fn hit_packet<'a>(&'a self, packet: &RayPacket, t_min: Float, hits: &mut PacketHits<'a>) {
+        if let Some(root) = &self.root {
+            root.hit_packet(packet, t_min, hits);
+        }
+        self.unbounded.hit_packet(packet, t_min, hits);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::Point3;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::{
+        material::Lambertian,
+        ray::{hittable::Sphere, mesh::Mesh, packet::LANES},
+        Color,
+    };
+
+    use super::*;
+
+    // spheres and a triangulated wavy floor, in a tree
+    fn objects() -> Vec<Arc<dyn Hittable>> {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mat = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+        let mut objects: Vec<Arc<dyn Hittable>> = (0..20)
+            .map(|_| {
+                let center = Point3::new(
+                    rng.gen_range(-4. ..4.),
+                    rng.gen_range(0. ..2.),
+                    rng.gen_range(-4. ..4.),
+                );
+                Arc::new(Sphere::new(center, rng.gen_range(0.2..1.), mat.clone())) as _
+            })
+            .collect();
+        let n = 8;
+        let positions = (0..=n)
+            .flat_map(|z| (0..=n).map(move |x| (x, z)))
+            .map(|(x, z)| {
+                Point3::new(
+                    x as Float - 4.,
+                    ((x + z) % 2) as Float * 0.3,
+                    z as Float - 4.,
+                )
+            })
+            .collect();
+        let mut indices = vec![];
+        for z in 0..n {
+            for x in 0..n {
+                let i = z * (n + 1) + x;
+                indices.push([i, i + 1, i + n + 1]);
+                indices.push([i + 1, i + n + 2, i + n + 1]);
+            }
+        }
+        let mesh = Arc::new(Mesh::new(positions, indices, mat).unwrap());
+        objects.extend(mesh.triangles());
+        objects
+    }
+
+    fn rays(n: usize) -> Vec<Ray> {
+        let mut rng = StdRng::seed_from_u64(4);
+        (0..n)
+            .map(|_| {
+                let orig = Point3::new(rng.gen_range(-6. ..6.), rng.gen_range(1. ..6.), -8.);
+                let target = Point3::new(rng.gen_range(-5. ..5.), 0., rng.gen_range(-5. ..5.));
+                Ray::new(orig, target - orig)
+            })
+            .collect()
+    }
+
+    // every packet size from a single ray to full packets finds the same hits as one ray
+    // at a time
+    fn check_packets(world: &dyn Hittable, rays: &[Ray]) -> usize {
+        let mut found = 0;
+        for len in 1..=LANES {
+            for chunk in rays.chunks(len) {
+                let packet = RayPacket::new(chunk);
+                let mut hits = PacketHits::new(&packet, Float::INFINITY);
+                world.hit_packet(&packet, 0.001, &mut hits);
+                for (r, packet_hit) in chunk.iter().zip(hits.into_records(chunk.len())) {
+                    match (world.hit(r, 0.001, Float::INFINITY), packet_hit) {
+                        (None, None) => {}
+                        (Some(expected), Some(rec)) => {
+                            assert_eq!(expected.t, rec.t);
+                            assert_eq!(expected.p, rec.p);
+                            assert_eq!(expected.normal, rec.normal);
+                            assert_eq!(expected.front_face, rec.front_face);
+                            assert_eq!((expected.u, expected.v), (rec.u, rec.v));
+                            found += 1;
+                        }
+                        (expected, rec) => panic!(
+                            "ray from {:?}: hit at {:?} one at a time, {:?} in a packet of {len}",
+                            r.origin(),
+                            expected.map(|rec| rec.t),
+                            rec.map(|rec| rec.t)
+                        ),
+                    }
+                }
+            }
+        }
+        found
+    }
+
+    #[test]
+    fn test_packets_match_single_rays() {
+        let rays = rays(5 * LANES + 1);
+        let objects = objects();
+        let mut found = 0;
+        for object in &objects {
+            found += check_packets(object.as_ref(), &rays);
+        }
+        assert!(found > 0);
+        let bvh = Bvh::new(objects);
+        assert!(check_packets(&bvh, &rays) > rays.len() / 2);
+    }
+}
@@ -1,33 +1,50 @@
-use std::{cell::RefCell, fmt::Debug, rc::Rc};
+use std::{fmt::Debug, sync::Arc};
 
 use cgmath::{vec3, InnerSpace, Point3, Vector3};
 
-use crate::material::Material;
+use crate::{
+    consts::PI,
+    material::Material,
+    stats::{self, Counter},
+    Float,
+};
 
-use super::Ray;
+use super::{
+    aabb::Aabb,
+    intersect,
+    packet::{PacketHits, RayPacket},
+    Ray,
+};
 
+#[derive(Clone)]
 pub struct HitRecord<'a> {
-    pub t: f64,
-    pub p: Point3<f64>,
-    pub normal: Vector3<f64>,
+    pub t: Float,
+    pub p: Point3<Float>,
+    pub normal: Vector3<Float>,
     pub front_face: bool,
     pub mat_ptr: &'a dyn Material,
+    // surface parameterization, (0, 0) for objects without one
+    pub u: Float,
+    pub v: Float,
+    // unit tangent along increasing u, perpendicular to the normal; with the normal
+    // it spans the tangent frame normal and bump maps are defined in
+    pub tangent: Vector3<Float>,
 }
 impl<'a> Debug for HitRecord<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "Hit Record {{t: {:?}, p: {:?}, normal: {:?}, front_face: {:?}}}",
-            self.t, self.p, self.normal, self.front_face
+            "Hit Record {{t: {:?}, p: {:?}, normal: {:?}, front_face: {:?}, uv: ({:?}, {:?})}}",
+            self.t, self.p, self.normal, self.front_face, self.u, self.v
         )
     }
 }
 
 impl<'a> HitRecord<'a> {
     pub fn new(
-        t: f64,
-        p: Point3<f64>,
-        outward_normal: Vector3<f64>,
+        t: Float,
+        p: Point3<Float>,
+        outward_normal: Vector3<Float>,
         r: &Ray,
         mat_ptr: &'a dyn Material,
     ) -> Self {
@@ -37,11 +54,35 @@ impl<'a> HitRecord<'a> {
             normal: vec3(0., 0., 0.),
             front_face: false,
             mat_ptr,
+            u: 0.,
+            v: 0.,
+            tangent: vec3(0., 0., 0.),
         };
         hr.set_face_normal(r, outward_normal);
+        hr.tangent = any_perpendicular(hr.normal);
         return hr;
     }
-    pub fn set_face_normal(&mut self, r: &Ray, outward_normal: Vector3<f64>) {
+
+    // `tangent` is dp/du and does not need to be normalized or perpendicular to the normal
+    pub fn with_uv(mut self, u: Float, v: Float, tangent: Vector3<Float>) -> Self {
+        self.u = u;
+        self.v = v;
+        let tangent = tangent - self.normal * tangent.dot(self.normal);
+        if tangent.magnitude2() > 1e-16 {
+            self.tangent = tangent.normalize();
+        }
+        self
+    }
+
+    pub fn bitangent(&self) -> Vector3<Float> {
+        self.normal.cross(self.tangent)
+    }
+
+    // tangent space (x along the tangent, z along the normal) to world space
+    pub fn to_world(&self, v: Vector3<Float>) -> Vector3<Float> {
+        self.tangent * v.x + self.bitangent() * v.y + self.normal * v.z
+    }
+    pub fn set_face_normal(&mut self, r: &Ray, outward_normal: Vector3<Float>) {
         self.front_face = r.dir.dot(outward_normal) < 0.;
         self.normal = if self.front_face {
             outward_normal
@@ -51,17 +92,77 @@ impl<'a> HitRecord<'a> {
     }
 }
 
+pub(crate) fn any_perpendicular(n: Vector3<Float>) -> Vector3<Float> {
+    let other = if n.x.abs() > 0.9 {
+        vec3(0., 1., 0.)
+    } else {
+        vec3(1., 0., 0.)
+    };
+    n.cross(other).normalize()
+}
+
 pub trait Hittable: Send + Sync {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord>;
+    // None for objects that cannot be bounded, which keeps them out of a `Bvh`
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+    // Adds the boxes of the parts with specular materials, which photons are aimed at
+    fn specular_bounds(&self, _bounds: &mut Vec<Aabb>) {}
+    // Records the hits of the rays of `packet` that are closer than the ones in `hits`.
+    // Objects that cannot do better trace the rays one at a time.
+    fn hit_packet<'a>(&'a self, packet: &RayPacket, t_min: Float, hits: &mut PacketHits<'a>) {
+        for (lane, r) in packet.rays().iter().enumerate() {
+            if let Some(rec) = self.hit(r, t_min, hits.t_max[lane]) {
+                hits.set(lane, rec);
+            }
+        }
+    }
+}
+
+impl<T: Hittable + ?Sized> Hittable for &T {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        (**self).hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        (**self).bounding_box()
+    }
+
+    fn specular_bounds(&self, bounds: &mut Vec<Aabb>) {
+        (**self).specular_bounds(bounds)
+    }
+
+    fn hit_packet<'a>(&'a self, packet: &RayPacket, t_min: Float, hits: &mut PacketHits<'a>) {
+        (**self).hit_packet(packet, t_min, hits)
+    }
+}
+
+impl<T: Hittable + ?Sized> Hittable for Arc<T> {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        (**self).hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        (**self).bounding_box()
+    }
+
+    fn specular_bounds(&self, bounds: &mut Vec<Aabb>) {
+        (**self).specular_bounds(bounds)
+    }
+
+    fn hit_packet<'a>(&'a self, packet: &RayPacket, t_min: Float, hits: &mut PacketHits<'a>) {
+        (**self).hit_packet(packet, t_min, hits)
+    }
 }
 
 #[derive(Default)]
 pub struct HittableList {
-    objects: Vec<Box<dyn Hittable>>,
+    objects: Vec<Arc<dyn Hittable>>,
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
         let mut temp_record = None;
         let mut closest_so_far = t_max;
 
@@ -74,68 +175,111 @@ impl Hittable for HittableList {
 
         return temp_record;
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.objects
+            .iter()
+            .map(|object| object.bounding_box())
+            .reduce(|acc, bbox| Some(acc?.surrounding(&bbox?)))?
+    }
+
+    fn specular_bounds(&self, bounds: &mut Vec<Aabb>) {
+        for object in self.objects.iter() {
+            object.specular_bounds(bounds);
+        }
+    }
+
+    fn hit_packet<'a>(&'a self, packet: &RayPacket, t_min: Float, hits: &mut PacketHits<'a>) {
+        for object in self.objects.iter() {
+            object.hit_packet(packet, t_min, hits);
+        }
+    }
 }
 
 impl HittableList {
-    pub fn new(object: Box<dyn Hittable>) -> Self {
+    pub fn new(object: Arc<dyn Hittable>) -> Self {
         let mut lst = Self::default();
         lst.add(object);
         return lst;
     }
 
-    pub fn add(&mut self, object: Box<dyn Hittable>) {
+    pub fn add(&mut self, object: Arc<dyn Hittable>) {
         self.objects.push(object);
     }
 
     pub fn clear(&mut self) {
         self.objects.clear();
     }
+
+    pub fn pop(&mut self) -> Option<Arc<dyn Hittable>> {
+        self.objects.pop()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    pub fn into_objects(self) -> Vec<Arc<dyn Hittable>> {
+        self.objects
+    }
 }
 
 pub struct Sphere {
-    pub center: Point3<f64>,
-    pub radius: f64,
-    pub mat_ptr: Box<dyn Material>, // pub mat_ptr: Rc<RefCell<dyn Material>>,
+    pub center: Point3<Float>,
+    pub radius: Float,
+    pub mat_ptr: Arc<dyn Material>,
 }
 
 impl Sphere {
-    // pub fn new(center: Point3<f64>, radius: f64, mat_ptr: Rc<RefCell<dyn Material>>) -> Self {
-    pub fn new(center: Point3<f64>, radius: f64, mat_ptr: Box<dyn Material>) -> Self {
+    pub fn new(center: Point3<Float>, radius: Float, mat_ptr: Arc<dyn Material>) -> Self {
         Self {
             center,
             radius,
             mat_ptr,
         }
     }
+
+    // u around the y axis starting at -x, v from the bottom pole to the top
+    fn uv(p: Vector3<Float>) -> (Float, Float) {
+        let theta = (-p.y).clamp(-1., 1.).acos();
+        let phi = (-p.z).atan2(p.x) + PI;
+        (phi / (2. * PI), theta / PI)
+    }
+
+    fn record<'a>(&'a self, r: &Ray, t: Float) -> HitRecord<'a> {
+        let p = r.at(t);
+        let outward_normal = (p - self.center) / self.radius;
+        let (u, v) = Self::uv(outward_normal);
+        let tangent = vec3(outward_normal.z, 0., -outward_normal.x);
+        HitRecord::new(t, p, outward_normal, r, self.mat_ptr.as_ref()).with_uv(u, v, tangent)
+    }
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = r.orig - self.center;
-        let a = r.dir.magnitude2();
-        let half_b = oc.dot(r.dir);
-        let c = oc.magnitude2() - self.radius * self.radius;
-        let discriminant = half_b * half_b - a * c;
-        if discriminant < 0. {
-            return None;
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord> {
+        stats::count(Counter::IntersectionTests);
+        let t = intersect::sphere(self.center, self.radius, r, t_min, t_max)?;
+        return Some(self.record(r, t));
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = vec3(self.radius, self.radius, self.radius).map(Float::abs);
+        Some(Aabb::new(self.center - r, self.center + r))
+    }
+
+    fn specular_bounds(&self, bounds: &mut Vec<Aabb>) {
+        if self.mat_ptr.is_specular() {
+            bounds.extend(self.bounding_box());
         }
-        let sqrtd = discriminant.sqrt();
-        let mut root = (-half_b - sqrtd) / a;
-        if root <= t_min || t_max <= root {
-            root = (-half_b + sqrtd) / a;
-            if root <= t_min || t_max <= root {
-                return None;
+    }
+
+    fn hit_packet<'a>(&'a self, packet: &RayPacket, t_min: Float, hits: &mut PacketHits<'a>) {
+        let t = intersect::sphere_packet(self.center, self.radius, packet, t_min, &hits.t_max);
+        for (lane, r) in packet.rays().iter().enumerate() {
+            stats::count(Counter::IntersectionTests);
+            if t[lane] < hits.t_max[lane] {
+                hits.set(lane, self.record(r, t[lane]));
             }
         }
-        let t = root;
-        let p = r.at(t);
-        let outward_normal = (p - self.center) / self.radius;
-        return Some(HitRecord::new(
-            t,
-            p,
-            outward_normal,
-            r,
-            self.mat_ptr.as_ref(),
-        ));
     }
 }
@@ -0,0 +1,260 @@
+use std::array;
+
+use cgmath::{InnerSpace, Point3};
+
+use crate::Real;
+
+use super::{packet::RayPacket, Ray};
+
+// Distance along `r` to the closest point of the sphere between t_min and t_max
+pub fn sphere<F: Real>(center: Point3<F>, radius: F, r: &Ray<F>, t_min: F, t_max: F) -> Option<F> {
+    let oc = r.orig - center;
+    let a = r.dir.magnitude2();
+    let half_b = oc.dot(r.dir);
+    let c = oc.magnitude2() - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < F::zero() {
+        return None;
+    }
+    let sqrtd = discriminant.sqrt();
+    let mut root = (-half_b - sqrtd) / a;
+    if root <= t_min || t_max <= root {
+        root = (-half_b + sqrtd) / a;
+        if root <= t_min || t_max <= root {
+            return None;
+        }
+    }
+    Some(root)
+}
+
+// `sphere` on every lane at once, without branches. Lanes that miss get infinity.
+pub fn sphere_packet<F: Real, const N: usize>(
+    center: Point3<F>,
+    radius: F,
+    packet: &RayPacket<F, N>,
+    t_min: F,
+    t_max: &[F; N],
+) -> [F; N] {
+    let [ox, oy, oz] = &packet.origin;
+    let [dx, dy, dz] = &packet.direction;
+    let r2 = radius * radius;
+    let mut t = [F::infinity(); N];
+    for i in 0..N {
+        let (ocx, ocy, ocz) = (ox[i] - center.x, oy[i] - center.y, oz[i] - center.z);
+        let a = dx[i] * dx[i] + dy[i] * dy[i] + dz[i] * dz[i];
+        let half_b = ocx * dx[i] + ocy * dy[i] + ocz * dz[i];
+        let c = ocx * ocx + ocy * ocy + ocz * ocz - r2;
+        let discriminant = half_b * half_b - a * c;
+        let sqrtd = discriminant.max(F::zero()).sqrt();
+        let near = (-half_b - sqrtd) / a;
+        let far = (-half_b + sqrtd) / a;
+        t[i] = if discriminant < F::zero() {
+            F::infinity()
+        } else if t_min < near && near < t_max[i] {
+            near
+        } else if t_min < far && far < t_max[i] {
+            far
+        } else {
+            F::infinity()
+        };
+    }
+    t
+}
+
+// Where a ray crosses a triangle: the distance along the ray and the barycentric
+// coordinates of the second and third vertex
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TriangleHit<F> {
+    pub t: F,
+    pub b1: F,
+    pub b2: F,
+}
+
+// Möller-Trumbore
+pub fn triangle<F: Real>(
+    [p0, p1, p2]: [Point3<F>; 3],
+    r: &Ray<F>,
+    t_min: F,
+    t_max: F,
+) -> Option<TriangleHit<F>> {
+    let e1 = p1 - p0;
+    let e2 = p2 - p0;
+    let pvec = r.dir.cross(e2);
+    let det = e1.dot(pvec);
+    if det.abs() < F::of(1e-12) {
+        return None;
+    }
+    let inv_det = F::one() / det;
+    let tvec = r.orig - p0;
+    let b1 = tvec.dot(pvec) * inv_det;
+    if !(F::zero()..=F::one()).contains(&b1) {
+        return None;
+    }
+    let qvec = tvec.cross(e1);
+    let b2 = r.dir.dot(qvec) * inv_det;
+    if b2 < F::zero() || b1 + b2 > F::one() {
+        return None;
+    }
+    let t = e2.dot(qvec) * inv_det;
+    if t <= t_min || t_max <= t {
+        return None;
+    }
+    Some(TriangleHit { t, b1, b2 })
+}
+
+// `triangle` on every lane at once, without branches. Lanes that miss get a `t` of infinity.
+pub fn triangle_packet<F: Real, const N: usize>(
+    [p0, p1, p2]: [Point3<F>; 3],
+    packet: &RayPacket<F, N>,
+    t_min: F,
+    t_max: &[F; N],
+) -> [TriangleHit<F>; N] {
+    let e1 = p1 - p0;
+    let e2 = p2 - p0;
+    let [ox, oy, oz] = &packet.origin;
+    let [dx, dy, dz] = &packet.direction;
+    array::from_fn(|i| {
+        let pvec = [
+            dy[i] * e2.z - dz[i] * e2.y,
+            dz[i] * e2.x - dx[i] * e2.z,
+            dx[i] * e2.y - dy[i] * e2.x,
+        ];
+        let det = e1.x * pvec[0] + e1.y * pvec[1] + e1.z * pvec[2];
+        let inv_det = F::one() / det;
+        let tvec = [ox[i] - p0.x, oy[i] - p0.y, oz[i] - p0.z];
+        let qvec = [
+            tvec[1] * e1.z - tvec[2] * e1.y,
+            tvec[2] * e1.x - tvec[0] * e1.z,
+            tvec[0] * e1.y - tvec[1] * e1.x,
+        ];
+        let b1 = (tvec[0] * pvec[0] + tvec[1] * pvec[1] + tvec[2] * pvec[2]) * inv_det;
+        let b2 = (dx[i] * qvec[0] + dy[i] * qvec[1] + dz[i] * qvec[2]) * inv_det;
+        let t = (e2.x * qvec[0] + e2.y * qvec[1] + e2.z * qvec[2]) * inv_det;
+        let inside = det.abs() >= F::of(1e-12)
+            && (F::zero()..=F::one()).contains(&b1)
+            && b2 >= F::zero()
+            && b1 + b2 <= F::one()
+            && t_min < t
+            && t < t_max[i];
+        TriangleHit {
+            t: if inside { t } else { F::infinity() },
+            b1,
+            b2,
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::{vec3, Point3};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    // rays from around (0, 0, -5) towards the unit cube around the origin, some missing it
+    fn rays<F: Real>(n: usize) -> Vec<Ray<F>> {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut coord = |range: std::ops::Range<f64>| F::of(rng.gen_range(range));
+        (0..n)
+            .map(|_| {
+                let orig = Point3::new(coord(-1. ..1.), coord(-1. ..1.), coord(-6. ..-4.));
+                let target = Point3::new(coord(-1.5..1.5), coord(-1.5..1.5), coord(-1. ..1.));
+                Ray::new(orig, target - orig)
+            })
+            .collect()
+    }
+
+    // the packet tests agree with the scalar ones, for full and partial packets
+    fn check_packets<F: Real, const N: usize>() {
+        let center = Point3::new(F::zero(), F::zero(), F::zero());
+        let radius = F::one();
+        let corners = [(-1., -1.), (1., -1.), (0., 1.)]
+            .map(|(x, y)| Point3::new(F::of(x), F::of(y), F::of(0.2)));
+        let t_min = F::of(0.001);
+        let rays = rays::<F>(10 * N + 3);
+        let (mut sphere_hits, mut triangle_hits) = (0, 0);
+        for len in 1..=N {
+            for chunk in rays.chunks(len) {
+                let packet = RayPacket::<F, N>::new(chunk);
+                // unused lanes never hit, like `PacketHits` sets them up
+                let t_max = array::from_fn(|i| {
+                    if i < chunk.len() {
+                        F::infinity()
+                    } else {
+                        F::zero()
+                    }
+                });
+                let spheres = sphere_packet(center, radius, &packet, t_min, &t_max);
+                let triangles = triangle_packet(corners, &packet, t_min, &t_max);
+                for (i, r) in chunk.iter().enumerate() {
+                    let expected = sphere(center, radius, r, t_min, F::infinity());
+                    assert_eq!(expected.unwrap_or(F::infinity()), spheres[i]);
+                    sphere_hits += expected.is_some() as usize;
+                    match triangle(corners, r, t_min, F::infinity()) {
+                        Some(hit) => {
+                            assert_eq!(hit, triangles[i]);
+                            triangle_hits += 1;
+                        }
+                        None => assert_eq!(triangles[i].t, F::infinity()),
+                    }
+                }
+                for i in chunk.len()..N {
+                    assert_eq!(spheres[i], F::infinity());
+                    assert_eq!(triangles[i].t, F::infinity());
+                }
+            }
+        }
+        assert!(sphere_hits > 0 && triangle_hits > 0);
+    }
+
+    #[test]
+    fn test_packets_f32() {
+        check_packets::<f32, { f32::LANES }>();
+    }
+
+    #[test]
+    fn test_packets_f64() {
+        check_packets::<f64, { f64::LANES }>();
+    }
+
+    #[test]
+    fn test_sphere() {
+        let r = Ray::new(Point3::new(0., 0., -5.), vec3(0., 0., 2.));
+        let center = Point3::new(0., 0., 0.);
+        // t is in units of the direction, which is not normalized
+        assert_eq!(sphere(center, 1., &r, 0.001, f64::INFINITY), Some(2.));
+        // from inside, the far side
+        assert_eq!(sphere(center, 1., &r, 2.5, f64::INFINITY), Some(3.));
+        assert_eq!(sphere(center, 1., &r, 0.001, 1.5), None);
+        let r = Ray::new(Point3::new(0f32, 2., -5.), vec3(0., 0., 1.));
+        assert_eq!(
+            sphere(Point3::new(0., 0., 0.), 1., &r, 0.001, f32::INFINITY),
+            None
+        );
+    }
+
+    #[test]
+    fn test_triangle() {
+        let corners = [
+            Point3::new(0., 0., 0.),
+            Point3::new(1., 0., 0.),
+            Point3::new(0., 1., 0.),
+        ];
+        let r = Ray::new(Point3::new(0.25, 0.5, 1.), vec3(0., 0., -1.));
+        let hit = triangle(corners, &r, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(
+            hit,
+            TriangleHit {
+                t: 1.,
+                b1: 0.25,
+                b2: 0.5
+            }
+        );
+        // outside the edge from the second to the third vertex
+        let r = Ray::new(Point3::new(0.75, 0.5, 1.), vec3(0., 0., -1.));
+        assert_eq!(triangle(corners, &r, 0.001, f64::INFINITY), None);
+        // parallel to the plane
+        let r = Ray::new(Point3::new(0.25, 0.25, 1.), vec3(1., 0., 0.));
+        assert_eq!(triangle(corners, &r, 0.001, f64::INFINITY), None);
+    }
+}
@@ -0,0 +1,223 @@
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use cgmath::{
+    vec3, InnerSpace, Matrix, Matrix3, Matrix4, Point3, SquareMatrix, Transform, Vector3,
+};
+
+use crate::{
+    material::Material,
+    stats::{self, Counter},
+    Float,
+};
+
+use super::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    intersect,
+    packet::{PacketHits, RayPacket},
+    Ray,
+};
+
+// Indexed triangles sharing one material. Normals, texture coordinates and tangents are
+// optional per vertex attributes; without them the triangles are shaded flat and use
+// their barycentric coordinates as uv.
+pub struct Mesh {
+    positions: Vec<Point3<Float>>,
+    normals: Option<Vec<Vector3<Float>>>,
+    uvs: Option<Vec<[Float; 2]>>,
+    tangents: Option<Vec<Vector3<Float>>>,
+    indices: Vec<[u32; 3]>,
+    material: Arc<dyn Material>,
+}
+
+impl Mesh {
+    pub fn new(
+        positions: Vec<Point3<Float>>,
+        indices: Vec<[u32; 3]>,
+        material: Arc<dyn Material>,
+    ) -> Result<Self> {
+        if let Some(i) = indices
+            .iter()
+            .flatten()
+            .find(|&&i| i as usize >= positions.len())
+        {
+            bail!("index {i} out of range for {} vertices", positions.len());
+        }
+        Ok(Self {
+            positions,
+            normals: None,
+            uvs: None,
+            tangents: None,
+            indices,
+            material,
+        })
+    }
+
+    fn check_len(&self, attribute: &str, len: usize) -> Result<()> {
+        if len != self.positions.len() {
+            bail!("{len} {attribute} for {} vertices", self.positions.len());
+        }
+        Ok(())
+    }
+
+    pub fn with_normals(mut self, normals: Vec<Vector3<Float>>) -> Result<Self> {
+        self.check_len("normals", normals.len())?;
+        self.normals = Some(normals);
+        Ok(self)
+    }
+
+    pub fn with_uvs(mut self, uvs: Vec<[Float; 2]>) -> Result<Self> {
+        self.check_len("texture coordinates", uvs.len())?;
+        self.uvs = Some(uvs);
+        Ok(self)
+    }
+
+    pub fn with_tangents(mut self, tangents: Vec<Vector3<Float>>) -> Result<Self> {
+        self.check_len("tangents", tangents.len())?;
+        self.tangents = Some(tangents);
+        Ok(self)
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    // bakes `m` into the vertices, normals go through the inverse transpose
+    pub fn transform(&mut self, m: &Matrix4<Float>) {
+        for p in self.positions.iter_mut() {
+            *p = m.transform_point(*p);
+        }
+        let linear = Matrix3::from_cols(m.x.truncate(), m.y.truncate(), m.z.truncate());
+        if let Some(normals) = &mut self.normals {
+            let normal_matrix = linear.invert().unwrap_or(linear).transpose();
+            for n in normals.iter_mut() {
+                *n = (normal_matrix * *n).normalize();
+            }
+        }
+        if let Some(tangents) = &mut self.tangents {
+            for t in tangents.iter_mut() {
+                *t = (linear * *t).normalize();
+            }
+        }
+    }
+
+    // one hittable per triangle, so they can be spread over a `Bvh`
+    pub fn triangles(self: &Arc<Self>) -> impl Iterator<Item = Arc<dyn Hittable>> + '_ {
+        (0..self.len()).map(|index| {
+            Arc::new(Triangle {
+                mesh: self.clone(),
+                index,
+            }) as Arc<dyn Hittable>
+        })
+    }
+}
+
+pub struct Triangle {
+    mesh: Arc<Mesh>,
+    index: usize,
+}
+
+impl Triangle {
+    fn vertices(&self) -> [usize; 3] {
+        self.mesh.indices[self.index].map(|i| i as usize)
+    }
+
+    fn positions(&self) -> [Point3<Float>; 3] {
+        self.vertices().map(|i| self.mesh.positions[i])
+    }
+
+    fn uvs(&self, [i0, i1, i2]: [usize; 3]) -> [[Float; 2]; 3] {
+        match &self.mesh.uvs {
+            Some(uvs) => [uvs[i0], uvs[i1], uvs[i2]],
+            None => [[0., 0.], [1., 0.], [0., 1.]],
+        }
+    }
+
+    // hit at distance `t` and barycentric coordinates `b1`, `b2` of the second and third vertex
+    fn record(&self, r: &Ray, t: Float, b1: Float, b2: Float) -> HitRecord<'_> {
+        let vertices @ [i0, i1, i2] = self.vertices();
+        let positions = &self.mesh.positions;
+        let e1 = positions[i1] - positions[i0];
+        let e2 = positions[i2] - positions[i0];
+        let b0 = 1. - b1 - b2;
+        let interpolate =
+            |a: Vector3<Float>, b: Vector3<Float>, c: Vector3<Float>| a * b0 + b * b1 + c * b2;
+
+        let geometric = e1.cross(e2).normalize();
+        let mut rec = HitRecord::new(t, r.at(t), geometric, r, self.mesh.material.as_ref());
+        if let Some(normals) = &self.mesh.normals {
+            let n = interpolate(normals[i0], normals[i1], normals[i2]);
+            if n.magnitude2() > 1e-16 {
+                let n = n.normalize();
+                rec.normal = if rec.front_face { n } else { -n };
+            }
+        }
+
+        let [uv0, uv1, uv2] = self.uvs(vertices);
+        let u = uv0[0] * b0 + uv1[0] * b1 + uv2[0] * b2;
+        let v = uv0[1] * b0 + uv1[1] * b1 + uv2[1] * b2;
+        let tangent = match &self.mesh.tangents {
+            Some(tangents) => interpolate(tangents[i0], tangents[i1], tangents[i2]),
+            None => {
+                // dp/du from the edges and their uv differences
+                let (du1, dv1) = (uv1[0] - uv0[0], uv1[1] - uv0[1]);
+                let (du2, dv2) = (uv2[0] - uv0[0], uv2[1] - uv0[1]);
+                let uv_det = du1 * dv2 - du2 * dv1;
+                if uv_det.abs() < 1e-12 {
+                    e1
+                } else {
+                    (e1 * dv2 - e2 * dv1) / uv_det
+                }
+            }
+        };
+        rec.with_uv(u, v, tangent)
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        stats::count(Counter::IntersectionTests);
+        let hit = intersect::triangle(self.positions(), r, t_min, t_max)?;
+        Some(self.record(r, hit.t, hit.b1, hit.b2))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let [i0, i1, i2] = self.vertices();
+        let p = &self.mesh.positions;
+        let pad = vec3(1e-4, 1e-4, 1e-4);
+        let min = Point3::new(
+            p[i0].x.min(p[i1].x).min(p[i2].x),
+            p[i0].y.min(p[i1].y).min(p[i2].y),
+            p[i0].z.min(p[i1].z).min(p[i2].z),
+        );
+        let max = Point3::new(
+            p[i0].x.max(p[i1].x).max(p[i2].x),
+            p[i0].y.max(p[i1].y).max(p[i2].y),
+            p[i0].z.max(p[i1].z).max(p[i2].z),
+        );
+        // flat triangles get some thickness so the slab test still hits them
+        Some(Aabb::new(min - pad, max + pad))
+    }
+
+    fn specular_bounds(&self, bounds: &mut Vec<Aabb>) {
+        if self.mesh.material.is_specular() {
+            bounds.extend(self.bounding_box());
+        }
+    }
+
+    fn hit_packet<'a>(&'a self, packet: &RayPacket, t_min: Float, hits: &mut PacketHits<'a>) {
+        let found = intersect::triangle_packet(self.positions(), packet, t_min, &hits.t_max);
+        for (lane, r) in packet.rays().iter().enumerate() {
+            stats::count(Counter::IntersectionTests);
+            let hit = found[lane];
+            if hit.t < hits.t_max[lane] {
+                hits.set(lane, self.record(r, hit.t, hit.b1, hit.b2));
+            }
+        }
+    }
+}
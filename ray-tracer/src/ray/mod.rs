@@ -1,51 +1,131 @@
+pub mod aabb;
+pub mod bvh;
 pub mod hittable;
-
-use std::f64::INFINITY;
+pub mod intersect;
+pub mod mesh;
+pub mod packet;
+pub mod transform;
 
 use cgmath::{vec3, ElementWise};
 use cgmath::{InnerSpace, Point3, Vector3};
 
-use self::hittable::Hittable;
+use crate::light::{direct_lighting, Light};
+use crate::spectral::rgb_to_spectrum;
+use crate::stats::{self, Counter};
+use crate::{Color, Float, Real};
+
+use self::hittable::{HitRecord, Hittable};
+
+// light arriving from far away along `direction`, a gradient from white to blue
+pub fn sky(direction: Vector3<Float>) -> Color {
+    let unit_direction = direction.normalize();
+    let t = 0.5 * (unit_direction.y + 1.);
+    (1. - t) * vec3(1.0, 1.0, 1.0) + t * vec3(0.5, 0.7, 1.0)
+}
 
-pub struct Ray {
-    orig: Point3<f64>,
-    dir: Vector3<f64>,
+#[derive(Clone, Copy)]
+pub struct Ray<F: Real = Float> {
+    orig: Point3<F>,
+    dir: Vector3<F>,
+    wavelength: Option<F>,
 }
 
-impl Default for Ray {
+impl<F: Real> Default for Ray<F> {
     fn default() -> Self {
         Self {
-            orig: Point3::new(0., 0., 0.),
-            dir: vec3(0., 0., 0.),
+            orig: Point3::new(F::zero(), F::zero(), F::zero()),
+            dir: vec3(F::zero(), F::zero(), F::zero()),
+            wavelength: None,
         }
     }
 }
 
-impl Ray {
-    pub fn new(orig: Point3<f64>, dir: Vector3<f64>) -> Self {
-        Self { orig, dir }
+impl<F: Real> Ray<F> {
+    pub fn new(orig: Point3<F>, dir: Vector3<F>) -> Self {
+        Self {
+            orig,
+            dir,
+            wavelength: None,
+        }
+    }
+
+    pub fn with_wavelength(mut self, wavelength: F) -> Self {
+        self.wavelength = Some(wavelength);
+        self
+    }
+
+    // new ray from a scatter event, carrying over the wavelength in spectral mode
+    pub fn bounce(&self, orig: Point3<F>, dir: Vector3<F>) -> Self {
+        Self {
+            orig,
+            dir,
+            wavelength: self.wavelength,
+        }
+    }
+
+    pub fn wavelength(&self) -> Option<F> {
+        self.wavelength
     }
 
-    pub fn at(&self, t: f64) -> Point3<f64> {
-        return self.orig + (t * self.dir);
+    pub fn at(&self, t: F) -> Point3<F> {
+        return self.orig + (self.dir * t);
     }
 
-    pub fn color(&self, world: &dyn Hittable, depth: i32) -> Vector3<f64> {
+    pub fn origin(&self) -> Point3<F> {
+        self.orig
+    }
+    pub fn direction(&self) -> Vector3<F> {
+        self.dir
+    }
+}
+
+impl Ray {
+    // collapses an rgb color to the ray's wavelength when tracing spectrally
+    pub(crate) fn project(&self, color: Color) -> Color {
+        match self.wavelength {
+            Some(wavelength) => {
+                let s = rgb_to_spectrum(color, wavelength);
+                vec3(s, s, s)
+            }
+            None => color,
+        }
+    }
+
+    pub fn color(&self, world: &dyn Hittable, lights: &[Light], depth: i32) -> Vector3<Float> {
         if depth <= 0 {
             return vec3(0., 0., 0.);
         }
 
-        if let Some(rec) = world.hit(self, 0.001, INFINITY) {
+        let hit = world.hit(self, 0.001, Float::INFINITY);
+        self.shade(hit, world, lights, depth)
+    }
+
+    // `color` of the ray when its closest hit in `world` is already known, for rays
+    // intersected in packets
+    pub fn shade(
+        &self,
+        hit: Option<HitRecord>,
+        world: &dyn Hittable,
+        lights: &[Light],
+        depth: i32,
+    ) -> Color {
+        if let Some(rec) = hit {
+            let mut color = self.project(rec.mat_ptr.emitted(&rec));
+            if !lights.is_empty() {
+                color += self.project(direct_lighting(world, lights, self, &rec));
+            }
             if let Some((attenuation, scattered)) = rec.mat_ptr.scatter(self, &rec) {
-                return attenuation.mul_element_wise(scattered.color(world, depth - 1));
+                if depth > 1 {
+                    stats::count(Counter::BounceRays);
+                }
+                color += self.project(attenuation).mul_element_wise(scattered.color(
+                    world,
+                    lights,
+                    depth - 1,
+                ));
             }
-            return vec3(0., 0., 0.);
+            return color;
         }
-        let unit_direction = self.dir.normalize();
-        let t = 0.5 * (unit_direction.y + 1.);
-        return (1. - t) * vec3(1.0, 1.0, 1.0) + t * vec3(0.5, 0.7, 1.0);
-    }
-    pub fn direction(&self) -> Vector3<f64> {
-        self.dir
+        return self.project(sky(self.dir));
     }
 }
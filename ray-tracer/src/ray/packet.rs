@@ -0,0 +1,81 @@
+use std::array;
+
+use cgmath::Point3;
+
+use crate::{Float, Real};
+
+use super::{hittable::HitRecord, Ray};
+
+// Rays per packet at the precision of the renderer: 4 in double precision and 8 with the
+// `f32` feature
+pub const LANES: usize = <Float as Real>::LANES;
+
+// One value per lane
+pub type Lanes<F = Float, const N: usize = LANES> = [F; N];
+
+// Up to N rays stored component by component, so the loops over the lanes in the
+// intersection tests compile to vector instructions
+pub struct RayPacket<'r, F: Real = Float, const N: usize = LANES> {
+    rays: &'r [Ray<F>],
+    pub origin: [Lanes<F, N>; 3],
+    pub direction: [Lanes<F, N>; 3],
+    pub inv_direction: [Lanes<F, N>; 3],
+}
+
+impl<'r, F: Real, const N: usize> RayPacket<'r, F, N> {
+    // lanes past the end of `rays` repeat the first ray and are never hit
+    pub fn new(rays: &'r [Ray<F>]) -> Self {
+        assert!(
+            !rays.is_empty() && rays.len() <= N,
+            "a packet holds 1 to {N} rays"
+        );
+        let lane = |i: usize| &rays[if i < rays.len() { i } else { 0 }];
+        let origin = [0, 1, 2].map(|a| array::from_fn(|i| lane(i).orig[a]));
+        let direction = [0, 1, 2].map(|a| array::from_fn(|i| lane(i).dir[a]));
+        let inv_direction = direction.map(|d: Lanes<F, N>| d.map(|d| F::one() / d));
+        Self {
+            rays,
+            origin,
+            direction,
+            inv_direction,
+        }
+    }
+
+    pub fn rays(&self) -> &'r [Ray<F>] {
+        self.rays
+    }
+
+    pub fn origin(&self, lane: usize) -> Point3<F> {
+        Point3::new(
+            self.origin[0][lane],
+            self.origin[1][lane],
+            self.origin[2][lane],
+        )
+    }
+}
+
+// Closest hits found so far for the rays of a packet
+pub struct PacketHits<'a> {
+    // rays only look for hits closer than this, it starts at 0 for the unused lanes
+    pub t_max: Lanes,
+    pub records: [Option<HitRecord<'a>>; LANES],
+}
+
+impl<'a> PacketHits<'a> {
+    pub fn new(packet: &RayPacket, t_max: Float) -> Self {
+        Self {
+            t_max: array::from_fn(|i| if i < packet.rays.len() { t_max } else { 0. }),
+            records: array::from_fn(|_| None),
+        }
+    }
+
+    pub fn set(&mut self, lane: usize, rec: HitRecord<'a>) {
+        self.t_max[lane] = rec.t;
+        self.records[lane] = Some(rec);
+    }
+
+    // hit records of the rays of the packet, in order
+    pub fn into_records(self, len: usize) -> impl Iterator<Item = Option<HitRecord<'a>>> {
+        self.records.into_iter().take(len)
+    }
+}
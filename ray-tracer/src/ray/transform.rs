@@ -0,0 +1,63 @@
+use cgmath::{InnerSpace, Matrix, Matrix4, SquareMatrix, Transform};
+
+use crate::Float;
+
+use super::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    Ray,
+};
+
+// Places an object in the world with an affine transform. Rays are moved into object
+// space instead of moving the object, so whatever the object built (e.g. a `Bvh`) is reused.
+pub struct Transformed<H: Hittable> {
+    object: H,
+    matrix: Matrix4<Float>,
+    inverse: Matrix4<Float>,
+}
+
+impl<H: Hittable> Transformed<H> {
+    // None if the matrix is not invertible
+    pub fn new(object: H, matrix: Matrix4<Float>) -> Option<Self> {
+        let inverse = matrix.invert()?;
+        Some(Self {
+            object,
+            matrix,
+            inverse,
+        })
+    }
+}
+
+impl<H: Hittable> Hittable for Transformed<H> {
+    fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        // the direction is not normalized, so t is the same in both spaces
+        let local = r.bounce(
+            self.inverse.transform_point(r.orig),
+            self.inverse.transform_vector(r.dir),
+        );
+        let mut rec = self.object.hit(&local, t_min, t_max)?;
+        rec.p = self.matrix.transform_point(rec.p);
+        rec.tangent = self.matrix.transform_vector(rec.tangent).normalize();
+        rec.normal = self
+            .inverse
+            .transpose()
+            .transform_vector(rec.normal)
+            .normalize();
+        rec.tangent = (rec.tangent - rec.normal * rec.tangent.dot(rec.normal)).normalize();
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.object
+            .bounding_box()
+            .map(|bbox| bbox.transform(&self.matrix))
+    }
+
+    fn specular_bounds(&self, bounds: &mut Vec<Aabb>) {
+        let start = bounds.len();
+        self.object.specular_bounds(bounds);
+        for bbox in bounds[start..].iter_mut() {
+            *bbox = bbox.transform(&self.matrix);
+        }
+    }
+}
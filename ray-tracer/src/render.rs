@@ -0,0 +1,433 @@
+use std::{
+    io::Write,
+    str::FromStr,
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use cgmath::vec3;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::Camera,
+    light::Light,
+    photon, random,
+    ray::{
+        hittable::Hittable,
+        packet::{PacketHits, RayPacket, LANES},
+        Ray,
+    },
+    seed_random, spectral,
+    stats::{self, Counter, Counters, RenderStats, TileStats},
+    wavefront, Color, Float,
+};
+
+// edge length of the square tiles the image is split into
+pub const TILE_SIZE: u32 = 16;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RenderSettings {
+    pub image_width: u32,
+    pub image_height: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: i32,
+    // when set every pixel reseeds its generator, so renders are reproducible
+    pub seed: Option<u64>,
+    pub spectral: bool,
+    pub integrator: Integrator,
+    // intersect the camera rays of a pixel in packets of `LANES`
+    pub packets: bool,
+    // trace the paths of several tiles together in stages instead of one recursive path
+    // at a time, see `wavefront::render_tiles`
+    pub wavefront: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Integrator {
+    #[default]
+    Path,
+    // Progressive photon mapping for caustics: every sample per pixel is a pass that
+    // traces `photons_per_pass` photons and one eye path per pixel, shrinking the photon
+    // gather radius from `radius` (or a size picked from the scene) as passes go on.
+    Photon {
+        photons_per_pass: u32,
+        radius: Option<Float>,
+    },
+}
+
+impl Integrator {
+    pub const PHOTONS_PER_PASS: u32 = 100_000;
+}
+
+impl FromStr for Integrator {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "path" => Ok(Self::Path),
+            "photon" => Ok(Self::Photon {
+                photons_per_pass: Self::PHOTONS_PER_PASS,
+                radius: None,
+            }),
+            _ => Err(anyhow!("unknown integrator {s:?}, expected path or photon")),
+        }
+    }
+}
+
+impl RenderSettings {
+    pub fn aspect_ratio(&self) -> Float {
+        self.image_width as Float / self.image_height as Float
+    }
+}
+
+// splitmix64 finalizer, spreads neighbouring pixel indices over the seed space
+pub fn pixel_seed(seed: u64, idx: u64) -> u64 {
+    let mut z = seed.wrapping_add((idx + 1).wrapping_mul(0x9E3779B97F4A7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+// Camera ray through a random point of pixel (i, j), j counting up from the bottom row
+pub fn camera_ray(cam: &Camera, settings: &RenderSettings, i: u32, j: u32) -> Option<Ray> {
+    let u = (i as Float + random(0. ..1.)) / (settings.image_width - 1) as Float;
+    let v = (j as Float + random(0. ..1.)) / (settings.image_height - 1) as Float;
+    stats::count(Counter::CameraRays);
+    cam.get_ray(u, v)
+}
+
+// `sample_pixel` with the camera rays traced `LANES` at a time
+fn sample_pixel_packets(
+    world: &dyn Hittable,
+    lights: &[Light],
+    cam: &Camera,
+    settings: &RenderSettings,
+    i: u32,
+    j: u32,
+) -> Color {
+    let mut acc = vec3(0., 0., 0.);
+    let mut remaining = settings.samples_per_pixel;
+    while remaining > 0 {
+        let n = remaining.min(LANES as u32);
+        remaining -= n;
+        let rays: Vec<Ray> = (0..n)
+            .filter_map(|_| camera_ray(cam, settings, i, j))
+            .collect();
+        if rays.is_empty() {
+            continue;
+        }
+        let packet = RayPacket::new(&rays);
+        let mut hits = PacketHits::new(&packet, Float::INFINITY);
+        world.hit_packet(&packet, 0.001, &mut hits);
+        let records = hits.into_records(rays.len());
+        for (ray, hit) in rays.into_iter().zip(records) {
+            let radiance = |ray: Ray| ray.shade(hit, world, lights, settings.max_depth);
+            let color = if settings.spectral {
+                spectral::trace(ray, radiance)
+            } else {
+                radiance(ray)
+            };
+            acc += color * cam.exposure();
+        }
+    }
+    acc
+}
+
+// Sum of `samples_per_pixel` samples for pixel (i, j), j counting up from the bottom row
+pub fn sample_pixel(
+    world: &dyn Hittable,
+    lights: &[Light],
+    cam: &Camera,
+    settings: &RenderSettings,
+    i: u32,
+    j: u32,
+) -> Color {
+    if settings.packets && settings.max_depth > 0 {
+        return sample_pixel_packets(world, lights, cam, settings, i, j);
+    }
+    (0..settings.samples_per_pixel).fold(vec3(0., 0., 0.), |acc, _| {
+        let Some(ray) = camera_ray(cam, settings, i, j) else {
+            return acc;
+        };
+        let radiance = |ray: Ray| ray.color(world, lights, settings.max_depth);
+        let color = if settings.spectral {
+            spectral::trace(ray, radiance)
+        } else {
+            radiance(ray)
+        };
+        acc + color * cam.exposure()
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl RenderSettings {
+    // Tiles covering the image in row major order, `y` counting down from the top row
+    pub fn tiles(&self) -> Vec<Tile> {
+        (0..self.image_height)
+            .step_by(TILE_SIZE as usize)
+            .flat_map(|y| {
+                (0..self.image_width)
+                    .step_by(TILE_SIZE as usize)
+                    .map(move |x| Tile {
+                        x,
+                        y,
+                        width: TILE_SIZE.min(self.image_width - x),
+                        height: TILE_SIZE.min(self.image_height - y),
+                    })
+            })
+            .collect()
+    }
+}
+
+// Renders one tile on the current thread, returning its averaged colors in row major order
+pub fn render_tile(
+    world: &dyn Hittable,
+    lights: &[Light],
+    cam: &Camera,
+    settings: &RenderSettings,
+    tile: Tile,
+) -> Vec<Color> {
+    if settings.wavefront {
+        return wavefront::render_tile(world, lights, cam, settings, tile);
+    }
+    let mut pixels = Vec::with_capacity((tile.width * tile.height) as usize);
+    for y in tile.y..tile.y + tile.height {
+        for x in tile.x..tile.x + tile.width {
+            if let Some(seed) = settings.seed {
+                let idx = y as u64 * settings.image_width as u64 + x as u64;
+                seed_random(pixel_seed(seed, idx));
+            }
+            let j = settings.image_height - 1 - y;
+            let color = sample_pixel(world, lights, cam, settings, x, j);
+            pixels.push(color / settings.samples_per_pixel as Float);
+        }
+    }
+    pixels
+}
+
+// A rendered tile with the time it took and the rays it traced
+pub struct RenderedTile {
+    pub pixels: Vec<Color>,
+    pub stats: TileStats,
+    pub counters: Counters,
+}
+
+// Renders one tile on the current thread and measures it
+pub fn render_tile_stats(
+    world: &dyn Hittable,
+    lights: &[Light],
+    cam: &Camera,
+    settings: &RenderSettings,
+    tile: Tile,
+) -> RenderedTile {
+    stats::take();
+    let tile_start = Instant::now();
+    let pixels = render_tile(world, lights, cam, settings, tile);
+    RenderedTile {
+        pixels,
+        stats: TileStats {
+            x: tile.x,
+            y: tile.y,
+            width: tile.width,
+            height: tile.height,
+            seconds: tile_start.elapsed().as_secs_f64(),
+        },
+        counters: stats::take(),
+    }
+}
+
+// Renders tiles on the current thread and measures them. Wavefront renders trace the tiles
+// together, sharing the time out by pixel count and counting all of their rays in the first.
+pub fn render_tiles_stats(
+    world: &dyn Hittable,
+    lights: &[Light],
+    cam: &Camera,
+    settings: &RenderSettings,
+    tiles: &[Tile],
+) -> Vec<RenderedTile> {
+    if !settings.wavefront {
+        return tiles
+            .iter()
+            .map(|&tile| render_tile_stats(world, lights, cam, settings, tile))
+            .collect();
+    }
+    stats::take();
+    let start = Instant::now();
+    let pixels = wavefront::render_tiles(world, lights, cam, settings, tiles);
+    let seconds = start.elapsed().as_secs_f64();
+    let mut counters = Some(stats::take());
+    let total = pixels.iter().map(Vec::len).sum::<usize>() as f64;
+    tiles
+        .iter()
+        .zip(pixels)
+        .map(|(tile, pixels)| RenderedTile {
+            stats: TileStats {
+                x: tile.x,
+                y: tile.y,
+                width: tile.width,
+                height: tile.height,
+                seconds: seconds * pixels.len() as f64 / total,
+            },
+            pixels,
+            counters: counters.take().unwrap_or_default(),
+        })
+        .collect()
+}
+
+// Renders the whole image, returning averaged linear colors in row major order from the top row
+pub fn render(
+    world: &dyn Hittable,
+    lights: &[Light],
+    cam: &Camera,
+    settings: &RenderSettings,
+) -> (Vec<Color>, RenderStats) {
+    if let Integrator::Photon {
+        photons_per_pass,
+        radius,
+    } = settings.integrator
+    {
+        return photon::render(world, lights, cam, settings, photons_per_pass, radius);
+    }
+    let start = Instant::now();
+    let tiles = settings.tiles();
+    let batch = if settings.wavefront {
+        wavefront::BATCH_TILES
+    } else {
+        1
+    };
+    let done = AtomicU32::new(0);
+    let rendered: Vec<RenderedTile> = tiles
+        .par_chunks(batch)
+        .flat_map_iter(|batch| {
+            let rendered = render_tiles_stats(world, lights, cam, settings, batch);
+            let prev = done.fetch_add(batch.len() as u32, Ordering::Relaxed);
+            report_progress(prev + batch.len() as u32, tiles.len());
+            rendered
+        })
+        .collect();
+    eprintln!("\nDone. ");
+    assemble(settings, rendered, start.elapsed())
+}
+
+pub fn report_progress(done: u32, total: usize) {
+    eprint!("\r{:.2}%", (done as Float / total as Float) * 100.);
+    std::io::stderr().flush().unwrap();
+}
+
+// Places rendered tiles into one image and combines their statistics
+pub fn assemble(
+    settings: &RenderSettings,
+    rendered: Vec<RenderedTile>,
+    elapsed: Duration,
+) -> (Vec<Color>, RenderStats) {
+    let mut pixels =
+        vec![vec3(0., 0., 0.); (settings.image_width * settings.image_height) as usize];
+    let mut counters = Counters::default();
+    let mut tile_stats = Vec::with_capacity(rendered.len());
+    for RenderedTile {
+        pixels: tile_pixels,
+        stats: tile,
+        counters: tile_counters,
+    } in rendered
+    {
+        for (row, chunk) in tile_pixels.chunks(tile.width as usize).enumerate() {
+            let start = ((tile.y + row as u32) * settings.image_width + tile.x) as usize;
+            pixels[start..start + chunk.len()].copy_from_slice(chunk);
+        }
+        counters += tile_counters;
+        tile_stats.push(tile);
+    }
+    let samples = settings.image_width as u64
+        * settings.image_height as u64
+        * settings.samples_per_pixel as u64;
+    let stats = RenderStats::new(counters, samples, elapsed, tile_stats);
+    (pixels, stats)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use cgmath::Point3;
+
+    use super::*;
+    use crate::{
+        camera::CameraSettings,
+        material::{Dielectric, Lambertian, Metal},
+        ray::hittable::{HittableList, Sphere},
+    };
+
+    fn scene() -> (HittableList, Vec<Light>) {
+        let mut world = HittableList::default();
+        let ground = Lambertian::new(vec3(0.5, 0.5, 0.5));
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0., -1000., 0.),
+            1000.,
+            ground,
+        )));
+        let glass = Dielectric::new(1.5);
+        world.add(Arc::new(Sphere::new(Point3::new(0., 1., 0.), 1., glass)));
+        let steel = Metal::new(vec3(0.7, 0.6, 0.5), 0.1);
+        world.add(Arc::new(Sphere::new(Point3::new(4., 1., 0.), 1., steel)));
+        let lights = vec![Light::Point {
+            position: Point3::new(2., 5., 3.),
+            intensity: vec3(20., 20., 20.),
+        }];
+        (world, lights)
+    }
+
+    // The wavefront renderer draws the same random numbers for each sample as the
+    // recursive one, so a seeded image comes out the same from both
+    #[test]
+    fn test_wavefront_matches_recursive() {
+        let (world, lights) = scene();
+        for (packets, spectral) in [(false, false), (true, false), (false, true), (true, true)] {
+            let settings = RenderSettings {
+                // not a multiple of the tile size, and more samples than fit in a packet
+                image_width: 40,
+                image_height: 27,
+                samples_per_pixel: LANES as u32 + 1,
+                max_depth: 5,
+                seed: Some(11),
+                spectral,
+                integrator: Integrator::Path,
+                packets,
+                wavefront: false,
+            };
+            let cam = CameraSettings::default()
+                .build(settings.aspect_ratio())
+                .unwrap();
+            let tiles = settings.tiles();
+            let recursive: Vec<Vec<Color>> = tiles
+                .iter()
+                .map(|&tile| render_tile(&world, &lights, &cam, &settings, tile))
+                .collect();
+            assert!(recursive.iter().flatten().any(|c| c.x > 0.));
+            for &tile in &tiles[..2] {
+                let pixels = wavefront::render_tile(&world, &lights, &cam, &settings, tile);
+                let expected = &recursive[tiles.iter().position(|&t| t == tile).unwrap()];
+                assert_eq!(&pixels, expected, "packets {packets}, spectral {spectral}");
+            }
+            let batched = wavefront::render_tiles(&world, &lights, &cam, &settings, &tiles);
+            assert_eq!(batched, recursive, "packets {packets}, spectral {spectral}");
+
+            let (image, _) = render(&world, &lights, &cam, &settings);
+            let wavefront_settings = RenderSettings {
+                wavefront: true,
+                ..settings
+            };
+            let (wavefront_image, stats) = render(&world, &lights, &cam, &wavefront_settings);
+            assert_eq!(wavefront_image, image);
+            assert_eq!(stats.tiles.len(), tiles.len());
+        }
+    }
+}
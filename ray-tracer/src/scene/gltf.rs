@@ -0,0 +1,451 @@
+use std::{collections::HashMap, fs, path::Path, sync::Arc};
+
+use ::gltf::{
+    buffer, camera::Projection, image, khr_lights_punctual::Kind, material::AlphaMode, mesh::Mode,
+    texture::WrappingMode, Document, Gltf, Node,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use cgmath::{vec3, Deg, InnerSpace, Matrix4, Point3, Rad, SquareMatrix, Transform, Vector3};
+
+use crate::{
+    camera::CameraSettings,
+    light::Light,
+    material::{Material, MetallicRoughness, NormalMap},
+    ray::{hittable::Hittable, mesh::Mesh},
+    texture::{ImageTexture, Texture},
+    Float,
+};
+
+// extensions the importer understands, anything else in `extensionsUsed` is reported
+const SUPPORTED_EXTENSIONS: [&str; 2] = ["KHR_lights_punctual", "KHR_materials_emissive_strength"];
+
+// Everything imported from a glTF file. Features the ray tracer has no equivalent for are
+// skipped and described in `warnings` instead of failing the whole import.
+pub struct Import {
+    pub objects: Vec<Arc<dyn Hittable>>,
+    pub lights: Vec<Light>,
+    // the first camera found in the scene graph
+    pub camera: Option<CameraSettings>,
+    pub warnings: Vec<String>,
+}
+
+// Imports the default scene of a .gltf or .glb file with its meshes, cameras, punctual
+// lights and metallic-roughness materials. Node transforms are baked into the vertices.
+pub fn import(path: &Path) -> Result<Import> {
+    let gltf = Gltf::open(path).with_context(|| format!("reading glTF {}", path.display()))?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    let buffers = gltf
+        .buffers()
+        .map(|b| load_buffer(&b, base, gltf.blob.as_deref()))
+        .collect::<Result<Vec<_>>>()
+        .with_context(|| format!("loading buffers of {}", path.display()))?;
+    let mut importer = Importer {
+        document: &gltf.document,
+        base,
+        buffers,
+        textures: HashMap::new(),
+        materials: HashMap::new(),
+        import: Import {
+            objects: vec![],
+            lights: vec![],
+            camera: None,
+            warnings: vec![],
+        },
+    };
+    importer.run();
+    Ok(importer.import)
+}
+
+fn decode_data_uri(uri: &str) -> Option<Result<Vec<u8>>> {
+    let (_, data) = uri.strip_prefix("data:")?.split_once(";base64,")?;
+    Some(STANDARD.decode(data).context("decoding data uri"))
+}
+
+// relative uris may escape characters like spaces as %20
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn load_buffer(buffer: &buffer::Buffer, base: &Path, blob: Option<&[u8]>) -> Result<Vec<u8>> {
+    let data = match buffer.source() {
+        buffer::Source::Bin => blob
+            .ok_or_else(|| anyhow!("buffer {} refers to a missing binary chunk", buffer.index()))?
+            .to_vec(),
+        buffer::Source::Uri(uri) => match decode_data_uri(uri) {
+            Some(data) => data?,
+            None => {
+                let path = base.join(percent_decode(uri));
+                fs::read(&path).with_context(|| format!("reading {}", path.display()))?
+            }
+        },
+    };
+    if data.len() < buffer.length() {
+        bail!(
+            "buffer {} has {} bytes, expected {}",
+            buffer.index(),
+            data.len(),
+            buffer.length()
+        );
+    }
+    Ok(data)
+}
+
+fn matrix(m: [[f32; 4]; 4]) -> Matrix4<Float> {
+    Matrix4::from(m.map(|col| col.map(|v| v as Float)))
+}
+
+fn color(c: [f32; 3]) -> Vector3<Float> {
+    vec3(c[0] as Float, c[1] as Float, c[2] as Float)
+}
+
+fn describe(kind: &str, index: Option<usize>, name: Option<&str>) -> String {
+    match (name, index) {
+        (Some(name), _) => format!("{kind} {name:?}"),
+        (None, Some(index)) => format!("{kind} {index}"),
+        (None, None) => format!("default {kind}"),
+    }
+}
+
+struct Importer<'a> {
+    document: &'a Document,
+    base: &'a Path,
+    buffers: Vec<Vec<u8>>,
+    // keyed by texture index and whether it holds sRGB colors
+    textures: HashMap<(usize, bool), Option<Arc<dyn Texture>>>,
+    materials: HashMap<Option<usize>, Arc<dyn Material>>,
+    import: Import,
+}
+
+impl<'a> Importer<'a> {
+    fn warn(&mut self, warning: String) {
+        if !self.import.warnings.contains(&warning) {
+            self.import.warnings.push(warning);
+        }
+    }
+
+    fn run(&mut self) {
+        for extension in self.document.extensions_used() {
+            if !SUPPORTED_EXTENSIONS.contains(&extension) {
+                self.warn(format!(
+                    "extension {extension} is not supported and was ignored"
+                ));
+            }
+        }
+        let animations = self.document.animations().count();
+        if animations > 0 {
+            self.warn(format!("{animations} animation(s) ignored"));
+        }
+        let scene = self
+            .document
+            .default_scene()
+            .or_else(|| self.document.scenes().next());
+        let Some(scene) = scene else {
+            self.warn("the file contains no scene".to_string());
+            return;
+        };
+        let scenes = self.document.scenes().count();
+        if scenes > 1 {
+            self.warn(format!(
+                "{scenes} scenes, only {} was imported",
+                describe("scene", Some(scene.index()), scene.name())
+            ));
+        }
+        for node in scene.nodes() {
+            self.node(&node, Matrix4::identity());
+        }
+    }
+
+    fn node(&mut self, node: &Node, parent: Matrix4<Float>) {
+        let world = parent * matrix(node.transform().matrix());
+        let name = describe("node", Some(node.index()), node.name());
+        if node.skin().is_some() {
+            self.warn(format!(
+                "{name}: skinning ignored, the mesh is used in its bind pose"
+            ));
+        }
+        if let Some(mesh) = node.mesh() {
+            self.mesh(&mesh, &world);
+        }
+        if let Some(camera) = node.camera() {
+            self.camera(&camera, &world, &name);
+        }
+        if let Some(light) = node.light() {
+            self.light(&light, &world, &name);
+        }
+        for child in node.children() {
+            self.node(&child, world);
+        }
+    }
+
+    fn camera(&mut self, camera: &::gltf::Camera, world: &Matrix4<Float>, name: &str) {
+        let Projection::Perspective(perspective) = camera.projection() else {
+            self.warn(format!("{name}: orthographic cameras are not supported"));
+            return;
+        };
+        if self.import.camera.is_some() {
+            self.warn(format!("{name}: only the first camera is used"));
+            return;
+        }
+        let lookfrom = world.transform_point(Point3::new(0., 0., 0.));
+        let forward = world.transform_vector(vec3(0., 0., -1.)).normalize();
+        let vup = world.transform_vector(vec3(0., 1., 0.)).normalize();
+        self.import.camera = Some(CameraSettings {
+            lookfrom,
+            lookat: lookfrom + forward,
+            vup,
+            vfov: Deg::from(Rad(perspective.yfov() as Float)),
+            aperture: 0.,
+            focus_dist: 1.,
+            ..CameraSettings::default()
+        });
+    }
+
+    fn light(
+        &mut self,
+        light: &::gltf::khr_lights_punctual::Light,
+        world: &Matrix4<Float>,
+        name: &str,
+    ) {
+        if light.range().is_some() {
+            self.warn(format!(
+                "{name}: light range ignored, lights fall off with distance squared"
+            ));
+        }
+        let intensity = color(light.color()) * light.intensity() as Float;
+        let position = world.transform_point(Point3::new(0., 0., 0.));
+        let direction = world.transform_vector(vec3(0., 0., -1.)).normalize();
+        self.import.lights.push(match light.kind() {
+            Kind::Directional => Light::Directional {
+                direction,
+                irradiance: intensity,
+            },
+            Kind::Point => Light::Point {
+                position,
+                intensity,
+            },
+            Kind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => Light::Spot {
+                position,
+                direction,
+                intensity,
+                cos_inner: (inner_cone_angle as Float).cos(),
+                cos_outer: (outer_cone_angle as Float).cos(),
+            },
+        });
+    }
+
+    fn mesh(&mut self, mesh: &::gltf::Mesh, world: &Matrix4<Float>) {
+        let name = describe("mesh", Some(mesh.index()), mesh.name());
+        for primitive in mesh.primitives() {
+            if primitive.mode() != Mode::Triangles {
+                self.warn(format!(
+                    "{name}: {:?} primitives are not supported, only triangles",
+                    primitive.mode()
+                ));
+                continue;
+            }
+            if primitive.morph_targets().next().is_some() {
+                self.warn(format!("{name}: morph targets ignored"));
+            }
+            if primitive.get(&::gltf::Semantic::Colors(0)).is_some() {
+                self.warn(format!("{name}: vertex colors ignored"));
+            }
+            let material = self.material(&primitive.material());
+            match self.primitive(&primitive, material) {
+                Ok(mut mesh) => {
+                    mesh.transform(world);
+                    let mesh = Arc::new(mesh);
+                    self.import.objects.extend(mesh.triangles());
+                }
+                Err(err) => self.warn(format!(
+                    "{name}: primitive {} skipped: {err:#}",
+                    primitive.index()
+                )),
+            }
+        }
+    }
+
+    fn primitive(
+        &mut self,
+        primitive: &::gltf::Primitive,
+        material: Arc<dyn Material>,
+    ) -> Result<Mesh> {
+        let buffers = &self.buffers;
+        let reader = primitive.reader(|b| buffers.get(b.index()).map(|data| data.as_slice()));
+        let positions: Vec<_> = reader
+            .read_positions()
+            .ok_or_else(|| anyhow!("no positions"))?
+            .map(|[x, y, z]| Point3::new(x as Float, y as Float, z as Float))
+            .collect();
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+        let triangles = indices
+            .chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]])
+            .collect();
+        let mut mesh = Mesh::new(positions, triangles, material)?;
+        if let Some(normals) = reader.read_normals() {
+            let normals = normals.map(color).collect();
+            mesh = mesh.with_normals(normals)?;
+        }
+        if let Some(uvs) = reader.read_tex_coords(0) {
+            // glTF puts v = 0 at the top of the image, the textures here at the bottom
+            let uvs = uvs
+                .into_f32()
+                .map(|[u, v]| [u as Float, 1. - v as Float])
+                .collect();
+            mesh = mesh.with_uvs(uvs)?;
+        }
+        if let Some(tangents) = reader.read_tangents() {
+            let mut mirrored = false;
+            let tangents = tangents
+                .map(|[x, y, z, w]| {
+                    mirrored |= w < 0.;
+                    color([x, y, z])
+                })
+                .collect();
+            mesh = mesh.with_tangents(tangents)?;
+            if mirrored {
+                self.warn(format!(
+                    "primitive {}: mirrored texture coordinates, normal maps bend the wrong way there",
+                    primitive.index()
+                ));
+            }
+        }
+        Ok(mesh)
+    }
+
+    fn material(&mut self, material: &::gltf::Material) -> Arc<dyn Material> {
+        if let Some(cached) = self.materials.get(&material.index()) {
+            return cached.clone();
+        }
+        let name = describe("material", material.index(), material.name());
+        if material.alpha_mode() != AlphaMode::Opaque {
+            self.warn(format!(
+                "{name}: alpha mode {:?} ignored, rendered opaque",
+                material.alpha_mode()
+            ));
+        }
+        let pbr = material.pbr_metallic_roughness();
+        let [r, g, b, _] = pbr.base_color_factor();
+        let emissive_strength = material.emissive_strength().unwrap_or(1.) as Float;
+        let mut result: Arc<dyn Material> = Arc::new(MetallicRoughness {
+            base_color: color([r, g, b]),
+            base_color_texture: pbr
+                .base_color_texture()
+                .and_then(|info| self.texture(&name, &info.texture(), info.tex_coord(), true)),
+            metallic: pbr.metallic_factor() as Float,
+            roughness: pbr.roughness_factor() as Float,
+            metallic_roughness_texture: pbr
+                .metallic_roughness_texture()
+                .and_then(|info| self.texture(&name, &info.texture(), info.tex_coord(), false)),
+            emissive: color(material.emissive_factor()) * emissive_strength,
+            emissive_texture: material
+                .emissive_texture()
+                .and_then(|info| self.texture(&name, &info.texture(), info.tex_coord(), true)),
+        });
+        if let Some(normal) = material.normal_texture() {
+            if let Some(map) = self.texture(&name, &normal.texture(), normal.tex_coord(), false) {
+                result = NormalMap::new(result, map, normal.scale() as Float);
+            }
+        }
+        // ambient occlusion maps are left out on purpose, paths find the occlusion themselves
+        self.materials.insert(material.index(), result.clone());
+        result
+    }
+
+    fn texture(
+        &mut self,
+        material: &str,
+        texture: &::gltf::Texture,
+        tex_coord: u32,
+        srgb: bool,
+    ) -> Option<Arc<dyn Texture>> {
+        if tex_coord != 0 {
+            self.warn(format!(
+                "{material}: texture {} uses TEXCOORD_{tex_coord}, only TEXCOORD_0 is supported",
+                texture.index()
+            ));
+            return None;
+        }
+        let key = (texture.index(), srgb);
+        if let Some(cached) = self.textures.get(&key) {
+            return cached.clone();
+        }
+        let sampler = texture.sampler();
+        if sampler.wrap_s() != WrappingMode::Repeat || sampler.wrap_t() != WrappingMode::Repeat {
+            self.warn(format!(
+                "texture {}: wrap mode {:?}/{:?} ignored, textures repeat",
+                texture.index(),
+                sampler.wrap_s(),
+                sampler.wrap_t()
+            ));
+        }
+        let loaded = match self.image(&texture.source(), srgb) {
+            Ok(image) => Some(image as Arc<dyn Texture>),
+            Err(err) => {
+                self.warn(format!(
+                    "{material}: texture {} skipped: {err:#}",
+                    texture.index()
+                ));
+                None
+            }
+        };
+        self.textures.insert(key, loaded.clone());
+        loaded
+    }
+
+    fn image(&self, image: &image::Image, srgb: bool) -> Result<Arc<ImageTexture>> {
+        let decode = |bytes: &[u8]| {
+            if srgb {
+                ImageTexture::decode_srgb(bytes)
+            } else {
+                ImageTexture::decode(bytes)
+            }
+        };
+        match image.source() {
+            image::Source::View { view, .. } => {
+                let buffer = &self.buffers[view.buffer().index()];
+                let bytes = buffer
+                    .get(view.offset()..view.offset() + view.length())
+                    .ok_or_else(|| anyhow!("buffer view {} out of range", view.index()))?;
+                decode(bytes)
+            }
+            image::Source::Uri { uri, .. } => match decode_data_uri(uri) {
+                Some(bytes) => decode(&bytes?),
+                None => {
+                    let path = self.base.join(percent_decode(uri));
+                    if srgb {
+                        ImageTexture::load_srgb(&path)
+                    } else {
+                        ImageTexture::load(&path)
+                    }
+                }
+            },
+        }
+    }
+}
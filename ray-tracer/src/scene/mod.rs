@@ -0,0 +1,660 @@
+pub mod gltf;
+
+use std::{collections::HashMap, fs, path::Path, str::FromStr, sync::Arc};
+
+use anyhow::{anyhow, bail, Context, Result};
+use cgmath::{vec3, Deg, ElementWise, EuclideanSpace, InnerSpace, Point3, Vector3};
+
+use crate::{
+    animation::{AnimatedObject, CameraAnimation, Interpolation, TransformAnimation},
+    camera::{aperture::Aperture, lens::LensSystem, CameraSettings},
+    light::Light,
+    material::{BumpMap, Dielectric, DiffuseLight, Lambertian, Material, Metal, NormalMap},
+    random,
+    ray::hittable::{Hittable, HittableList, Sphere},
+    spectral::Dispersion,
+    texture::{ImageTexture, SolidColor, Texture, Tiled},
+    vector_additon::VectorAdditions,
+    Float,
+};
+
+pub struct Scene {
+    pub world: HittableList,
+    pub camera: CameraSettings,
+    pub camera_animation: CameraAnimation,
+    // objects with keyframes, kept out of `world` so it can stay static
+    pub animated: Vec<AnimatedObject>,
+    pub lights: Vec<Light>,
+    // problems that did not stop the scene from loading, like unsupported glTF features
+    pub warnings: Vec<String>,
+}
+
+impl Scene {
+    // The big glass sphere is dispersive BK7 when rendering spectrally, in rgb it keeps the
+    // index of 1.5 it always had
+    pub fn random(spectral: bool) -> Self {
+        Self {
+            world: random_scene(spectral),
+            camera: CameraSettings::default(),
+            camera_animation: CameraAnimation::default(),
+            animated: vec![],
+            lights: vec![],
+            warnings: vec![],
+        }
+    }
+
+    // .gltf and .glb files are imported, anything else is parsed as a scene description
+    pub fn load(path: &Path) -> Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        if matches!(extension.to_ascii_lowercase().as_str(), "gltf" | "glb") {
+            return Self::import_gltf(path);
+        }
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("reading scene {}", path.display()))?;
+        Self::parse(&source, path.parent().unwrap_or(Path::new(".")))
+            .with_context(|| format!("parsing scene {}", path.display()))
+    }
+
+    fn import_gltf(path: &Path) -> Result<Self> {
+        let import = gltf::import(path)?;
+        let mut world = HittableList::default();
+        for object in import.objects {
+            world.add(object);
+        }
+        let mut warnings = import.warnings;
+        let bounds = world.bounding_box();
+        let camera = match (import.camera, bounds) {
+            (Some(mut camera), bounds) => {
+                if let Some(bounds) = bounds {
+                    camera.focus_dist = (bounds.centroid() - camera.lookfrom).magnitude();
+                }
+                camera
+            }
+            (None, Some(bounds)) => {
+                warnings.push("no camera, framing the whole scene".to_string());
+                let center = bounds.centroid();
+                let radius = 0.5 * (bounds.max - bounds.min).magnitude();
+                let distance = 3. * radius.max(1e-3);
+                CameraSettings {
+                    lookfrom: center + vec3(0., 0.4, 1.).normalize() * distance,
+                    lookat: center,
+                    vup: vec3(0., 1., 0.),
+                    vfov: Deg(40.),
+                    aperture: 0.,
+                    focus_dist: distance,
+                    ..CameraSettings::default()
+                }
+            }
+            (None, None) => {
+                warnings.push("no camera and no geometry".to_string());
+                CameraSettings::default()
+            }
+        };
+        Ok(Self {
+            world,
+            camera,
+            camera_animation: CameraAnimation::default(),
+            animated: vec![],
+            lights: import.lights,
+            warnings,
+        })
+    }
+}
+
+impl FromStr for Scene {
+    type Err = anyhow::Error;
+
+    // texture paths are relative to the working directory
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s, Path::new("."))
+    }
+}
+
+struct Tokens<'a> {
+    iter: std::str::SplitWhitespace<'a>,
+}
+
+impl<'a> Tokens<'a> {
+    fn word(&mut self) -> Result<&'a str> {
+        self.iter
+            .next()
+            .ok_or_else(|| anyhow!("unexpected end of line"))
+    }
+
+    fn number<T: FromStr>(&mut self) -> Result<T>
+    where
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        let word = self.word()?;
+        word.parse()
+            .with_context(|| format!("expected a number, found {word:?}"))
+    }
+
+    fn vector(&mut self) -> Result<Vector3<Float>> {
+        Ok(vec3(self.number()?, self.number()?, self.number()?))
+    }
+
+    fn point(&mut self) -> Result<Point3<Float>> {
+        Ok(Point3::from_vec(self.vector()?))
+    }
+}
+
+// Line based scene description, see `ray-tracer/scenes/spheres.txt`:
+//
+//   camera [lookfrom x y z] [lookat x y z] [vup x y z] [vfov deg] [aperture a] [focus_dist d]
+//          [blades n] [blade_rotation deg] [aperture_mask <texture>]
+//          [lens <prescription>] [film_diagonal mm] [lens_scale units_per_mm]
+//   material <name> lambertian r g b | metal r g b fuzz | dielectric ir | dispersive bk7|diamond|fused_silica
+//   material <name> normal_map|bump <material> <texture> <strength> [repeat]
+//   material <name> light r g b
+//   sphere x y z radius <material>
+//   random_spheres
+//   light point x y z r g b | spot x y z dx dy dz r g b inner_deg outer_deg | directional dx dy dz r g b
+//   gltf <file>                                        adds the meshes and lights of a glTF file
+//
+// Animation, times are in seconds:
+//
+//   interpolation linear|spline                       used by tracks keyed after this line
+//   camera_key <time> [lookfrom x y z] [lookat x y z] [vfov deg] [aperture a] [focus_dist d] [orbit deg]
+//   object_key <time> [translate x y z] [rotate x y z] [scale s]   animates the last sphere
+impl Scene {
+    // Parses a scene description, resolving texture paths against `base`
+    pub fn parse(s: &str, base: &Path) -> Result<Self> {
+        let mut world = HittableList::default();
+        let mut camera = CameraSettings::default();
+        let mut materials: HashMap<&str, Arc<dyn Material>> = HashMap::new();
+        let mut interpolation = Interpolation::Linear;
+        let mut camera_animation = CameraAnimation::default();
+        let mut animated: Vec<AnimatedObject> = vec![];
+        let mut lights = vec![];
+        let mut warnings = vec![];
+        // whether the last sphere has been moved into `animated`
+        let mut last_animated = false;
+
+        for (ln, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut tokens = Tokens {
+                iter: line.split_whitespace(),
+            };
+            let Some(command) = tokens.iter.next() else {
+                continue;
+            };
+            (|| -> Result<()> {
+                match command {
+                    "camera" => {
+                        let (mut film_diagonal, mut lens_scale) = (None, None);
+                        while let Some(key) = tokens.iter.next() {
+                            match key {
+                                "lookfrom" => camera.lookfrom = tokens.point()?,
+                                "lookat" => camera.lookat = tokens.point()?,
+                                "vup" => camera.vup = tokens.vector()?,
+                                "vfov" => camera.vfov = Deg(tokens.number()?),
+                                "aperture" => camera.aperture = tokens.number()?,
+                                "focus_dist" => camera.focus_dist = tokens.number()?,
+                                "blades" => {
+                                    let rotation = match camera.aperture_shape {
+                                        Aperture::Polygon { rotation, .. } => rotation,
+                                        _ => Deg(0.),
+                                    };
+                                    camera.aperture_shape = Aperture::Polygon {
+                                        blades: tokens.number()?,
+                                        rotation,
+                                    };
+                                }
+                                "blade_rotation" => match &mut camera.aperture_shape {
+                                    Aperture::Polygon { rotation, .. } => {
+                                        *rotation = Deg(tokens.number()?)
+                                    }
+                                    _ => bail!("blade_rotation must follow blades"),
+                                },
+                                "aperture_mask" => {
+                                    let texture = ImageTexture::load(&base.join(tokens.word()?))?;
+                                    camera.aperture_shape = Aperture::Mask(texture);
+                                }
+                                "lens" => {
+                                    let lens = LensSystem::load(&base.join(tokens.word()?))?;
+                                    camera.lens = Some(Arc::new(lens));
+                                }
+                                "film_diagonal" => film_diagonal = Some(tokens.number()?),
+                                "lens_scale" => lens_scale = Some(tokens.number()?),
+                                _ => bail!("unknown camera parameter {key:?}"),
+                            }
+                        }
+                        if let Some(lens) = &mut camera.lens {
+                            let lens = Arc::make_mut(lens);
+                            lens.film_diagonal = film_diagonal.unwrap_or(lens.film_diagonal);
+                            lens.scale = lens_scale.unwrap_or(lens.scale);
+                        } else if film_diagonal.is_some() || lens_scale.is_some() {
+                            bail!("film_diagonal and lens_scale need a lens");
+                        }
+                    }
+                    "material" => {
+                        let name = tokens.word()?;
+                        let material: Arc<dyn Material> = match tokens.word()? {
+                            "lambertian" => Lambertian::new(tokens.vector()?),
+                            "metal" => Metal::new(tokens.vector()?, tokens.number()?),
+                            "dielectric" => Dielectric::new(tokens.number()?),
+                            "dispersive" => Dielectric::dispersive(match tokens.word()? {
+                                "bk7" => Dispersion::BK7,
+                                "diamond" => Dispersion::DIAMOND,
+                                "fused_silica" => Dispersion::FUSED_SILICA,
+                                glass => bail!("unknown glass {glass:?}"),
+                            }),
+                            kind @ ("normal_map" | "bump") => {
+                                let inner = tokens.word()?;
+                                let inner = materials
+                                    .get(inner)
+                                    .ok_or_else(|| anyhow!("undefined material {inner:?}"))?
+                                    .clone();
+                                let texture = ImageTexture::load(&base.join(tokens.word()?))?;
+                                let strength = tokens.number()?;
+                                let texture: Arc<dyn Texture> = match tokens.iter.next() {
+                                    Some(repeat) => Tiled::new(texture, repeat.parse()?),
+                                    None => texture,
+                                };
+                                if kind == "bump" {
+                                    BumpMap::new(inner, texture, strength)
+                                } else {
+                                    NormalMap::new(inner, texture, strength)
+                                }
+                            }
+                            "light" => DiffuseLight::new(SolidColor::new(tokens.vector()?)),
+                            kind => bail!("unknown material type {kind:?}"),
+                        };
+                        materials.insert(name, material);
+                    }
+                    "sphere" => {
+                        let center = tokens.point()?;
+                        let radius = tokens.number()?;
+                        let name = tokens.word()?;
+                        let material = materials
+                            .get(name)
+                            .ok_or_else(|| anyhow!("undefined material {name:?}"))?;
+                        world.add(Arc::new(Sphere::new(center, radius, material.clone())));
+                        last_animated = false;
+                    }
+                    "random_spheres" => {
+                        random_spheres(&mut world);
+                        last_animated = true;
+                    }
+                    "light" => lights.push(match tokens.word()? {
+                        "point" => Light::Point {
+                            position: tokens.point()?,
+                            intensity: tokens.vector()?,
+                        },
+                        "spot" => Light::Spot {
+                            position: tokens.point()?,
+                            direction: tokens.vector()?.normalize(),
+                            intensity: tokens.vector()?,
+                            cos_inner: tokens.number::<Float>()?.to_radians().cos(),
+                            cos_outer: tokens.number::<Float>()?.to_radians().cos(),
+                        },
+                        "directional" => Light::Directional {
+                            direction: tokens.vector()?.normalize(),
+                            irradiance: tokens.vector()?,
+                        },
+                        kind => bail!("unknown light type {kind:?}"),
+                    }),
+                    "gltf" => {
+                        let import = gltf::import(&base.join(tokens.word()?))?;
+                        for object in import.objects {
+                            world.add(object);
+                        }
+                        lights.extend(import.lights);
+                        warnings.extend(import.warnings);
+                        last_animated = true;
+                    }
+                    "interpolation" => interpolation = tokens.word()?.parse()?,
+                    "camera_key" => {
+                        let time = tokens.number()?;
+                        let anim = &mut camera_animation;
+                        while let Some(key) = tokens.iter.next() {
+                            match key {
+                                "lookfrom" => anim.lookfrom.insert(time, tokens.point()?),
+                                "lookat" => anim.lookat.insert(time, tokens.point()?),
+                                "vfov" => anim.vfov.insert(time, Deg(tokens.number()?)),
+                                "aperture" => anim.aperture.insert(time, tokens.number()?),
+                                "focus_dist" => anim.focus_dist.insert(time, tokens.number()?),
+                                "orbit" => anim.orbit.insert(time, Deg(tokens.number()?)),
+                                _ => bail!("unknown camera parameter {key:?}"),
+                            }
+                        }
+                        anim.set_interpolation(interpolation);
+                    }
+                    "object_key" => {
+                        let time = tokens.number()?;
+                        if !last_animated {
+                            let object = world
+                                .pop()
+                                .ok_or_else(|| anyhow!("object_key before any sphere"))?;
+                            animated.push(AnimatedObject {
+                                object,
+                                transform: TransformAnimation::default(),
+                            });
+                            last_animated = true;
+                        }
+                        let anim = match animated.last_mut() {
+                            Some(object) => &mut object.transform,
+                            None => bail!("object_key must follow a sphere"),
+                        };
+                        while let Some(key) = tokens.iter.next() {
+                            match key {
+                                "translate" => anim.translation.insert(time, tokens.vector()?),
+                                "rotate" => anim.rotation.insert(time, tokens.vector()?),
+                                "scale" => anim.scale.insert(time, tokens.number()?),
+                                _ => bail!("unknown transform {key:?}"),
+                            }
+                        }
+                        anim.set_interpolation(interpolation);
+                    }
+                    _ => bail!("unknown command {command:?}"),
+                }
+                if let Some(extra) = tokens.iter.next() {
+                    bail!("unexpected {extra:?}");
+                }
+                Ok(())
+            })()
+            .with_context(|| format!("line {}", ln + 1))?;
+        }
+
+        Ok(Self {
+            world,
+            camera,
+            camera_animation,
+            animated,
+            lights,
+            warnings,
+        })
+    }
+}
+
+// The small spheres scattered over the ground in `random_scene`
+pub fn random_spheres(world: &mut HittableList) {
+    let glass = Dielectric::new(1.5);
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = random(0. ..1.);
+            let center = Point3::new(
+                a as Float + 0.9 * random(0. ..1.),
+                0.2,
+                b as Float + 0.9 * random(0. ..1.),
+            );
+            if (center.to_vec() - vec3(4., 0.2, 0.)).magnitude() > 0.9 {
+                if choose_mat < 0.8 {
+                    // diffuse
+                    let albedo: Vector3<Float> =
+                        Vector3::random(0. ..1.).mul_element_wise(Vector3::random(0. ..1.));
+                    world.add(Arc::new(Sphere::new(center, 0.2, Lambertian::new(albedo))));
+                } else if choose_mat < 0.95 {
+                    // metal
+                    let albedo = Vector3::random(0. ..0.5);
+                    let fuzz = random(0. ..0.5);
+                    world.add(Arc::new(Sphere::new(center, 0.2, Metal::new(albedo, fuzz))));
+                } else {
+                    // glass
+                    world.add(Arc::new(Sphere::new(center, 0.2, glass.clone())));
+                }
+            }
+        }
+    }
+}
+
+pub fn random_scene(spectral: bool) -> HittableList {
+    let mut world = HittableList::default();
+    let ground_material = Lambertian::new(vec3(0.5, 0.5, 0.5));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        ground_material,
+    )));
+
+    random_spheres(&mut world);
+
+    let material1 = if spectral {
+        Dielectric::dispersive(Dispersion::BK7)
+    } else {
+        Dielectric::new(1.5)
+    };
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0., 1., 0.),
+        1.,
+        material1,
+    )));
+    let material2 = Lambertian::new(vec3(0.4, 0.2, 0.1));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(-4., 1., 0.),
+        1.,
+        material2,
+    )));
+    let material3 = Metal::new(vec3(0.7, 0.6, 0.5), 0.);
+    world.add(Arc::new(Sphere::new(
+        Point3::new(4., 1., 0.),
+        1.,
+        material3,
+    )));
+    world
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ray::Ray;
+
+    fn parse(s: &str) -> Result<Scene> {
+        Scene::parse(s, Path::new("scenes"))
+    }
+
+    // the error of a scene that fails to parse, with its context
+    fn parse_error(s: &str) -> String {
+        match parse(s) {
+            Ok(_) => panic!("{s:?} parsed"),
+            Err(e) => format!("{e:#}"),
+        }
+    }
+
+    fn hit_t(world: &dyn Hittable, from: Point3<Float>, dir: Vector3<Float>) -> Option<Float> {
+        world
+            .hit(&Ray::new(from, dir), 0.001, Float::INFINITY)
+            .map(|rec| rec.t)
+    }
+
+    fn close(a: Float, b: Float) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn test_camera() {
+        let scene = parse(
+            "camera lookfrom 1 2 3 lookat 4 5 6 vup 0 0 1 vfov 30 aperture 0.5 focus_dist 7\n\
+             camera blades 6 blade_rotation 15",
+        )
+        .unwrap();
+        let camera = scene.camera;
+        assert_eq!(camera.lookfrom, Point3::new(1., 2., 3.));
+        assert_eq!(camera.lookat, Point3::new(4., 5., 6.));
+        assert_eq!(camera.vup, vec3(0., 0., 1.));
+        assert_eq!(camera.vfov, Deg(30.));
+        assert_eq!(camera.aperture, 0.5);
+        assert_eq!(camera.focus_dist, 7.);
+        assert!(matches!(
+            camera.aperture_shape,
+            Aperture::Polygon { blades: 6, rotation } if rotation == Deg(15.)
+        ));
+        assert!(camera.lens.is_none());
+
+        assert_eq!(
+            parse_error("camera blade_rotation 15"),
+            "line 1: blade_rotation must follow blades"
+        );
+        assert_eq!(
+            parse_error("camera film_diagonal 35"),
+            "line 1: film_diagonal and lens_scale need a lens"
+        );
+        assert_eq!(
+            parse_error("camera zoom 2"),
+            "line 1: unknown camera parameter \"zoom\""
+        );
+    }
+
+    #[test]
+    fn test_materials_and_spheres() {
+        let scene = parse(
+            "material red lambertian 1 0 0\n\
+             material steel metal 0.7 0.6 0.5 0.1\n\
+             material glass dielectric 1.5\n\
+             material prism dispersive bk7\n\
+             material lamp light 4 4 4\n\
+             sphere 0 0 0 1 red\n\
+             sphere 0 0 5 1 steel\n\
+             sphere 0 0 10 1 glass\n\
+             sphere 0 0 15 1 prism\n\
+             sphere 0 0 20 1 lamp",
+        )
+        .unwrap();
+        let from = Point3::new(0., 0., -10.);
+        assert!(close(
+            hit_t(&scene.world, from, vec3(0., 0., 1.)).unwrap(),
+            9.
+        ));
+        assert!(hit_t(&scene.world, Point3::new(0., 0., 20.), vec3(0., 1., 0.)).is_some());
+        assert_eq!(scene.world.into_objects().len(), 5);
+
+        assert_eq!(
+            parse_error("material a lambertian 1 0 0\nsphere 0 0 0 1 b"),
+            "line 2: undefined material \"b\""
+        );
+        assert_eq!(
+            parse_error("material a plastic 1 0 0"),
+            "line 1: unknown material type \"plastic\""
+        );
+        assert_eq!(
+            parse_error("material a dispersive flint"),
+            "line 1: unknown glass \"flint\""
+        );
+        assert!(
+            parse_error("material a lambertian 1 0 0\nmaterial b bump a missing.png 1")
+                .starts_with("line 2: loading texture")
+        );
+    }
+
+    #[test]
+    fn test_random_spheres() {
+        let scene = parse("random_spheres").unwrap();
+        assert!(scene.world.into_objects().len() > 100);
+    }
+
+    #[test]
+    fn test_lights() {
+        let scene = parse(
+            "light point 1 2 3 4 5 6\n\
+             light spot 0 5 0 0 -2 0 1 1 1 30 60\n\
+             light directional 0 0 -3 2 2 2",
+        )
+        .unwrap();
+        assert_eq!(
+            scene.lights[0],
+            Light::Point {
+                position: Point3::new(1., 2., 3.),
+                intensity: vec3(4., 5., 6.),
+            }
+        );
+        match scene.lights[1] {
+            Light::Spot {
+                position,
+                direction,
+                intensity,
+                cos_inner,
+                cos_outer,
+            } => {
+                assert_eq!(position, Point3::new(0., 5., 0.));
+                assert_eq!(direction, vec3(0., -1., 0.));
+                assert_eq!(intensity, vec3(1., 1., 1.));
+                assert!(close(cos_inner, (3. as Float).sqrt() / 2.));
+                assert!(close(cos_outer, 0.5));
+            }
+            light => panic!("expected a spot light, got {light:?}"),
+        }
+        assert_eq!(
+            scene.lights[2],
+            Light::Directional {
+                direction: vec3(0., 0., -1.),
+                irradiance: vec3(2., 2., 2.),
+            }
+        );
+        assert_eq!(
+            parse_error("light area 0 0 0"),
+            "line 1: unknown light type \"area\""
+        );
+    }
+
+    #[test]
+    fn test_gltf() {
+        let scene = parse("gltf cubes.gltf").unwrap();
+        assert_eq!(scene.lights.len(), 2);
+        assert!(!scene.world.is_empty());
+    }
+
+    #[test]
+    fn test_animation() {
+        let scene = parse(
+            "camera_key 0 lookfrom 0 0 0 vfov 20\n\
+             camera_key 2 lookfrom 2 0 0 vfov 40\n\
+             material red lambertian 1 0 0\n\
+             sphere 0 0 0 1 red\n\
+             sphere 0 0 0 2 red\n\
+             interpolation spline\n\
+             object_key 0 translate 0 0 0 scale 1\n\
+             object_key 1 translate 0 4 0 scale 2",
+        )
+        .unwrap();
+        let camera = &scene.camera_animation;
+        assert_eq!(camera.lookfrom.sample(1.), Some(Point3::new(1., 0., 0.)));
+        assert_eq!(camera.vfov.sample(1.), Some(Deg(30.)));
+        assert_eq!(camera.lookfrom.interpolation, Interpolation::Linear);
+
+        // the last sphere is moved out of the static world
+        assert_eq!(scene.animated.len(), 1);
+        let transform = &scene.animated[0].transform;
+        assert_eq!(transform.translation.interpolation, Interpolation::Spline);
+        assert_eq!(transform.translation.sample(1.), Some(vec3(0., 4., 0.)));
+        assert_eq!(transform.scale.sample(0.5), Some(1.5));
+        assert_eq!(scene.world.into_objects().len(), 1);
+
+        assert_eq!(
+            parse_error("interpolation cubic"),
+            "line 1: unknown interpolation \"cubic\", expected linear or spline"
+        );
+        assert_eq!(
+            parse_error("object_key 0 translate 1 0 0"),
+            "line 1: object_key before any sphere"
+        );
+        assert_eq!(
+            parse_error("camera_key 0 roll 3"),
+            "line 1: unknown camera parameter \"roll\""
+        );
+    }
+
+    #[test]
+    fn test_error_lines() {
+        // comments and blank lines still count
+        assert_eq!(
+            parse_error("# a scene\n\ncamera vfov 20 # wide\nteapot"),
+            "line 4: unknown command \"teapot\""
+        );
+        assert_eq!(
+            parse_error("camera vfov 20\ncamera vfov wide"),
+            "line 2: expected a number, found \"wide\": invalid float literal"
+        );
+        assert_eq!(
+            parse_error("light point 1 2 3 4 5"),
+            "line 1: unexpected end of line"
+        );
+        assert_eq!(
+            parse_error("random_spheres\nrandom_spheres please"),
+            "line 2: unexpected \"please\""
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use std::sync::OnceLock;
+
+use cgmath::{vec3, Matrix3, Vector3};
+
+use crate::{random, ray::Ray, Color, Float};
+
+// visible range sampled in spectral mode, in nanometers
+pub const LAMBDA_MIN: Float = 380.;
+pub const LAMBDA_MAX: Float = 780.;
+
+// Wavelength dependent index of refraction. Coefficients use wavelengths in micrometers,
+// which is how they are usually published.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dispersion {
+    // n = a + b / λ²
+    Cauchy { a: Float, b: Float },
+    // n² = 1 + Σ bᵢλ² / (λ² - cᵢ)
+    Sellmeier { b: [Float; 3], c: [Float; 3] },
+}
+
+// published coefficients are kept as is, even when `Float` cannot hold all their digits
+#[allow(clippy::excessive_precision)]
+impl Dispersion {
+    pub const BK7: Self = Self::Sellmeier {
+        b: [1.03961212, 0.231792344, 1.01046945],
+        c: [0.00600069867, 0.0200179144, 103.560653],
+    };
+    pub const DIAMOND: Self = Self::Sellmeier {
+        b: [0.3306, 4.3356, 0.],
+        c: [0.030625, 0.011236, 0.],
+    };
+    pub const FUSED_SILICA: Self = Self::Cauchy {
+        a: 1.4580,
+        b: 0.00354,
+    };
+
+    // sodium d-line, used as the single index when rendering in rgb
+    pub const D_LINE: Float = 587.6;
+
+    pub fn ior(&self, wavelength: Float) -> Float {
+        let l2 = (wavelength / 1000.).powi(2);
+        match self {
+            Self::Cauchy { a, b } => a + b / l2,
+            Self::Sellmeier { b, c } => (1.
+                + b.iter()
+                    .zip(c.iter())
+                    .map(|(b, c)| b * l2 / (l2 - c))
+                    .sum::<Float>())
+            .sqrt(),
+        }
+    }
+}
+
+// Multi-lobe gaussian fit of the CIE 1931 2° observer (Wyman, Sloan & Shirley 2013)
+pub fn cie_xyz(wavelength: Float) -> Vector3<Float> {
+    let g = |mu: Float, s1: Float, s2: Float| {
+        let t = (wavelength - mu) / if wavelength < mu { s1 } else { s2 };
+        (-0.5 * t * t).exp()
+    };
+    vec3(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+#[allow(clippy::excessive_precision)]
+pub fn xyz_to_rgb(xyz: Vector3<Float>) -> Color {
+    // linear sRGB (D65), cgmath matrices are column major
+    let m = Matrix3::new(
+        3.2404542, -0.9692660, 0.0556434, //
+        -1.5371385, 1.8760108, -0.2040259, //
+        -0.4985314, 0.0415560, 1.0572252,
+    );
+    m * xyz
+}
+
+// Smooth partition of unity over the visible range, so white maps to a flat spectrum
+pub fn rgb_to_spectrum(rgb: Color, wavelength: Float) -> Float {
+    let smoothstep = |e0: Float, e1: Float| {
+        let t = ((wavelength - e0) / (e1 - e0)).clamp(0., 1.);
+        t * t * (3. - 2. * t)
+    };
+    let b = 1. - smoothstep(480., 510.);
+    let r = smoothstep(570., 600.);
+    let g = 1. - b - r;
+    r * rgb.x + g * rgb.y + b * rgb.z
+}
+
+// Scales a single wavelength sample so a flat unit spectrum averages out to (1, 1, 1)
+fn white_balance() -> Vector3<Float> {
+    static WHITE: OnceLock<Vector3<Float>> = OnceLock::new();
+    *WHITE.get_or_init(|| {
+        let steps = (LAMBDA_MAX - LAMBDA_MIN) as u32;
+        let xyz: Vector3<Float> = (0..steps)
+            .map(|i| cie_xyz(LAMBDA_MIN + i as Float + 0.5))
+            .sum::<Vector3<Float>>()
+            / steps as Float;
+        let white = xyz_to_rgb(xyz);
+        vec3(1. / white.x, 1. / white.y, 1. / white.z)
+    })
+}
+
+pub fn spectrum_to_rgb(radiance: Float, wavelength: Float) -> Color {
+    let rgb = xyz_to_rgb(radiance * cie_xyz(wavelength));
+    let wb = white_balance();
+    vec3(rgb.x * wb.x, rgb.y * wb.y, rgb.z * wb.z)
+}
+
+pub fn sample_wavelength() -> Float {
+    random(LAMBDA_MIN..LAMBDA_MAX)
+}
+
+// Traces `r` at a single random wavelength with `radiance` and returns its rgb contribution
+pub fn trace(r: Ray, radiance: impl FnOnce(Ray) -> Color) -> Color {
+    let wavelength = sample_wavelength();
+    let radiance = radiance(r.with_wavelength(wavelength));
+    spectrum_to_rgb(radiance.x, wavelength)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ior() {
+        // published indices at the sodium d-line
+        assert!((Dispersion::BK7.ior(Dispersion::D_LINE) - 1.5168).abs() < 1e-4);
+        assert!((Dispersion::DIAMOND.ior(Dispersion::D_LINE) - 2.4175).abs() < 2e-3);
+        let silica = Dispersion::FUSED_SILICA.ior(Dispersion::D_LINE);
+        assert!((silica - 1.4580 - 0.00354 / 0.5876 / 0.5876).abs() < 1e-6);
+        // normal dispersion: blue bends more than red
+        for glass in [
+            Dispersion::BK7,
+            Dispersion::DIAMOND,
+            Dispersion::FUSED_SILICA,
+        ] {
+            assert!(glass.ior(450.) > glass.ior(650.));
+        }
+    }
+
+    #[test]
+    fn test_white_point() {
+        // a flat unit spectrum sampled uniformly over the visible range averages to white
+        let steps = 4000;
+        let white = (0..steps)
+            .map(|i| {
+                let wavelength =
+                    LAMBDA_MIN + (LAMBDA_MAX - LAMBDA_MIN) * (i as Float + 0.5) / steps as Float;
+                spectrum_to_rgb(rgb_to_spectrum(vec3(1., 1., 1.), wavelength), wavelength)
+            })
+            .sum::<Color>()
+            / steps as Float;
+        for c in [white.x, white.y, white.z] {
+            assert!((c - 1.).abs() < 1e-2, "{white:?}");
+        }
+    }
+}
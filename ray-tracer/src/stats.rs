@@ -0,0 +1,326 @@
+use std::{
+    cell::Cell,
+    fmt::Display,
+    ops::{Add, AddAssign},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Counter {
+    CameraRays,
+    BounceRays,
+    ShadowRays,
+    IntersectionTests,
+    BvhNodeVisits,
+}
+
+const COUNTERS: usize = 5;
+
+thread_local! {
+    static LOCAL: [Cell<u64>; COUNTERS] = const { [const { Cell::new(0) }; COUNTERS] };
+}
+
+// Counters are per thread, `take` collects and resets the ones of the current thread
+#[inline]
+pub fn count(counter: Counter) {
+    LOCAL.with(|local| {
+        let c = &local[counter as usize];
+        c.set(c.get() + 1);
+    });
+}
+
+pub fn take() -> Counters {
+    LOCAL.with(|local| {
+        let get = |counter: Counter| local[counter as usize].take();
+        Counters {
+            camera_rays: get(Counter::CameraRays),
+            bounce_rays: get(Counter::BounceRays),
+            shadow_rays: get(Counter::ShadowRays),
+            intersection_tests: get(Counter::IntersectionTests),
+            bvh_node_visits: get(Counter::BvhNodeVisits),
+        }
+    })
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Counters {
+    pub camera_rays: u64,
+    pub bounce_rays: u64,
+    pub shadow_rays: u64,
+    pub intersection_tests: u64,
+    pub bvh_node_visits: u64,
+}
+
+impl Add for Counters {
+    type Output = Counters;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Counters {
+            camera_rays: self.camera_rays + rhs.camera_rays,
+            bounce_rays: self.bounce_rays + rhs.bounce_rays,
+            shadow_rays: self.shadow_rays + rhs.shadow_rays,
+            intersection_tests: self.intersection_tests + rhs.intersection_tests,
+            bvh_node_visits: self.bvh_node_visits + rhs.bvh_node_visits,
+        }
+    }
+}
+
+impl AddAssign for Counters {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TileStats {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub seconds: f64,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct RenderStats {
+    pub counters: Counters,
+    pub samples: u64,
+    pub seconds: f64,
+    pub samples_per_second: f64,
+    // bounces per camera ray, counting the camera ray itself
+    pub average_path_length: f64,
+    pub tiles: Vec<TileStats>,
+}
+
+impl RenderStats {
+    pub fn new(counters: Counters, samples: u64, time: Duration, tiles: Vec<TileStats>) -> Self {
+        let mut stats = Self {
+            counters,
+            samples,
+            seconds: time.as_secs_f64(),
+            tiles,
+            ..Default::default()
+        };
+        stats.update_rates();
+        stats
+    }
+
+    fn update_rates(&mut self) {
+        self.samples_per_second = if self.seconds > 0. {
+            self.samples as f64 / self.seconds
+        } else {
+            0.
+        };
+        self.average_path_length = if self.counters.camera_rays > 0 {
+            (self.counters.camera_rays + self.counters.bounce_rays) as f64
+                / self.counters.camera_rays as f64
+        } else {
+            0.
+        };
+    }
+
+    // Accumulates another render, e.g. the next frame of a sequence
+    pub fn merge(&mut self, other: &RenderStats) {
+        self.counters += other.counters;
+        self.samples += other.samples;
+        self.seconds += other.seconds;
+        self.tiles.extend_from_slice(&other.tiles);
+        self.update_rates();
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+impl Display for RenderStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let c = &self.counters;
+        let per_sample = |n: u64| n as f64 / self.samples.max(1) as f64;
+        writeln!(f, "{:<22}{:>16}{:>14}", "", "total", "per sample")?;
+        for (name, n) in [
+            ("camera rays", c.camera_rays),
+            ("bounce rays", c.bounce_rays),
+            ("shadow rays", c.shadow_rays),
+            ("intersection tests", c.intersection_tests),
+            ("bvh node visits", c.bvh_node_visits),
+        ] {
+            writeln!(f, "{:<22}{:>16}{:>14.2}", name, n, per_sample(n))?;
+        }
+        writeln!(
+            f,
+            "{:<22}{:>16.3}",
+            "average path length", self.average_path_length
+        )?;
+        writeln!(f, "{:<22}{:>16}", "samples", self.samples)?;
+        writeln!(f, "{:<22}{:>15.3}s", "render time", self.seconds)?;
+        writeln!(
+            f,
+            "{:<22}{:>16.0}",
+            "samples / second", self.samples_per_second
+        )?;
+        if let (Some(fastest), Some(slowest)) = (
+            self.tiles
+                .iter()
+                .min_by(|a, b| a.seconds.total_cmp(&b.seconds)),
+            self.tiles
+                .iter()
+                .max_by(|a, b| a.seconds.total_cmp(&b.seconds)),
+        ) {
+            let mean = self.tiles.iter().map(|t| t.seconds).sum::<f64>() / self.tiles.len() as f64;
+            writeln!(f, "{:<22}{:>16}", "tiles", self.tiles.len())?;
+            writeln!(f, "{:<22}{:>14.2}ms", "tile time mean", mean * 1000.)?;
+            writeln!(
+                f,
+                "{:<22}{:>14.2}ms  at ({}, {})",
+                "tile time min",
+                fastest.seconds * 1000.,
+                fastest.x,
+                fastest.y
+            )?;
+            writeln!(
+                f,
+                "{:<22}{:>14.2}ms  at ({}, {})",
+                "tile time max",
+                slowest.seconds * 1000.,
+                slowest.x,
+                slowest.y
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tile(x: u32, seconds: f64) -> TileStats {
+        TileStats {
+            x,
+            y: 0,
+            width: 16,
+            height: 16,
+            seconds,
+        }
+    }
+
+    #[test]
+    fn test_take_resets() {
+        take();
+        count(Counter::CameraRays);
+        count(Counter::CameraRays);
+        count(Counter::ShadowRays);
+        count(Counter::BvhNodeVisits);
+        assert_eq!(
+            take(),
+            Counters {
+                camera_rays: 2,
+                shadow_rays: 1,
+                bvh_node_visits: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(take(), Counters::default());
+        // other threads count on their own
+        std::thread::spawn(|| count(Counter::BounceRays))
+            .join()
+            .unwrap();
+        assert_eq!(take(), Counters::default());
+    }
+
+    #[test]
+    fn test_merge() {
+        let counters = Counters {
+            camera_rays: 100,
+            bounce_rays: 150,
+            shadow_rays: 10,
+            intersection_tests: 1000,
+            bvh_node_visits: 500,
+        };
+        let mut stats = RenderStats::new(counters, 100, Duration::from_secs(2), vec![tile(0, 1.)]);
+        assert_eq!(stats.samples_per_second, 50.);
+        assert_eq!(stats.average_path_length, 2.5);
+        let next = RenderStats::new(
+            Counters {
+                camera_rays: 100,
+                bounce_rays: 50,
+                ..Default::default()
+            },
+            300,
+            Duration::from_secs(2),
+            vec![tile(16, 0.5), tile(32, 1.5)],
+        );
+        stats.merge(&next);
+        assert_eq!(
+            stats.counters,
+            Counters {
+                camera_rays: 200,
+                bounce_rays: 200,
+                ..counters
+            }
+        );
+        assert_eq!(stats.samples, 400);
+        assert_eq!(stats.seconds, 4.);
+        assert_eq!(stats.samples_per_second, 100.);
+        assert_eq!(stats.average_path_length, 2.);
+        let xs: Vec<u32> = stats.tiles.iter().map(|t| t.x).collect();
+        assert_eq!(xs, [0, 16, 32]);
+    }
+
+    #[test]
+    fn test_empty_rates() {
+        let stats = RenderStats::new(Counters::default(), 0, Duration::ZERO, vec![]);
+        assert_eq!(stats.samples_per_second, 0.);
+        assert_eq!(stats.average_path_length, 0.);
+    }
+
+    #[test]
+    fn test_json() {
+        let counters = Counters {
+            camera_rays: 4,
+            bounce_rays: 2,
+            shadow_rays: 1,
+            intersection_tests: 30,
+            bvh_node_visits: 20,
+        };
+        let stats = RenderStats::new(counters, 4, Duration::from_secs(1), vec![tile(16, 0.25)]);
+        let json: serde_json::Value = serde_json::from_str(&stats.to_json().unwrap()).unwrap();
+        let keys = |value: &serde_json::Value| {
+            let mut keys: Vec<String> = value.as_object().unwrap().keys().cloned().collect();
+            keys.sort();
+            keys
+        };
+        assert_eq!(
+            keys(&json),
+            [
+                "average_path_length",
+                "counters",
+                "samples",
+                "samples_per_second",
+                "seconds",
+                "tiles"
+            ]
+        );
+        assert_eq!(
+            keys(&json["counters"]),
+            [
+                "bounce_rays",
+                "bvh_node_visits",
+                "camera_rays",
+                "intersection_tests",
+                "shadow_rays"
+            ]
+        );
+        let back: Counters = serde_json::from_value(json["counters"].clone()).unwrap();
+        assert_eq!(back, counters);
+        assert_eq!(json["samples"], 4);
+        assert_eq!(json["average_path_length"], 1.5);
+        let tiles: Vec<TileStats> = serde_json::from_value(json["tiles"].clone()).unwrap();
+        assert_eq!(
+            (tiles[0].x, tiles[0].width, tiles[0].seconds),
+            (16, 16, 0.25)
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use std::{path::Path, sync::Arc};
+
+use ::image::DynamicImage;
+use anyhow::{Context, Result};
+use cgmath::{vec3, Point3};
+
+use crate::{Color, Float};
+
+fn open(path: &Path) -> Result<DynamicImage> {
+    ::image::open(path).with_context(|| format!("loading texture {}", path.display()))
+}
+
+fn srgb_to_linear(c: Float) -> Float {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub trait Texture: Send + Sync {
+    fn value(&self, u: Float, v: Float, p: Point3<Float>) -> Color;
+
+    // uv distance between neighbouring samples, used to take finite differences
+    fn texel_size(&self) -> (Float, Float) {
+        (1e-3, 1e-3)
+    }
+}
+
+pub struct SolidColor {
+    color: Color,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> Arc<Self> {
+        Arc::new(Self { color })
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: Float, _v: Float, _p: Point3<Float>) -> Color {
+        self.color
+    }
+}
+
+// Bilinearly filtered image, repeating outside of [0, 1]. v = 0 is the bottom row.
+pub struct ImageTexture {
+    width: u32,
+    height: u32,
+    data: Vec<Color>,
+}
+
+impl ImageTexture {
+    // values are used as stored, which is what normal and height maps expect
+    pub fn load(path: &Path) -> Result<Arc<Self>> {
+        Ok(Self::from_image(open(path)?, |c| c))
+    }
+
+    // converts from sRGB to linear, for color textures
+    pub fn load_srgb(path: &Path) -> Result<Arc<Self>> {
+        Ok(Self::from_image(open(path)?, srgb_to_linear))
+    }
+
+    // like `load`, from an encoded png or jpeg in memory
+    pub fn decode(bytes: &[u8]) -> Result<Arc<Self>> {
+        Ok(Self::from_image(::image::load_from_memory(bytes)?, |c| c))
+    }
+
+    pub fn decode_srgb(bytes: &[u8]) -> Result<Arc<Self>> {
+        Ok(Self::from_image(
+            ::image::load_from_memory(bytes)?,
+            srgb_to_linear,
+        ))
+    }
+
+    fn from_image(img: DynamicImage, convert: impl Fn(Float) -> Float) -> Arc<Self> {
+        let img = img.into_rgb8();
+        let data = img
+            .pixels()
+            .map(|p| {
+                vec3(
+                    convert(p[0] as Float / 255.),
+                    convert(p[1] as Float / 255.),
+                    convert(p[2] as Float / 255.),
+                )
+            })
+            .collect();
+        Arc::new(Self {
+            width: img.width(),
+            height: img.height(),
+            data,
+        })
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Color {
+        let x = x.rem_euclid(self.width as i64) as usize;
+        let y = y.rem_euclid(self.height as i64) as usize;
+        self.data[y * self.width as usize + x]
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: Float, v: Float, _p: Point3<Float>) -> Color {
+        let x = u * self.width as Float - 0.5;
+        let y = (1. - v) * self.height as Float - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let top = self.texel(x0, y0) * (1. - fx) + self.texel(x0 + 1, y0) * fx;
+        let bottom = self.texel(x0, y0 + 1) * (1. - fx) + self.texel(x0 + 1, y0 + 1) * fx;
+        top * (1. - fy) + bottom * fy
+    }
+
+    fn texel_size(&self) -> (Float, Float) {
+        (1. / self.width as Float, 1. / self.height as Float)
+    }
+}
+
+// Repeats another texture `scale` times over the uv range
+pub struct Tiled {
+    inner: Arc<dyn Texture>,
+    scale: Float,
+}
+
+impl Tiled {
+    pub fn new(inner: Arc<dyn Texture>, scale: Float) -> Arc<Self> {
+        Arc::new(Self { inner, scale })
+    }
+}
+
+impl Texture for Tiled {
+    fn value(&self, u: Float, v: Float, p: Point3<Float>) -> Color {
+        self.inner.value(u * self.scale, v * self.scale, p)
+    }
+
+    fn texel_size(&self) -> (Float, Float) {
+        let (du, dv) = self.inner.texel_size();
+        (du / self.scale, dv / self.scale)
+    }
+}
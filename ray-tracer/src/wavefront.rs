@@ -0,0 +1,300 @@
+use std::collections::VecDeque;
+
+use cgmath::{vec3, ElementWise};
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{
+    camera::Camera,
+    light::{shadow_rays, Light, ShadowRay},
+    material::Material,
+    random,
+    ray::{
+        hittable::{HitRecord, Hittable},
+        packet::{PacketHits, RayPacket, LANES},
+        sky, Ray,
+    },
+    render::{camera_ray, pixel_seed, RenderSettings, Tile},
+    spectral,
+    stats::{self, Counter},
+    swap_random, Color, Float,
+};
+
+// One bounce of a path: the light leaving the surface it hit by emission and direct
+// lighting, and how much of the light arriving along the scattered ray it passes on
+struct Bounce {
+    radiance: Color,
+    attenuation: Option<Color>,
+}
+
+// Tiles traced together by `render_tiles`, so every stage runs over enough paths
+pub const BATCH_TILES: usize = 8;
+
+// The paths of a batch of tiles, one slot per pixel, kept in flat arrays and advanced a
+// bounce at a time. A slot traces the samples of its pixel one after the other with the
+// pixel's own generator, so each sample draws the same random numbers it would in a
+// recursive `Ray::color`, and the bounces are summed in the same order once the path ends.
+// With packets the camera rays of a slot are drawn `LANES` at a time like
+// `sample_pixel_packets` does, and queued until the slot gets to them.
+struct Paths<'a> {
+    // pixel of each slot, `y` counting down from the top row
+    pixel: Vec<(u32, u32)>,
+    rng: Vec<StdRng>,
+    queued: Vec<VecDeque<Ray>>,
+    remaining: Vec<u32>,
+    sum: Vec<Color>,
+    alive: Vec<bool>,
+    ray: Vec<Ray>,
+    depth: Vec<i32>,
+    wavelength: Vec<Option<Float>>,
+    hit: Vec<Option<HitRecord<'a>>>,
+    emitted: Vec<Color>,
+    direct: Vec<Color>,
+    scattered: Vec<Option<(Color, Ray)>>,
+    bounces: Vec<Vec<Bounce>>,
+}
+
+impl<'a> Paths<'a> {
+    fn new(settings: &RenderSettings, tiles: &[Tile]) -> Self {
+        let pixel: Vec<(u32, u32)> = tiles
+            .iter()
+            .flat_map(|tile| {
+                (tile.y..tile.y + tile.height)
+                    .flat_map(move |y| (tile.x..tile.x + tile.width).map(move |x| (x, y)))
+            })
+            .collect();
+        let len = pixel.len();
+        let rng = pixel
+            .iter()
+            .map(|&(x, y)| {
+                let seed = match settings.seed {
+                    Some(seed) => {
+                        pixel_seed(seed, y as u64 * settings.image_width as u64 + x as u64)
+                    }
+                    None => random(0..=u64::MAX),
+                };
+                StdRng::seed_from_u64(seed)
+            })
+            .collect();
+        Self {
+            pixel,
+            rng,
+            queued: (0..len).map(|_| VecDeque::new()).collect(),
+            remaining: vec![settings.samples_per_pixel; len],
+            sum: vec![vec3(0., 0., 0.); len],
+            alive: vec![false; len],
+            ray: vec![Ray::default(); len],
+            depth: vec![0; len],
+            wavelength: vec![None; len],
+            hit: (0..len).map(|_| None).collect(),
+            emitted: vec![vec3(0., 0., 0.); len],
+            direct: vec![vec3(0., 0., 0.); len],
+            scattered: (0..len).map(|_| None).collect(),
+            bounces: (0..len).map(|_| vec![]).collect(),
+        }
+    }
+
+    // draws from the generator of `slot` inside `f`
+    fn with_rng<T>(&mut self, slot: usize, f: impl FnOnce() -> T) -> T {
+        swap_random(&mut self.rng[slot]);
+        let result = f();
+        swap_random(&mut self.rng[slot]);
+        result
+    }
+
+    // Ends the path of `slot` with `tail` arriving along its last ray, folding the bounces
+    // back to front like the recursion unwinds
+    fn finish(&mut self, slot: usize, tail: Color, cam: &Camera) {
+        let mut color = tail;
+        for bounce in self.bounces[slot].drain(..).rev() {
+            color = match bounce.attenuation {
+                Some(attenuation) => bounce.radiance + attenuation.mul_element_wise(color),
+                None => bounce.radiance,
+            };
+        }
+        if let Some(wavelength) = self.wavelength[slot].take() {
+            color = spectral::spectrum_to_rgb(color.x, wavelength);
+        }
+        self.sum[slot] += color * cam.exposure();
+        self.remaining[slot] -= 1;
+        self.alive[slot] = false;
+    }
+}
+
+// `render_tiles` for a single tile
+pub fn render_tile(
+    world: &dyn Hittable,
+    lights: &[Light],
+    cam: &Camera,
+    settings: &RenderSettings,
+    tile: Tile,
+) -> Vec<Color> {
+    render_tiles(world, lights, cam, settings, &[tile])
+        .pop()
+        .unwrap()
+}
+
+// Renders tiles in stages over all of their paths at once: new camera rays for the pixels
+// whose path ended, closest hits, material sorting, shading, shadow rays, then moving every
+// path on to its scattered ray. Returns the pixels of each tile like
+// `render::render_tile`, and matches it for the same seed, with or without packets.
+pub fn render_tiles(
+    world: &dyn Hittable,
+    lights: &[Light],
+    cam: &Camera,
+    settings: &RenderSettings,
+    tiles: &[Tile],
+) -> Vec<Vec<Color>> {
+    let mut paths = Paths::new(settings, tiles);
+    let len = paths.ray.len();
+    // `sample_pixel` only uses packets for paths that go somewhere
+    let batch_camera_rays = settings.packets && settings.max_depth > 0;
+    let mut active = Vec::with_capacity(len);
+    let mut shadows: Vec<(usize, ShadowRay)> = vec![];
+
+    loop {
+        // generate
+        for slot in 0..len {
+            while !paths.alive[slot] && paths.remaining[slot] > 0 {
+                let (x, y) = paths.pixel[slot];
+                let j = settings.image_height - 1 - y;
+                if batch_camera_rays && paths.queued[slot].is_empty() {
+                    let n = paths.remaining[slot].min(LANES as u32);
+                    let rays: Vec<Option<Ray>> = paths.with_rng(slot, || {
+                        (0..n).map(|_| camera_ray(cam, settings, x, j)).collect()
+                    });
+                    for ray in rays {
+                        match ray {
+                            Some(ray) => paths.queued[slot].push_back(ray),
+                            None => paths.remaining[slot] -= 1,
+                        }
+                    }
+                    continue;
+                }
+                let ray = match paths.queued[slot].pop_front() {
+                    Some(ray) => Some(ray),
+                    None => paths.with_rng(slot, || camera_ray(cam, settings, x, j)),
+                };
+                let Some(ray) = ray else {
+                    paths.remaining[slot] -= 1;
+                    continue;
+                };
+                let (ray, wavelength) = if settings.spectral {
+                    let wavelength = paths.with_rng(slot, spectral::sample_wavelength);
+                    (ray.with_wavelength(wavelength), Some(wavelength))
+                } else {
+                    (ray, None)
+                };
+                paths.wavelength[slot] = wavelength;
+                if settings.max_depth <= 0 {
+                    paths.finish(slot, vec3(0., 0., 0.), cam);
+                    continue;
+                }
+                paths.ray[slot] = ray;
+                paths.depth[slot] = settings.max_depth;
+                paths.alive[slot] = true;
+            }
+        }
+        active.clear();
+        active.extend((0..len).filter(|&slot| paths.alive[slot]));
+        if active.is_empty() {
+            break;
+        }
+
+        // intersect
+        if settings.packets {
+            for chunk in active.chunks(LANES) {
+                let rays: Vec<Ray> = chunk.iter().map(|&slot| paths.ray[slot]).collect();
+                let packet = RayPacket::new(&rays);
+                let mut hits = PacketHits::new(&packet, Float::INFINITY);
+                world.hit_packet(&packet, 0.001, &mut hits);
+                for (&slot, hit) in chunk.iter().zip(hits.into_records(rays.len())) {
+                    paths.hit[slot] = hit;
+                }
+            }
+        } else {
+            for &slot in active.iter() {
+                paths.hit[slot] = world.hit(&paths.ray[slot], 0.001, Float::INFINITY);
+            }
+        }
+        // rays that left the scene see the sky
+        active.retain(|&slot| {
+            if paths.hit[slot].is_some() {
+                return true;
+            }
+            let ray = paths.ray[slot];
+            paths.finish(slot, ray.project(sky(ray.direction())), cam);
+            false
+        });
+
+        // sort, so the shading stage runs each material over all of its hits together
+        active.sort_by_key(|&slot| {
+            let rec = paths.hit[slot].as_ref().unwrap();
+            rec.mat_ptr as *const dyn Material as *const () as usize
+        });
+
+        // shade
+        shadows.clear();
+        for &slot in active.iter() {
+            let rec = paths.hit[slot].take().unwrap();
+            let ray = paths.ray[slot];
+            paths.emitted[slot] = ray.project(rec.mat_ptr.emitted(&rec));
+            paths.direct[slot] = vec3(0., 0., 0.);
+            shadows.extend(shadow_rays(lights, &ray, &rec).map(|shadow| (slot, shadow)));
+            let scattered = paths.with_rng(slot, || rec.mat_ptr.scatter(&ray, &rec));
+            if scattered.is_some() && paths.depth[slot] > 1 {
+                stats::count(Counter::BounceRays);
+            }
+            paths.scattered[slot] = scattered;
+        }
+
+        // shadow
+        for (slot, shadow) in shadows.iter() {
+            if world.hit(&shadow.ray, 0.001, shadow.t_max).is_none() {
+                paths.direct[*slot] += shadow.light;
+            }
+        }
+
+        // continue along the scattered rays
+        for &slot in active.iter() {
+            let ray = paths.ray[slot];
+            let mut radiance = paths.emitted[slot];
+            if !lights.is_empty() {
+                radiance += ray.project(paths.direct[slot]);
+            }
+            match paths.scattered[slot].take() {
+                Some((attenuation, scattered)) => {
+                    paths.bounces[slot].push(Bounce {
+                        radiance,
+                        attenuation: Some(ray.project(attenuation)),
+                    });
+                    paths.ray[slot] = scattered;
+                    paths.depth[slot] -= 1;
+                    if paths.depth[slot] <= 0 {
+                        paths.finish(slot, vec3(0., 0., 0.), cam);
+                    }
+                }
+                None => {
+                    paths.bounces[slot].push(Bounce {
+                        radiance,
+                        attenuation: None,
+                    });
+                    paths.finish(slot, vec3(0., 0., 0.), cam);
+                }
+            }
+        }
+    }
+
+    let mut pixels = paths
+        .sum
+        .into_iter()
+        .map(|sum| sum / settings.samples_per_pixel as Float);
+    tiles
+        .iter()
+        .map(|tile| {
+            pixels
+                .by_ref()
+                .take((tile.width * tile.height) as usize)
+                .collect()
+        })
+        .collect()
+}
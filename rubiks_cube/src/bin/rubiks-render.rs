@@ -0,0 +1,103 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use ray_tracer::{
+    image::{save_image, ImageFormat},
+    ray::bvh::Bvh,
+    render::{render, Integrator, RenderSettings},
+};
+use rubiks_cube::{
+    game_logic::RubiksCube,
+    ray_trace::{scene, Turn},
+};
+
+/// Path traces a saved Rubiks Cube.
+#[derive(Parser, Debug)]
+#[command(name = "rubiks-render")]
+struct Args {
+    /// Save file written by the game, relative to the working directory
+    save: String,
+
+    /// Output image path
+    #[arg(short, long, default_value = "rubiks_cube.png")]
+    output: PathBuf,
+
+    /// Output format (ppm, png or pfm), inferred from the output extension when omitted
+    #[arg(short, long)]
+    format: Option<ImageFormat>,
+
+    /// Image width in pixels
+    #[arg(short = 'W', long, default_value_t = 1600)]
+    width: u32,
+
+    /// Image height in pixels
+    #[arg(short = 'H', long, default_value_t = 1200)]
+    height: u32,
+
+    /// Samples per pixel
+    #[arg(long, default_value_t = 500)]
+    spp: u32,
+
+    /// Maximum number of bounces
+    #[arg(long, default_value_t = 50)]
+    max_depth: i32,
+
+    /// Show this layer mid turn, numbered like the game's controls (0-8)
+    #[arg(long)]
+    turn: Option<usize>,
+
+    /// How far through the turn the layer is, from 0 to 1
+    #[arg(long, default_value_t = 0.3, requires = "turn")]
+    progress: f64,
+
+    /// Turn the layer counterclockwise
+    #[arg(long, requires = "turn")]
+    counterclockwise: bool,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    if args.width < 2 || args.height < 2 || args.spp == 0 {
+        bail!("image must be at least 2x2 pixels with one or more samples per pixel");
+    }
+    let format = match args.format {
+        Some(format) => format,
+        None => ImageFormat::from_path(&args.output)?,
+    };
+
+    let cube = RubiksCube::new_from_save(&args.save)?;
+    let turn = args.turn.map(|face| Turn {
+        face,
+        progress: args.progress,
+        is_clockwise: !args.counterclockwise,
+    });
+    let scene = scene(&cube, turn)?;
+    let settings = RenderSettings {
+        image_width: args.width,
+        image_height: args.height,
+        samples_per_pixel: args.spp,
+        max_depth: args.max_depth,
+        seed: None,
+        spectral: false,
+        integrator: Integrator::Path,
+        packets: false,
+        wavefront: false,
+    };
+    let cam = scene.camera.build(settings.aspect_ratio())?;
+    let world = Bvh::from(scene.world);
+    let (pixels, stats) = render(&world, &scene.lights, &cam, &settings);
+    save_image(
+        &args.output,
+        format,
+        settings.image_width,
+        settings.image_height,
+        &pixels,
+    )?;
+    eprintln!(
+        "rendered {} in {:.2}s",
+        args.output.display(),
+        stats.seconds
+    );
+    Ok(())
+}
@@ -9,9 +9,10 @@ use learn_opengl::{
         buffers::{
             bindable::Bindable,
             texture::{Texture2D, Textures},
-            Attribute, VOs,
+            vertex::Vertex,
+            VOs,
         },
-        shader::{Shader, ShaderProgram},
+        shader::reload::ReloadingProgram,
     },
     window::Window,
 };
@@ -24,44 +25,41 @@ use rubiks_cube::{
 const SCR_WIDTH: u32 = 1600;
 const SCR_HEIGHT: u32 = 1200;
 
-const VERTEX_SHADER_SOURCE: &'static str = include_str!("../../shaders/vert.glsl");
-const FRAG_SHADER_SOURCE: &'static str = include_str!("../../shaders/frag.glsl");
+const VERTEX_SHADER_PATH: &'static str = "./rubiks_cube/shaders/vert.glsl";
+const FRAG_SHADER_PATH: &'static str = "./rubiks_cube/shaders/frag.glsl";
+
+// a corner of a sticker, read by vert.glsl
+#[derive(Clone, Copy, Vertex)]
+struct FaceVertex {
+    position: [f32; 3],
+    tex_coords: [f32; 2],
+}
+
+impl FaceVertex {
+    fn new(position: [f32; 3], tex_coords: [f32; 2]) -> Self {
+        Self {
+            position,
+            tex_coords,
+        }
+    }
+}
 
 fn main() {
     let mut window = Window::new(SCR_WIDTH, SCR_HEIGHT, "Rubiks Cube", true, false).unwrap();
-    let v_shader =
-        Shader::new(VERTEX_SHADER_SOURCE, gl::VERTEX_SHADER).expect("Failed to compile V Shader");
-    let f_shader =
-        Shader::new(FRAG_SHADER_SOURCE, gl::FRAGMENT_SHADER).expect("Failed to compile F Shader");
-    let shader = ShaderProgram::new([v_shader, f_shader]).expect("Failed to Create Shader Program");
-
-    #[rustfmt::skip]
-    let face_verts: [f32; 30] = [
-        -0.5, -0.5, 0.5, 0., 0.,
-         0.5, -0.5, 0.5, 1., 0.,
-         0.5,  0.5, 0.5, 1., 1.,
-         0.5,  0.5, 0.5, 1., 1.,
-        -0.5,  0.5, 0.5, 0., 1.,
-        -0.5, -0.5, 0.5, 0., 0.,
-    ];
+    // the shaders are read from disk and rebuilt whenever they are saved
+    let mut shader = ReloadingProgram::new(&[
+        (VERTEX_SHADER_PATH, gl::VERTEX_SHADER),
+        (FRAG_SHADER_PATH, gl::FRAGMENT_SHADER),
+    ])
+    .expect("Failed to Create Shader Program");
 
-    let attributes = [
-        Attribute {
-            // cords
-            location: 0,
-            size: 3,
-            normalized: false,
-            stride: 5,
-            offset: 0,
-        },
-        Attribute {
-            // texture
-            location: 1,
-            size: 2,
-            normalized: false,
-            stride: 5,
-            offset: 3,
-        },
+    let face_verts: [FaceVertex; 6] = [
+        FaceVertex::new([-0.5, -0.5, 0.5], [0., 0.]),
+        FaceVertex::new([0.5, -0.5, 0.5], [1., 0.]),
+        FaceVertex::new([0.5, 0.5, 0.5], [1., 1.]),
+        FaceVertex::new([0.5, 0.5, 0.5], [1., 1.]),
+        FaceVertex::new([-0.5, 0.5, 0.5], [0., 1.]),
+        FaceVertex::new([-0.5, -0.5, 0.5], [0., 0.]),
     ];
 
     let imgs: [Texture2D; 12] = core::array::from_fn(|n| {
@@ -99,8 +97,7 @@ fn main() {
 
     shader.set_uniform("has_texture", false).unwrap();
 
-    let face_obj =
-        VOs::new(&face_verts, &attributes, gl::TRIANGLES).expect("vbo or vba failed to bind");
+    let face_obj = VOs::new(&face_verts, gl::TRIANGLES).expect("vbo or vba failed to bind");
 
     let mut projection: Matrix4<f32> =
         perspective(Deg(45.0), SCR_WIDTH as f32 / SCR_HEIGHT as f32, 0.1, 100.0);
@@ -129,6 +126,7 @@ fn main() {
     let mut is_clockwise: bool = true;
     let mut won: bool = false;
     window.app_loop(|mut w| {
+        shader.update();
         let (rotate_clicked, is_left_click, show_shadow_face, is_shift) =
             process_input(&w.window, &cube_state);
         process_events(&mut w, &mut projection, &mut cam, is_left_click, last_left);
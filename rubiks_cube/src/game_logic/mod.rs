@@ -148,11 +148,7 @@ impl RubiksCube {
         if rotating_face >= 9 || face >= 6 {
             bail!("Unsupported face")
         }
-        let center = if rotating_face >= 6 {
-            vec3(1., 1., 1.)
-        } else {
-            (self.blocks[rotating_face].convert_cord)(1., 1.)
-        };
+        let center = self.layer_center(rotating_face);
 
         let v = (self.blocks[face].convert_cord)(x, y) - center;
         return Ok(Matrix4::from_translation(-v)
@@ -161,6 +157,43 @@ impl RubiksCube {
                 .unwrap()
             * Matrix4::from_translation(v));
     }
+    // Rotation of the whole layer `rotating_face` turns, for drawing the cubies mid turn
+    pub fn get_layer_rotate_matrix(
+        &self,
+        rotating_face: usize,
+        p: f64,
+        is_clockwise: bool,
+    ) -> Result<Matrix4<f32>> {
+        if rotating_face >= 9 {
+            bail!("Unsupported face")
+        }
+        let center = self.layer_center(rotating_face);
+        return Ok(Matrix4::from_translation(center)
+            * self.get_face_rotate(rotating_face, p, is_clockwise)?
+            * Matrix4::from_translation(-center));
+    }
+
+    // Whether the cubie centered at `position` turns with `rotating_face`
+    pub fn layer_contains(&self, rotating_face: usize, position: Vector3<f32>) -> Result<bool> {
+        let axis = match rotating_face {
+            3 | 1 | 6 => 2,
+            0 | 5 | 7 => 1,
+            2 | 4 | 8 => 0,
+            _ => bail!("Unsupported face"),
+        };
+        let layer = self.layer_center(rotating_face)[axis].clamp(0., 2.);
+        return Ok((position[axis] - layer).abs() < 0.5);
+    }
+
+    // a point on the axis the layer turns around
+    fn layer_center(&self, rotating_face: usize) -> Vector3<f32> {
+        if rotating_face >= 6 {
+            vec3(1., 1., 1.)
+        } else {
+            (self.blocks[rotating_face].convert_cord)(1., 1.)
+        }
+    }
+
     fn get_face_rotate(&self, face: usize, mut p: f64, is_clockwise: bool) -> Result<Matrix4<f32>> {
         p = match face {
             5 | 7 | 4 | 3 => -p,
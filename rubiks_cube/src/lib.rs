@@ -1,2 +1,3 @@
 pub mod camera;
 pub mod game_logic;
+pub mod ray_trace;
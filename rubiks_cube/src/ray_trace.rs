@@ -0,0 +1,307 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use cgmath::{vec3, Deg, EuclideanSpace, InnerSpace, Matrix4, Point3, Transform, Vector3, Vector4};
+use ray_tracer::{
+    animation::CameraAnimation,
+    camera::CameraSettings,
+    light::Light,
+    material::{Lambertian, Material},
+    ray::{hittable::HittableList, mesh::Mesh},
+    scene::Scene,
+    Color, Float,
+};
+
+use crate::game_logic::{RubiksCube, ShadowPlane};
+
+// cubies are unit cubes centered on the integer points of [0, 2]³, stickers sit on
+// their faces like in the game
+const CUBIE_RADIUS: f32 = 0.08;
+// vertices across each rounded edge
+const BEVEL_STEPS: usize = 4;
+const STICKER_HALF_SIZE: f32 = 0.4;
+// lifts the stickers off the cubies so they do not z-fight with them
+const STICKER_OFFSET: f32 = 0.002;
+const CUBE_CENTER: Vector3<f32> = vec3(1., 1., 1.);
+
+// A layer caught part of the way through a turn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Turn {
+    // the layer, numbered like the game's controls (0-5 faces, 6-8 inner slices)
+    pub face: usize,
+    // 0 to 1
+    pub progress: f64,
+    pub is_clockwise: bool,
+}
+
+fn to_point(p: Vector3<f32>) -> Point3<Float> {
+    Point3::new(p.x as Float, p.y as Float, p.z as Float)
+}
+
+fn to_vector(v: Vector3<f32>) -> Vector3<Float> {
+    vec3(v.x as Float, v.y as Float, v.z as Float)
+}
+
+// Sticker colors are meant for the screen, so they are gamma decoded into albedos like
+// image textures are
+fn albedo(color: Vector4<f32>) -> Color {
+    vec3(color.x, color.y, color.z).map(|c| 0.85 * (c as Float).powi(2))
+}
+
+// A cubie centered on the origin, copied into place for every cubie
+struct Cubie {
+    positions: Vec<Vector3<f32>>,
+    normals: Vec<Vector3<f32>>,
+    indices: Vec<[u32; 3]>,
+}
+
+// A unit cube with rounded edges. Each face is a grid that is dense along the edges,
+// pushing the grid points near the edges onto a sphere of `CUBIE_RADIUS` around the
+// inner box rounds them off.
+fn rounded_cube() -> Cubie {
+    let flat = 0.5 - CUBIE_RADIUS;
+    let steps: Vec<f32> = (0..=BEVEL_STEPS)
+        .map(|i| -0.5 + CUBIE_RADIUS * i as f32 / BEVEL_STEPS as f32)
+        .collect();
+    let coords: Vec<f32> = steps
+        .iter()
+        .cloned()
+        .chain(steps.iter().rev().map(|s| -s))
+        .collect();
+    let n = coords.len();
+
+    let (mut positions, mut normals, mut indices) = (vec![], vec![], vec![]);
+    for axis in 0..3 {
+        for side in [-0.5, 0.5] {
+            let start = positions.len() as u32;
+            for &a in coords.iter() {
+                for &b in coords.iter() {
+                    let mut p = Vector3::new(0., 0., 0.);
+                    p[axis] = side;
+                    p[(axis + 1) % 3] = a;
+                    p[(axis + 2) % 3] = b;
+                    let inner = p.map(|c| c.clamp(-flat, flat));
+                    let normal = (p - inner).normalize();
+                    positions.push(inner + normal * CUBIE_RADIUS);
+                    normals.push(normal);
+                }
+            }
+            for i in 0..n as u32 - 1 {
+                for j in 0..n as u32 - 1 {
+                    let v = start + i * n as u32 + j;
+                    let next = v + n as u32;
+                    indices.extend([[v, next, next + 1], [v, next + 1, v + 1]]);
+                }
+            }
+        }
+    }
+    Cubie {
+        positions,
+        normals,
+        indices,
+    }
+}
+
+// Adds the cubies and stickers of `cube`, with the layer of `turn` rotated part of the way
+pub fn add_cube(world: &mut HittableList, cube: &RubiksCube, turn: Option<Turn>) -> Result<()> {
+    let layer = match turn {
+        Some(turn) => Some((
+            turn.face,
+            cube.get_layer_rotate_matrix(turn.face, turn.progress, turn.is_clockwise)?,
+        )),
+        None => None,
+    };
+
+    let plastic: Arc<dyn Material> = Lambertian::new(vec3(0.02, 0.02, 0.02));
+    let cubie = rounded_cube();
+    for x in 0..3 {
+        for y in 0..3 {
+            for z in 0..3 {
+                let center = vec3(x as f32, y as f32, z as f32);
+                // the middle cubie can never be seen
+                if center == CUBE_CENTER {
+                    continue;
+                }
+                let mut model = Matrix4::from_translation(center);
+                if let Some((face, rotate)) = layer {
+                    if cube.layer_contains(face, center)? {
+                        model = rotate * model;
+                    }
+                }
+                let positions = cubie
+                    .positions
+                    .iter()
+                    .map(|&p| to_point(model.transform_point(Point3::from_vec(p)).to_vec()))
+                    .collect();
+                let normals = cubie
+                    .normals
+                    .iter()
+                    .map(|&n| to_vector(model.transform_vector(n)))
+                    .collect();
+                let mesh = Mesh::new(positions, cubie.indices.clone(), plastic.clone())?
+                    .with_normals(normals)?;
+                for triangle in Arc::new(mesh).triangles() {
+                    world.add(triangle);
+                }
+            }
+        }
+    }
+
+    // the stickers of the turning face and the ring around it turn, like in the game
+    let turning: Vec<(usize, usize, usize)> = match turn {
+        Some(turn) => {
+            let shadow_plane: ShadowPlane = turn.face.try_into()?;
+            shadow_plane
+                .plane
+                .iter()
+                .flat_map(|&(face, cords, _)| cords.map(|(y, x)| (face, y, x)))
+                .collect()
+        }
+        None => vec![],
+    };
+    let corners = [
+        vec3(-STICKER_HALF_SIZE, -STICKER_HALF_SIZE, 0.5),
+        vec3(STICKER_HALF_SIZE, -STICKER_HALF_SIZE, 0.5),
+        vec3(STICKER_HALF_SIZE, STICKER_HALF_SIZE, 0.5),
+        vec3(-STICKER_HALF_SIZE, STICKER_HALF_SIZE, 0.5),
+    ];
+    for (face, block) in cube.iter().enumerate() {
+        for (y, row) in block.iter().enumerate() {
+            for (x, color) in row.iter().enumerate() {
+                let mut model = block.convert_cords(x as f32, y as f32) * block.get_rotation();
+                let on_turning_face = turn.is_some_and(|turn| turn.face == face);
+                if let Some((_, rotate)) = layer {
+                    if on_turning_face || turning.contains(&(face, y, x)) {
+                        model = rotate * model;
+                    }
+                }
+                let mut quad: Vec<Vector3<f32>> = corners
+                    .iter()
+                    .map(|&c| model.transform_point(Point3::from_vec(c)).to_vec())
+                    .collect();
+                // push the sticker outwards, away from the middle of the cube
+                let mid = quad.iter().sum::<Vector3<f32>>() / 4.;
+                let mut normal = (quad[1] - quad[0]).cross(quad[2] - quad[0]).normalize();
+                if normal.dot(mid - CUBE_CENTER) < 0. {
+                    normal = -normal;
+                }
+                for corner in quad.iter_mut() {
+                    *corner += normal * STICKER_OFFSET;
+                }
+                let sticker = Mesh::new(
+                    quad.into_iter().map(to_point).collect(),
+                    vec![[0, 1, 2], [0, 2, 3]],
+                    Lambertian::new(albedo(color.into())),
+                )?;
+                for triangle in Arc::new(sticker).triangles() {
+                    world.add(triangle);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// A product shot of `cube` on a grey floor, seen from above its front right corner
+pub fn scene(cube: &RubiksCube, turn: Option<Turn>) -> Result<Scene> {
+    let mut world = HittableList::default();
+    add_cube(&mut world, cube, turn)?;
+
+    let floor_y = -0.5 - 1.5 * CUBIE_RADIUS as Float;
+    let floor = Mesh::new(
+        vec![
+            Point3::new(-50., floor_y, -50.),
+            Point3::new(-50., floor_y, 50.),
+            Point3::new(50., floor_y, 50.),
+            Point3::new(50., floor_y, -50.),
+        ],
+        vec![[0, 1, 2], [0, 2, 3]],
+        Lambertian::new(vec3(0.5, 0.5, 0.5)),
+    )?;
+    for triangle in Arc::new(floor).triangles() {
+        world.add(triangle);
+    }
+
+    let lookat = to_point(CUBE_CENTER);
+    let lookfrom = lookat + vec3(6., 5., 8.);
+    let camera = CameraSettings {
+        lookfrom,
+        lookat,
+        vup: vec3(0., 1., 0.),
+        vfov: Deg(30.),
+        aperture: 0.,
+        focus_dist: (lookfrom - lookat).magnitude(),
+        ..CameraSettings::default()
+    };
+
+    Ok(Scene {
+        world,
+        camera,
+        camera_animation: CameraAnimation::default(),
+        animated: vec![],
+        lights: vec![Light::Point {
+            position: lookat + vec3(-4., 10., 6.),
+            intensity: vec3(60., 60., 60.),
+        }],
+        warnings: vec![],
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use ray_tracer::ray::{aabb::Aabb, hittable::Hittable};
+
+    use super::*;
+
+    fn bounds(cube: &RubiksCube, turn: Option<Turn>) -> Vec<Aabb> {
+        let mut world = HittableList::default();
+        add_cube(&mut world, cube, turn).unwrap();
+        world
+            .into_objects()
+            .iter()
+            .map(|object| object.bounding_box().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_stickers() {
+        let cube = RubiksCube::new();
+        let cubie = rounded_cube().indices.len();
+        // 26 cubies, 54 stickers of two triangles each and the floor
+        let scene = scene(&cube, None).unwrap();
+        assert_eq!(scene.world.into_objects().len(), 26 * cubie + 54 * 2 + 2);
+    }
+
+    #[test]
+    fn test_turn() {
+        let cube = RubiksCube::new();
+        let cubie = rounded_cube().indices.len();
+        let still = bounds(&cube, None);
+        for face in 0..9 {
+            let turn = |progress| Turn {
+                face,
+                progress,
+                is_clockwise: true,
+            };
+            assert_eq!(bounds(&cube, Some(turn(0.))), still);
+
+            let turned = bounds(&cube, Some(turn(0.5)));
+            assert_eq!(turned.len(), still.len());
+            let moved = turned
+                .iter()
+                .zip(&still)
+                .filter(|(a, b)| {
+                    (a.min - b.min).magnitude() > 1e-4 || (a.max - b.max).magnitude() > 1e-4
+                })
+                .count();
+            // a face turns 9 cubies, its own stickers and the ring of 12 around it, an
+            // inner slice 8 cubies around the hidden middle one and its ring
+            let expected = if face < 6 {
+                9 * cubie + (9 + 12) * 2
+            } else {
+                8 * cubie + 12 * 2
+            };
+            assert_eq!(moved, expected, "face {face}");
+        }
+    }
+}